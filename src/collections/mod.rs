@@ -7,6 +7,7 @@ extern crate alloc;
 
 use std::ptr::{self, Unique};
 use std::{mem, slice};
+use std::hash::{Hash, Hasher};
 use self::alloc::heap;
 use std::process::exit;
 use std::ops::{Deref, DerefMut};
@@ -30,12 +31,19 @@ pub struct Vector<T> {
 }
 
 /// A two-dimensional fixed-size array
+#[derive(PartialEq)]
 pub struct Matrix<T> {
   mem: Vector<Option<T>>,
   pub w: usize,
   pub h: usize
 }
 
+/// A binary min-heap built on top of Vector; pop always returns the smallest
+/// item (as determined by PartialOrd)
+pub struct Heap<T: PartialOrd> {
+    items: Vector<T>,
+}
+
 impl<T> ResizableMemory<T> {
     /// Creates a new resizable memory
     fn new() -> Self {
@@ -268,6 +276,13 @@ impl<T: PartialEq> PartialEq for Vector<T> {
 
 impl<T: Eq> Eq for Vector<T> {}
 
+impl<T: Hash> Hash for Vector<T> {
+	/// Hashes the vector's contents, so equal vectors hash the same
+	fn hash<H: Hasher>(&self, state: &mut H) {
+		(**self).hash(state);
+	}
+}
+
 impl<T> Matrix<T> {
 	/// Creates a new matrix with given dimensions and fills it with None
 	pub fn new(width: usize, height: usize) -> Self{
@@ -291,6 +306,70 @@ impl<T> Matrix<T> {
 	}
 }
 
+impl<T: PartialOrd> Heap<T> {
+    /// Creates a new empty heap
+    pub fn new() -> Self {
+        Heap { items: Vector::new() }
+    }
+    /// Pushes an item to the heap and moves it up to its correct place
+    pub fn push(&mut self, item: T) {
+        self.items.push(item);
+        let mut i = self.items.len() - 1;
+        while i > 0 {
+            let parent = (i - 1) / 2;
+            if self.items[i] < self.items[parent] {
+                self.items.swap(i, parent);
+                i = parent;
+            } else {
+                break;
+            }
+        }
+    }
+    /// Removes the smallest item from the heap and returns it wrapped in an
+    /// Option. Returns None if the heap is empty.
+    pub fn pop(&mut self) -> Option<T> {
+        let len = self.items.len();
+        if len == 0 {
+            return None;
+        }
+        self.items.swap(0, len - 1);
+        let result = self.items.pop();
+        let len = self.items.len();
+        let mut i = 0;
+        loop {
+            let left = 2 * i + 1;
+            let right = left + 1;
+            let mut smallest = i;
+            if left < len && self.items[left] < self.items[smallest] {
+                smallest = left;
+            }
+            if right < len && self.items[right] < self.items[smallest] {
+                smallest = right;
+            }
+            if smallest == i {
+                break;
+            }
+            self.items.swap(i, smallest);
+            i = smallest;
+        }
+        result
+    }
+    /// Returns a reference to the smallest item without removing it
+    pub fn peek(&self) -> Option<&T> {
+        if self.items.len() == 0 {
+            None
+        } else {
+            Some(&self.items[0])
+        }
+    }
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.items.len() == 0
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
@@ -421,4 +500,18 @@ mod tests {
     		}
     	}
     }
-}
+    
+    #[test]
+    fn heap_pops_in_ascending_order() {
+        let mut heap = Heap::new();
+        for i in 0..20 {
+            heap.push((i * 7) % 20);
+        }
+        assert_eq!(Some(&0), heap.peek());
+        for i in 0..20 {
+            assert_eq!(Some(i), heap.pop());
+        }
+        assert_eq!(None, heap.pop());
+        assert!(heap.is_empty());
+    }
+}
\ No newline at end of file
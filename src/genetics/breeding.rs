@@ -1,39 +1,45 @@
 //! This module contains functions that manipulate populations (vectors of
 //! chromosomes). Functionality such as control of who mates who is found here.
 
-use rand::Rng;
+use rand::{self, Rng, SeedableRng, StdRng};
 use std::cmp::Ordering;
 use std::cmp::Ordering::Equal;
 use std::fmt::{Debug, Formatter, Result};
+use std::sync::Arc;
+use std::{mem, thread};
 use super::genes::{Gene, Target, Chromosome};
+use super::fitness::FitnessFunction;
+use mapping::site::Site;
+use super::config::GaConfig;
+use super::pool::BreedingPool;
 use collections::Vector;
-
-/// Percentage of population that should be kept alive for the next round of
-/// breeding
-pub const KEEP_ALIVE_PERCENTAGE: f32 = 0.1;
-/// Percentage of population to kill when purging a population
-pub const PURGE_PERCENTAGE: f32 = 0.7;
-/// How many generations of stagnation before purge
-pub const PURGE_INTERVAL: usize = 100;
+use io::ui::Verbosity;
 
 /// Candidate is a container for a chromosome with a determined probability
 /// of selection for breeding
-#[derive(PartialEq)]
-pub struct Candidate<'a> {
+pub struct Candidate<'a, F: 'a> {
     prob_range_end: f32,
-    pub chromosome: &'a Chromosome,
+    pub chromosome: &'a Chromosome<F>,
+}
+
+impl<'a, F> PartialEq for Candidate<'a, F> {
+    fn eq(&self, other: &Candidate<'a, F>) -> bool {
+        self.prob_range_end == other.prob_range_end &&
+        self.chromosome == other.chromosome
+    }
 }
 
-impl<'a> Debug for Candidate<'a> {
+impl<'a, F> Debug for Candidate<'a, F> {
   /// Debug output formatting for candidate
   fn fmt(&self, f: &mut Formatter) -> Result {
     write!(f, "Candidate with prob range end {}", self.prob_range_end)
   }
 }
 
-impl<'a> Candidate<'a> {
+impl<'a, F> Candidate<'a, F> {
     /// Constructor for candidate
-    fn new(prob_range_end: f32, chromosome: &'a Chromosome) -> Candidate {
+    fn new(prob_range_end: f32, chromosome: &'a Chromosome<F>)
+        -> Candidate<'a, F> {
         Candidate {
             prob_range_end: prob_range_end,
             chromosome: chromosome,
@@ -41,18 +47,18 @@ impl<'a> Candidate<'a> {
     }
 }
 
-impl<'a> PartialOrd for Candidate<'a> {
+impl<'a, F> PartialOrd for Candidate<'a, F> {
     /// Ordering for candidate, based on it's probability range end
-    fn partial_cmp(&self, other: &Candidate) -> Option<Ordering> {
+    fn partial_cmp(&self, other: &Candidate<'a, F>) -> Option<Ordering> {
         self.prob_range_end.partial_cmp(&other.prob_range_end)
     }
 }
 
 /// Binary searches the candidate with the smallest probability range end that's
 /// larger than random_value
-pub fn search_candidate<'a>(candidates: &'a Vector<Candidate>, random_value: f32)
--> Option<&'a Candidate<'a>> {
-    let mut smallest_match: Option<&Candidate> = None;
+pub fn search_candidate<'a, 'b, F>(candidates: &'a Vector<Candidate<'b, F>>,
+    random_value: f32) -> Option<&'a Candidate<'b, F>> {
+    let mut smallest_match: Option<&Candidate<F>> = None;
     let mut min = 0;
     let mut max = candidates.len() - 1;
     while min <= max {
@@ -76,19 +82,35 @@ pub fn search_candidate<'a>(candidates: &'a Vector<Candidate>, random_value: f32
     smallest_match
 }
 
-/// Generates an initial population with determined size
-pub fn generate_initial_population<R: Rng>(genes: Vector<Gene>, 
-	targets: Vector<Target>, size: usize, rng: &mut R) -> Vector<Chromosome> {
-    let mut population: Vector<Chromosome> = Vector::new();
+/// Creates a random number generator from a seed. Runs with the same seed
+/// (and the same blueprint and parameters) give the same results.
+pub fn seeded_rng(seed: usize) -> StdRng {
+    let seed_slice: &[usize] = &[seed];
+    SeedableRng::from_seed(seed_slice)
+}
+
+/// Picks a random seed for when the user doesn't give one
+pub fn random_seed() -> usize {
+    rand::thread_rng().gen()
+}
+
+/// Generates an initial population with determined size on given site, scored
+/// by given fitness function
+pub fn generate_initial_population<F: FitnessFunction, R: Rng>(
+	genes: Vector<Gene>, targets: Vector<Target>, site: Arc<Site>,
+	fitness_function: Arc<F>, size: usize, rng: &mut R)
+	-> Vector<Chromosome<F>> {
+    let mut population: Vector<Chromosome<F>> = Vector::new();
     for _ in 0..size {
         population.push(Chromosome::generate_initial(genes.clone(), 
-        		targets.clone(), rng));
+        		targets.clone(), site.clone(), fitness_function.clone(), rng));
     }
     population
 }
 	
 /// Returns the most fit chromosome in a population
-pub fn most_fit(population: &Vector<Chromosome>) -> Option<&Chromosome> {
+pub fn most_fit<F>(population: &Vector<Chromosome<F>>)
+	-> Option<&Chromosome<F>> {
 	let mut most_fit = None;
 	let mut largest_fitness = 0.0;
 	for i in 0..population.len() {
@@ -100,122 +122,350 @@ pub fn most_fit(population: &Vector<Chromosome>) -> Option<&Chromosome> {
 	most_fit
 }
 
-/// Breeds a population by 1 step; generates and mutates children and returns
-/// the next population.
-pub fn breed<R: Rng>(population: Vector<Chromosome>, rng: &mut R)
--> Vector<Chromosome> {
+/// Builds the candidates for selecting parents from a population that has been
+/// sorted from most fit to least fit
+pub fn candidates<F>(population: &Vector<Chromosome<F>>)
+    -> Vector<Candidate<F>> {
     let mut total_fitness = 0.0;
-    let mut work_population = population.to_vec();
-    work_population.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Equal));
-    work_population.reverse();
-    for i in 0..work_population.len() {
-        total_fitness += work_population[i].fitness;
+    for i in 0..population.len() {
+        total_fitness += population[i].fitness;
     }
-    let mut candidates: Vector<Candidate> = Vector::new();
+    let mut candidates: Vector<Candidate<F>> = Vector::new();
     let mut current_prob_range_end = 0.0;
-    for i in 0..work_population.len() {
-        if i == work_population.len() - 1 { // Make sure float inaccuracy doesn't destroy things
+    for i in 0..population.len() {
+        if i == population.len() - 1 { // Make sure float inaccuracy doesn't destroy things
           current_prob_range_end = 1.0;
         } else {
-          current_prob_range_end += work_population[i].fitness / total_fitness;
+          current_prob_range_end += population[i].fitness / total_fitness;
         }
         candidates
-        .push(Candidate::new(current_prob_range_end, &work_population[i]));
-    }
-    let mut next_population: Vector<Chromosome> = Vector::new(); //TODO: Keeping the best of previous population
-    let keep_alive = work_population.len() as f32 * KEEP_ALIVE_PERCENTAGE;
-    let keep_alive_usize = keep_alive.round() as usize;
-    for i in 0..keep_alive_usize {
-      next_population.push(work_population[i].clone());
+        .push(Candidate::new(current_prob_range_end, &population[i]));
     }
-    while next_population.len() < population.len() {
-        let candidate1 = search_candidate(&candidates, rng.next_f32());
+    candidates
+}
+
+/// Generates given amount of mated and mutated children from candidates
+pub fn breed_children<F: FitnessFunction, R: Rng>(
+	candidates: &Vector<Candidate<F>>, amount: usize, config: &GaConfig,
+	rng: &mut R) -> Vector<Chromosome<F>> {
+    let mut children: Vector<Chromosome<F>> = Vector::new();
+    while children.len() < amount {
+        let candidate1 = search_candidate(candidates, rng.next_f32());
         // TODO: Handling None
-        let candidate2 = search_candidate(&candidates, rng.next_f32());
+        let candidate2 = search_candidate(candidates, rng.next_f32());
         let actual_candidate1 = candidate1.unwrap();
         let actual_candidate2 = candidate2.unwrap();
         let chromosome1 = actual_candidate1.chromosome;
         let chromosome2 = actual_candidate2.chromosome;
         // TODO: Avoiding duplicates
-        let (mut child1, mut child2) = chromosome1.mate(chromosome2, rng);
-    child1.mutate(rng);
-    child2.mutate(rng);
-        next_population.push(child1);
-        if next_population.len() < population.len() {
-          next_population.push(child2);
+        let (mut child1, mut child2) = chromosome1.mate(chromosome2, config,
+        	rng);
+    child1.mutate(config, rng);
+    child2.mutate(config, rng);
+        children.push(child1);
+        if children.len() < amount {
+          children.push(child2);
         }
     }
+    children
+}
+
+/// Sorts a population from most fit to least fit and returns it along with
+/// the chromosomes that should be kept alive for the next generation
+fn survivors<F>(population: Vector<Chromosome<F>>, config: &GaConfig)
+	-> (Vector<Chromosome<F>>, Vector<Chromosome<F>>) {
+    let mut work_population = population;
+    work_population.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Equal));
+    work_population.reverse();
+    let mut next_population: Vector<Chromosome<F>> = Vector::new();
+    let keep_alive = work_population.len() as f32 * config.keep_alive_percentage;
+    let keep_alive_usize = keep_alive.round() as usize;
+    for i in 0..keep_alive_usize {
+      next_population.push(work_population[i].clone());
+    }
+    (work_population, next_population)
+}
+
+/// Breeds a population by 1 step; generates and mutates children and returns
+/// the next population. The children are bred on the calling thread whatever
+/// config.threads is; use breed_in_pool to breed them on the threads of a
+/// pool that is kept for the whole run, like breed_for does.
+pub fn breed<F: FitnessFunction, R: Rng>(population: Vector<Chromosome<F>>,
+	config: &GaConfig, rng: &mut R) -> Vector<Chromosome<F>> {
+    let size = population.len();
+    let (work_population, mut next_population) = survivors(population, config);
+    let amount = size - next_population.len();
+    let children = breed_children(&candidates(&work_population), amount,
+    	config, rng);
+    for i in 0..children.len() {
+        next_population.push(children[i].clone());
+    }
+    next_population
+}
+
+/// Breeds a population by 1 step like breed, but generates the children on the
+/// threads of a pool. The result is the same for the same rng state and pool
+/// size.
+pub fn breed_in_pool<F: FitnessFunction + 'static, R: Rng>(
+	population: Vector<Chromosome<F>>, pool: &BreedingPool<F>,
+	config: &GaConfig, rng: &mut R) -> Vector<Chromosome<F>> {
+    let size = population.len();
+    let (work_population, mut next_population) = survivors(population, config);
+    let amount = size - next_population.len();
+    let children = pool.breed_children(Arc::new(work_population), amount,
+    	config, rng);
+    for i in 0..children.len() {
+        next_population.push(children[i].clone());
+    }
     next_population
 }
 
-/// Replaces number of worst chromosomes equal to PURGE_PERCENTAGE with initial
-/// chromosomes.
-pub fn purge<R: Rng>(population: &mut Vector<Chromosome>, rng: &mut R) {
-  population.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Equal));
+/// Replaces number of worst chromosomes equal to the config's purge_percentage
+/// with initial chromosomes.
+pub fn purge<F: FitnessFunction, R: Rng>(population: &mut Vector<Chromosome<F>>,
+	config: &GaConfig, rng: &mut R) {
+    population.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Equal));
     population.reverse(); //TODO: Get rid of excess sorts
-  let kill = population.len() as f32 * PURGE_PERCENTAGE;
+    let kill = population.len() as f32 * config.purge_percentage;
     let kill_usize = kill.round() as usize;
     let genes = population[0].genes.clone();
     let targets = population[0].targets.clone();
-    for i in kill_usize..population.len() {
-      population[i] = Chromosome::generate_initial(genes.clone(), 
-      	targets.clone(), rng);
+    let site = population[0].get_site();
+    let fitness_function = population[0].get_fitness_function();
+    for i in population.len() - kill_usize..population.len() {
+        population[i] = Chromosome::generate_initial(genes.clone(),
+        	targets.clone(), site.clone(), fitness_function.clone(), rng);
     }
 }
 
-/// Breeds population for given number of generations
-pub fn breed_for<R: Rng>(population: Vector<Chromosome>, generations: usize, 
-	rng: &mut R) -> Vector<Chromosome> {
+/// Breeds population for given number of generations. Prints progress every
+/// purge_interval generations, or every generation if verbose. Children are
+/// bred on config.threads threads.
+pub fn breed_for<F: FitnessFunction + 'static, R: Rng>(
+	population: Vector<Chromosome<F>>, generations: usize, config: &GaConfig,
+	verbosity: Verbosity, rng: &mut R) -> Vector<Chromosome<F>> {
 	let mut last_fitness = 0.0;
 	let mut work_population = population.clone();
 	let mut purge_imminent = false;
+	let pool = if config.threads > 1 {
+		Some(BreedingPool::new(config.threads))
+	} else {
+		None
+	};
 	for i in 0..generations {
-		work_population = breed(work_population, rng);
-		if i % PURGE_INTERVAL == 0 {
+		work_population = match pool {
+			Some(ref pool) => breed_in_pool(work_population, pool, config, rng),
+			None => breed(work_population, config, rng),
+		};
+		let check = i % config.purge_interval == 0;
+		if check || verbosity == Verbosity::Verbose {
 			let most_fit = most_fit(&work_population).unwrap();
-			print!("Generation {}/{}, largest fitness: {}", i, generations,
-			most_fit.fitness);
-			if most_fit.fitness > last_fitness {
-				last_fitness = most_fit.fitness;
-			} else {
-				purge_imminent = true;
-			} 
-			if !purge_imminent {
+			if verbosity != Verbosity::Quiet {
+				print!("Generation {}/{}, largest fitness: {}", i, generations,
+				most_fit.fitness);
+			}
+			if check {
+				if most_fit.fitness > last_fitness {
+					last_fitness = most_fit.fitness;
+				} else {
+					purge_imminent = true;
+				}
+			}
+			if !purge_imminent && verbosity != Verbosity::Quiet {
 				println!("");
 			}
 		}
 		if purge_imminent {
-			println!(" -> Purging stale population");
-			purge(&mut work_population, rng);
+			if verbosity != Verbosity::Quiet {
+				println!(" -> Purging stale population");
+			}
+			purge(&mut work_population, config, rng);
 			purge_imminent = false;
 		}
 	}
 	work_population
 }
 	
-/// Creates a population of given size from genes and targets and breeds it for
-/// given amount of generations.
+/// Creates a population of given size from genes and targets on given site,
+/// scored by given fitness function, and breeds it for given amount of
+/// generations.
 /// # Panics
 /// Panics if can't for some reason find a most fit chromosomes.
-pub fn breeder<R: Rng>(genes: Vector<Gene>, targets: Vector<Target>, 
-	pop_size: usize, generations: usize, rng: &mut R) -> Chromosome {
+pub fn breeder<F: FitnessFunction + 'static, R: Rng>(genes: Vector<Gene>,
+	targets: Vector<Target>, site: Arc<Site>, fitness_function: Arc<F>,
+	pop_size: usize, generations: usize, config: &GaConfig,
+	verbosity: Verbosity, rng: &mut R) -> Chromosome<F> {
 	let mut population = generate_initial_population(
-		genes, targets, pop_size, rng
+		genes, targets, site, fitness_function, pop_size, rng
 	);
-	population = breed_for(population, generations, rng);
+	population = breed_for(population, generations, config, verbosity, rng);
 	match most_fit(&population) {
 		None => panic!("Couldn't find most fit chromosome!"),
 		Some(chromosome) => chromosome.clone()
 	}
 }
 
+/// Island is one of the independent populations of the island model, along
+/// with its own random number generator
+struct Island<F> {
+	population: Vector<Chromosome<F>>,
+	rng: StdRng,
+}
+
+impl<F: FitnessFunction + 'static> Island<F> {
+	/// Creates an island with an initial population of given size
+	fn new(genes: Vector<Gene>, targets: Vector<Target>, site: Arc<Site>,
+		fitness_function: Arc<F>, size: usize, seed: usize) -> Self {
+		let mut rng = seeded_rng(seed);
+		let population = generate_initial_population(genes, targets, site,
+			fitness_function, size, &mut rng);
+		Island {
+			population: population,
+			rng: rng,
+		}
+	}
+	/// Breeds the population of this island for given number of generations
+	fn evolve(mut self, generations: usize, config: &GaConfig) -> Self {
+		let population = mem::replace(&mut self.population, Vector::new());
+		self.population = breed_for(population, generations, config,
+			Verbosity::Quiet, &mut self.rng);
+		self
+	}
+	/// Returns copies of given amount of the most fit chromosomes
+	fn emigrants(&mut self, amount: usize) -> Vector<Chromosome<F>> {
+		sort_by_fitness(&mut self.population);
+		let mut emigrants = Vector::new();
+		for i in 0..amount.min(self.population.len()) {
+			emigrants.push(self.population[i].clone());
+		}
+		emigrants
+	}
+	/// Replaces the least fit chromosomes with immigrants
+	fn settle(&mut self, immigrants: Vector<Chromosome<F>>) {
+		sort_by_fitness(&mut self.population);
+		let len = self.population.len();
+		for i in 0..immigrants.len().min(len) {
+			self.population[len - 1 - i] = immigrants[i].clone();
+		}
+	}
+}
+
+/// Sorts a population from most fit to least fit
+fn sort_by_fitness<F>(population: &mut Vector<Chromosome<F>>) {
+	population.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Equal));
+	population.reverse();
+}
+
+/// Breeds every island for given number of generations. Islands are bred on
+/// config.threads threads at a time; every island uses only its own random
+/// number generator, so the result doesn't depend on the thread count.
+fn evolve_islands<F: FitnessFunction + 'static>(mut islands: Vector<Island<F>>,
+	generations: usize, config: &GaConfig) -> Vector<Island<F>> {
+	let mut island_config = *config;
+	island_config.threads = 1;
+	let mut evolved = Vector::new_with_size(islands.len());
+	if config.threads <= 1 {
+		while islands.len() > 0 {
+			evolved.push(islands.remove(0).evolve(generations, &island_config));
+		}
+		return evolved;
+	}
+	while islands.len() > 0 {
+		let mut handles = Vector::new();
+		while handles.len() < config.threads && islands.len() > 0 {
+			let island = islands.remove(0);
+			handles.push(thread::spawn(move || {
+				island.evolve(generations, &island_config)
+			}));
+		}
+		while handles.len() > 0 {
+			let handle: thread::JoinHandle<Island<F>> = handles.remove(0);
+			evolved.push(handle.join().unwrap());
+		}
+	}
+	evolved
+}
+
+/// Moves copies of the most fit chromosomes of every island to the next
+/// island in a ring, where they replace the least fit chromosomes
+fn migrate<F: FitnessFunction + 'static>(islands: &mut Vector<Island<F>>,
+	migrants: usize) {
+	let mut emigrants = Vector::new_with_size(islands.len());
+	for i in 0..islands.len() {
+		emigrants.push(islands[i].emigrants(migrants));
+	}
+	for i in 0..islands.len() {
+		let destination = (i + 1) % islands.len();
+		islands[destination].settle(emigrants[i].clone());
+	}
+}
+
+/// An alternative to breeder that uses the island model: the population is
+/// split into config.islands independent populations that are bred side by
+/// side, and every config.migration_interval generations config.migrants of
+/// the most fit chromosomes of each island migrate to the next island. There
+/// are never more islands than chromosomes, so that no island is left empty.
+/// # Panics
+/// Panics if can't for some reason find a most fit chromosomes.
+pub fn island_breeder<F: FitnessFunction + 'static, R: Rng>(
+	genes: Vector<Gene>, targets: Vector<Target>, site: Arc<Site>,
+	fitness_function: Arc<F>, pop_size: usize, generations: usize,
+	config: &GaConfig, verbosity: Verbosity, rng: &mut R) -> Chromosome<F> {
+	let island_count = config.islands.min(pop_size).max(1);
+	let mut islands = Vector::new_with_size(island_count);
+	for i in 0..island_count {
+		// Spread the population evenly, giving the remainder to the first islands
+		let size = pop_size / island_count +
+			if i < pop_size % island_count { 1 } else { 0 };
+		islands.push(Island::new(genes.clone(), targets.clone(), site.clone(),
+			fitness_function.clone(), size, rng.gen()));
+	}
+	let interval = config.migration_interval.max(1);
+	let epochs = (generations + interval - 1) / interval;
+	for epoch in 0..epochs {
+		let epoch_generations = interval.min(generations - epoch * interval);
+		islands = evolve_islands(islands, epoch_generations, config);
+		if epoch + 1 < epochs {
+			migrate(&mut islands, config.migrants);
+		}
+		if verbosity != Verbosity::Quiet {
+			let mut best = 0.0;
+			for i in 0..islands.len() {
+				if let Some(chromosome) = most_fit(&islands[i].population) {
+					best = chromosome.fitness.max(best);
+				}
+			}
+			println!("Generation {}/{}, largest fitness on {} islands: {}",
+			(epoch * interval + epoch_generations), generations, islands.len(),
+			best);
+		}
+	}
+	let mut best: Option<&Chromosome<F>> = None;
+	for i in 0..islands.len() {
+		if let Some(chromosome) = most_fit(&islands[i].population) {
+			if best.map_or(true, |b| chromosome.fitness > b.fitness) {
+				best = Some(chromosome);
+			}
+		}
+	}
+	match best {
+		None => panic!("Couldn't find most fit chromosome!"),
+		Some(chromosome) => chromosome.clone()
+	}
+}
+
 #[cfg(test)]
 mod tests {
 
     use super::*;
     use mapping::shapes::Rect;
-    use genetics::genes::{Gene, Chromosome};
+    use genetics::genes::{Gene, Target, Chromosome};
+    use genetics::fitness::{self, AreaFitness, DistanceMetric};
+    use genetics::config::GaConfig;
     use collections::Vector;
+    use io::ui::Verbosity;
+    use mapping::site::Site;
+    use std::sync::Arc;
     use rand;
 
     #[test]
@@ -255,13 +505,32 @@ mod tests {
         genes.push(gene3);
         genes.push(gene4);
         let initial_pop = generate_initial_population(
-          genes, Vector::new(), 100, &mut rng
+          genes, Vector::new(), Arc::new(Site::empty()), Arc::new(AreaFitness),
+          100, &mut rng
         );
         assert_eq!(100, initial_pop.len());
-        let next_pop = breed(initial_pop, &mut rng);
+        let next_pop = breed(initial_pop, &GaConfig::new(), &mut rng);
         assert_eq!(100, next_pop.len());
     }
 
+    #[test]
+    fn purge_keeps_the_fittest_chromosomes() {
+        let mut genes = Vector::new();
+        genes.push(Gene::new(Rect::new(0, 0, 5, 5), 0));
+        genes.push(Gene::new(Rect::new(0, 0, 3, 7), 1));
+        genes.push(Gene::new(Rect::new(0, 0, 8, 4), 2));
+        let mut rng = seeded_rng(7);
+        let mut population = generate_initial_population(
+            genes, Vector::new(), Arc::new(Site::empty()), Arc::new(AreaFitness),
+            20, &mut rng);
+        let best = most_fit(&population).unwrap().clone();
+        let mut config = GaConfig::new();
+        config.purge_percentage = 0.75;
+        purge(&mut population, &config, &mut rng);
+        assert_eq!(20, population.len());
+        assert!(best == population[0]);
+    }
+
     #[test]
     fn search_candidate_finds_correct_candidate() {
       let rect = Rect { x:0, y:0, w:2, h:2 };
@@ -284,4 +553,89 @@ mod tests {
       assert_eq!(8, found_candidate.chromosome.genes.len());
     }
 
+    #[test]
+    fn same_seed_gives_same_result() {
+      let mut genes = Vector::new();
+      for i in 0..8 {
+        let rect = Rect { x: 0, y: 0, w: (i * 5) % 7 + 3, h: (i * 3) % 5 + 3 };
+        genes.push(Gene::new(rect, i));
+      }
+      let mut from = Vector::new();
+      from.push(0);
+      let mut to = Vector::new();
+      to.push(3);
+      to.push(5);
+      let mut targets = Vector::new();
+      targets.push(Target::new(from, to, 1.0));
+      let config = GaConfig::new();
+      let site = Arc::new(Site::empty());
+      let result1 = breeder(genes.clone(), targets.clone(), site.clone(),
+        Arc::new(fitness::default_for(&targets, DistanceMetric::Euclidean)),
+        30, 20,
+        &config,
+        Verbosity::Quiet, &mut seeded_rng(1337));
+      let result2 = breeder(genes.clone(), targets.clone(), site.clone(),
+        Arc::new(fitness::default_for(&targets, DistanceMetric::Euclidean)),
+        30, 20,
+        &config,
+        Verbosity::Quiet, &mut seeded_rng(1337));
+      assert!(result1 == result2, "{:?} != {:?}", result1, result2);
+      assert!(result1.genes == result2.genes);
+    }
+
+    #[test]
+    fn same_seed_and_threads_give_same_result() {
+      let mut genes = Vector::new();
+      for i in 0..8 {
+        let rect = Rect { x: 0, y: 0, w: (i * 5) % 7 + 3, h: (i * 3) % 5 + 3 };
+        genes.push(Gene::new(rect, i));
+      }
+      let mut config = GaConfig::new();
+      config.threads = 3;
+      let site = Arc::new(Site::empty());
+      let result1 = breeder(genes.clone(), Vector::new(), site.clone(),
+        Arc::new(AreaFitness), 30, 20,
+        &config,
+        Verbosity::Quiet, &mut seeded_rng(1337));
+      let result2 = breeder(genes.clone(), Vector::new(), site.clone(),
+        Arc::new(AreaFitness), 30, 20,
+        &config,
+        Verbosity::Quiet, &mut seeded_rng(1337));
+      assert!(result1.genes == result2.genes);
+    }
+
+    #[test]
+    fn island_breeder_does_not_depend_on_thread_count() {
+      let mut genes = Vector::new();
+      for i in 0..8 {
+        let rect = Rect { x: 0, y: 0, w: (i * 5) % 7 + 3, h: (i * 3) % 5 + 3 };
+        genes.push(Gene::new(rect, i));
+      }
+      let mut config = GaConfig::new();
+      config.islands = 3;
+      config.migration_interval = 7;
+      let site = Arc::new(Site::empty());
+      let result1 = island_breeder(genes.clone(), Vector::new(), site.clone(),
+        Arc::new(AreaFitness), 31, 20, &config, Verbosity::Quiet, &mut seeded_rng(42));
+      config.threads = 2;
+      let result2 = island_breeder(genes.clone(), Vector::new(), site.clone(),
+        Arc::new(AreaFitness), 31, 20, &config, Verbosity::Quiet, &mut seeded_rng(42));
+      assert_eq!(8, result1.genes.len());
+      assert!(result1.genes == result2.genes);
+    }
+
+    #[test]
+    fn island_breeder_leaves_no_island_empty() {
+      let mut genes = Vector::new();
+      for i in 0..4 {
+        genes.push(Gene::new(Rect { x: 0, y: 0, w: i + 3, h: 4 }, i));
+      }
+      let mut config = GaConfig::new();
+      config.islands = 5;
+      config.migration_interval = 3;
+      let result = island_breeder(genes, Vector::new(), Arc::new(Site::empty()),
+        Arc::new(AreaFitness), 3, 10, &config, Verbosity::Quiet, &mut seeded_rng(7));
+      assert_eq!(4, result.genes.len());
+    }
+
 }
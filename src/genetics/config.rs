@@ -0,0 +1,107 @@
+//! This module contains the parameters that control how the genetic algorithm
+//! runs.
+
+/// Default chance that, during mating, two genes will be switched
+pub const DEFAULT_CROSSOVER_CHANCE: f32 = 0.5;
+/// Default chance that, during mutation, a gene will mutate
+pub const DEFAULT_MUTATION_CHANCE: f32 = 0.04;
+/// Default percentage of population that should be kept alive for the next
+/// round of breeding
+pub const DEFAULT_KEEP_ALIVE_PERCENTAGE: f32 = 0.1;
+/// Default percentage of population to kill when purging a population
+pub const DEFAULT_PURGE_PERCENTAGE: f32 = 0.7;
+/// Default number of generations of stagnation before purge
+pub const DEFAULT_PURGE_INTERVAL: usize = 100;
+/// Default number of threads to breed children on
+pub const DEFAULT_THREADS: usize = 1;
+/// Default number of islands; with one island the island model isn't used
+pub const DEFAULT_ISLANDS: usize = 1;
+/// Default number of generations between migrations of the island model
+pub const DEFAULT_MIGRATION_INTERVAL: usize = 50;
+/// Default number of chromosomes migrating from each island
+pub const DEFAULT_MIGRANTS: usize = 2;
+/// Default relative probabilities of the mutation types
+pub const DEFAULT_MUTATION_WEIGHTS: MutationWeights = MutationWeights {
+    rotation: 1.0,
+    flip: 1.0,
+    position: 1.0,
+    nudge: 2.0,
+    swap: 0.5,
+    slide: 1.0,
+    level: 1.0,
+};
+
+/// Parameters of the genetic algorithm. Chances and percentages are between
+/// 0.0 and 1.0.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct GaConfig {
+    /// The chance that, during mating, two genes will be switched
+    pub crossover_chance: f32,
+    /// The chance that, during mutation, a gene will mutate
+    pub mutation_chance: f32,
+    /// Percentage of population that should be kept alive for the next round
+    /// of breeding
+    pub keep_alive_percentage: f32,
+    /// Percentage of population to kill when purging a population
+    pub purge_percentage: f32,
+    /// How many generations of stagnation before purge
+    pub purge_interval: usize,
+    /// How many threads children are bred on. Results are reproducible for
+    /// the same seed and the same number of threads.
+    pub threads: usize,
+    /// How many independent populations the island model breeds. With more
+    /// than one island, the population size is split between them.
+    pub islands: usize,
+    /// How many generations the islands are bred between migrations
+    pub migration_interval: usize,
+    /// How many of the most fit chromosomes of an island migrate to the next
+    /// island
+    pub migrants: usize,
+    /// How likely each type of mutation is compared to the others
+    pub mutation_weights: MutationWeights,
+}
+
+/// Relative probabilities of the mutation types (see
+/// genes::ChromosomeMutation). A mutation type with weight 0.0 is never used.
+/// Types that can't change a gene are left out, and the chances of the rest
+/// are in proportion to their weights.
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub struct MutationWeights {
+    /// Turning the room
+    pub rotation: f32,
+    /// Flipping the room over; shared by horizontal and vertical flips
+    pub flip: f32,
+    /// Moving the room to a random spot anywhere in the allowed area
+    pub position: f32,
+    /// Moving the room by a few tiles
+    pub nudge: f32,
+    /// Swapping the places of two rooms of different types
+    pub swap: f32,
+    /// Sliding the room in a straight line until it's about to hit another
+    pub slide: f32,
+    /// Moving the room to another z-level
+    pub level: f32,
+}
+
+impl GaConfig {
+    /// Creates a config with the default parameters
+    pub fn new() -> Self {
+        GaConfig {
+            crossover_chance: DEFAULT_CROSSOVER_CHANCE,
+            mutation_chance: DEFAULT_MUTATION_CHANCE,
+            keep_alive_percentage: DEFAULT_KEEP_ALIVE_PERCENTAGE,
+            purge_percentage: DEFAULT_PURGE_PERCENTAGE,
+            purge_interval: DEFAULT_PURGE_INTERVAL,
+            threads: DEFAULT_THREADS,
+            islands: DEFAULT_ISLANDS,
+            migration_interval: DEFAULT_MIGRATION_INTERVAL,
+            migrants: DEFAULT_MIGRANTS,
+            mutation_weights: DEFAULT_MUTATION_WEIGHTS,
+        }
+    }
+}
+
+/// Checks if the value can be used as a chance or a percentage
+pub fn is_valid_chance(value: f32) -> bool {
+    value >= 0.0 && value <= 1.0
+}
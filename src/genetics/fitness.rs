@@ -0,0 +1,899 @@
+//! This module contains the fitness functions that measure how good a solution
+//! a chromosome is. Library users can implement FitnessFunction to score
+//! layouts their own way, and breed populations of chromosomes scored by it.
+
+use std::f32;
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::{Arc, Mutex, MutexGuard};
+use collections::{Matrix, Vector};
+use mapping::doors::Doors;
+use mapping::masks::Shape;
+use mapping::paths;
+use mapping::shapes::{Point, Rect};
+use mapping::site::Site;
+use super::genes::{Gene, Genome, Target, LEVEL_DISTANCE};
+
+/// Default weight of the target distances in CompositeFitness
+pub const DEFAULT_DISTANCE_WEIGHT: f32 = 1.0;
+/// Default weight of the bounding box utilization in CompositeFitness
+pub const DEFAULT_UTILIZATION_WEIGHT: f32 = 1.0;
+/// Default weight of the bounding box perimeter in CompositeFitness
+pub const DEFAULT_PERIMETER_WEIGHT: f32 = 1.0;
+/// Walking distances between rooms that can't reach each other are the
+/// Manhattan distance multiplied by this
+pub const UNREACHABLE_FACTOR: f32 = 2.0;
+/// The largest fitness AreaFitness gives, to the genomes whose genes use up
+/// all of their bounding box
+pub const MAX_AREA_FITNESS: f32 = 10000.0;
+/// The largest fitness DistanceFitness gives, to the genomes whose targets are
+/// so many or so short that the score would overflow
+pub const MAX_DISTANCE_FITNESS: f32 = 1.0e8;
+/// How many placements of levels the walking distances are remembered for.
+/// When there would be more, the remembered walks are forgotten.
+pub const MAX_CACHED_PLACEMENTS: usize = 1024;
+
+/// How the distances between the rooms of attraction targets are measured
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum DistanceMetric {
+    /// Straight line between the nearest entrances of the rooms (see
+    /// Gene::entrances)
+    Euclidean,
+    /// Steps along the grid between the nearest entrances of the rooms
+    Manhattan,
+    /// Shortest walk along the grid from a wall of one room to a wall of the
+    /// other, going around the rooms and blocked tiles in between
+    Walking,
+}
+
+/// A fitness function gives a score to the genome of a chromosome; the fitter
+/// the chromosome, the larger the score. Scores must be positive and finite,
+/// because chromosomes are picked for breeding with probabilities proportional
+/// to them. The fitness function is shared by all the chromosomes of a
+/// population, and by the threads breeding them.
+pub trait FitnessFunction: Send + Sync {
+    /// Calculates the fitness of given genome
+    fn fitness(&self, genome: &Genome) -> f32;
+}
+
+/// Scores chromosomes by the weighted average of the distances between the
+/// genes of attraction targets; the shorter, the better. The score is at most
+/// MAX_DISTANCE_FITNESS. Rooms on different levels are reached through the
+/// stairs (see Genome::travel_distance).
+/// Walking distances are remembered between evaluations, and clones of the
+/// fitness function share what they remember.
+#[derive(Clone, Debug)]
+pub struct DistanceFitness {
+    pub metric: DistanceMetric,
+    walks: Arc<WalkCache>,
+}
+
+/// Scores chromosomes by comparing the area used up by the genes to the area
+/// of their bounding box, so compact, rectangular designs flourish. Genes that
+/// use up all of their bounding box get MAX_AREA_FITNESS.
+#[derive(Copy, Clone, Debug)]
+pub struct AreaFitness;
+
+/// Scores chromosomes by the share of their bounding box that the genes use up
+/// (total_area / bounding_box.area()), between 0.0 and 1.0
+#[derive(Copy, Clone, Debug)]
+pub struct UtilizationFitness;
+
+/// Scores chromosomes by comparing the smallest perimeter that a box with the
+/// total area of the genes could have (a square) to the perimeter of their
+/// bounding box, so the score is between 0.0 and 1.0
+#[derive(Copy, Clone, Debug)]
+pub struct PerimeterFitness;
+
+/// Mixes the target distances with the utilization and the perimeter of the
+/// bounding box, so that layouts are both well connected and compact. The
+/// fitness is the product of DistanceFitness, UtilizationFitness and
+/// PerimeterFitness, each raised to the power of its weight; since the parts
+/// are multiplied, their different scales don't matter, only how much each one
+/// improves.
+#[derive(Clone, Debug)]
+pub struct CompositeFitness {
+    pub distance: f32,
+    pub utilization: f32,
+    pub perimeter: f32,
+    pub metric: DistanceMetric,
+    walks: Arc<WalkCache>,
+}
+
+/// Combines other fitness functions: the fitness is the weighted average of
+/// their fitnesses.
+pub struct WeightedFitness {
+    parts: Vector<(f32, Box<FitnessFunction>)>,
+}
+
+/// Keeps what walking distances need between the fitness evaluations of a run:
+/// the grids of the site's levels, built once per level, and the distances
+/// walked between the genes of a level, by the placement of the genes on the
+/// level. Everything is forgotten when the genomes are on another site.
+struct WalkCache {
+    state: Mutex<CachedWalks>,
+}
+
+/// The contents of a WalkCache
+struct CachedWalks {
+    site: Option<Arc<Site>>,
+    grids: Vector<(isize, Arc<SiteGrid>)>,
+    walks: HashMap<Placement, Vector<(usize, usize, f32)>>,
+}
+
+/// The tiles of a level of a site that can't be walked on: blocked tiles and
+/// tiles outside the bounds. The grid covers the map of the level and the
+/// bounds, so beyond it only the bounds block.
+struct SiteGrid {
+    area: Rect,
+    blocked: Matrix<bool>,
+    bounded: bool,
+}
+
+/// The genes of a level and the area of the grid that they are walked on
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct Placement {
+    area: Rect,
+    genes: Vector<Gene>,
+}
+
+/// The distances walked between the genes of a level during an evaluation.
+/// The first `cached` of them were remembered by the WalkCache.
+struct LevelWalks {
+    z: isize,
+    placement: Placement,
+    distances: Vector<(usize, usize, f32)>,
+    cached: usize,
+}
+
+/// Measures the distances between the genes of a chromosome with a metric,
+/// from the entrances of the genes (see Gene::entrances). Walking distances
+/// are found on an occupancy grid of each level, which is built the first time
+/// it's needed; the distances from a gene to every tile of its level are kept,
+/// so each gene is walked from at most once. Walks are looked up from the
+/// WalkCache first, and the new ones are added to it when done.
+struct TravelDistances<'a> {
+    genome: &'a Genome,
+    metric: DistanceMetric,
+    cache: &'a WalkCache,
+    site: Arc<Site>,
+    entrances: Vector<Vector<Point>>,
+    origin: Point,
+    grids: Vector<(isize, Matrix<bool>)>,
+    walks: Vector<Option<Matrix<isize>>>,
+    levels: Vector<LevelWalks>,
+}
+
+impl DistanceMetric {
+    /// Gives the metric with given name ("euclidean", "manhattan" or
+    /// "walking"), or None if there's no such metric
+    pub fn from_name(name: &str) -> Option<DistanceMetric> {
+        match name {
+            "euclidean" => Some(DistanceMetric::Euclidean),
+            "manhattan" => Some(DistanceMetric::Manhattan),
+            "walking" => Some(DistanceMetric::Walking),
+            _ => None,
+        }
+    }
+}
+
+impl WalkCache {
+    fn new() -> Self {
+        WalkCache {
+            state: Mutex::new(CachedWalks {
+                site: None,
+                grids: Vector::new(),
+                walks: HashMap::new(),
+            }),
+        }
+    }
+    /// Locks the cache for walks on given site, forgetting everything if the
+    /// last walks were on another site
+    fn lock<'a>(&'a self, site: &Arc<Site>) -> MutexGuard<'a, CachedWalks> {
+        let mut state = self.state.lock().unwrap();
+        let same_site = match state.site {
+            Some(ref known) => {
+                &**known as *const Site == &**site as *const Site
+            },
+            None => false,
+        };
+        if !same_site {
+            state.site = Some(site.clone());
+            state.grids = Vector::new();
+            state.walks.clear();
+        }
+        state
+    }
+    /// Gives the grid of level z of given site, building it if needed
+    fn site_grid(&self, site: &Arc<Site>, z: isize) -> Arc<SiteGrid> {
+        let mut state = self.lock(site);
+        if let Some(i) = state.grids.iter().position(|&(level, _)| level == z) {
+            return state.grids[i].1.clone();
+        }
+        let grid = Arc::new(SiteGrid::new(site, z));
+        state.grids.push((z, grid.clone()));
+        grid
+    }
+    /// Gives the distances remembered for given placement on given site
+    fn known(&self, site: &Arc<Site>, placement: &Placement)
+        -> Vector<(usize, usize, f32)> {
+        let state = self.lock(site);
+        match state.walks.get(placement) {
+            Some(distances) => distances.clone(),
+            None => Vector::new(),
+        }
+    }
+    /// Remembers the distances walked on given placement on given site
+    fn remember(&self, site: &Arc<Site>, placement: Placement,
+                distances: Vector<(usize, usize, f32)>) {
+        let mut state = self.lock(site);
+        if state.walks.len() >= MAX_CACHED_PLACEMENTS &&
+           !state.walks.contains_key(&placement) {
+            state.walks.clear();
+        }
+        state.walks.insert(placement, distances);
+    }
+}
+
+impl Debug for WalkCache {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "WalkCache")
+    }
+}
+
+impl SiteGrid {
+    /// Builds the grid of level z of given site
+    fn new(site: &Site, z: isize) -> Self {
+        let bounds = site.get_bounds();
+        let area = match (site.map_area(z), bounds) {
+            (Some(map), Some(bounds)) => {
+                let x = map.x.min(bounds.x);
+                let y = map.y.min(bounds.y);
+                Rect::new(x, y, (map.x + map.w).max(bounds.x + bounds.w) - x,
+                          (map.y + map.h).max(bounds.y + bounds.h) - y)
+            },
+            (Some(area), None) | (None, Some(area)) => area,
+            (None, None) => Rect::new(0, 0, 0, 0),
+        };
+        let mut blocked = Matrix::new(area.w as usize, area.h as usize);
+        for x in 0..blocked.w {
+            for y in 0..blocked.h {
+                let tile = Point::new(x as isize + area.x, y as isize + area.y);
+                if site.is_blocked(tile.x, tile.y, z) ||
+                   !site.is_inside(Rect::new(tile.x, tile.y, 1, 1)) {
+                    blocked.set(x, y, true);
+                }
+            }
+        }
+        SiteGrid {
+            area: area,
+            blocked: blocked,
+            bounded: bounds.is_some(),
+        }
+    }
+    /// Checks if given tile can't be walked on
+    fn is_blocked(&self, tile: Point) -> bool {
+        let x = tile.x - self.area.x;
+        let y = tile.y - self.area.y;
+        if x < 0 || y < 0 || x >= self.area.w || y >= self.area.h {
+            return self.bounded;
+        }
+        self.blocked.get(x as usize, y as usize).is_some()
+    }
+}
+
+impl LevelWalks {
+    /// Gives the distance walked between given genes, if it's known. Walks
+    /// are the same both ways.
+    fn distance(&self, from: usize, to: usize) -> Option<f32> {
+        for i in 0..self.distances.len() {
+            let (a, b, distance) = self.distances[i];
+            if (a, b) == (from, to) || (a, b) == (to, from) {
+                return Some(distance);
+            }
+        }
+        None
+    }
+}
+
+impl<'a> TravelDistances<'a> {
+    fn new(genome: &'a Genome, metric: DistanceMetric, cache: &'a WalkCache)
+        -> Self {
+        let bounding_box = genome.get_bounding_box();
+        let genes = &genome.genes;
+        let mut entrances = Vector::new_with_size(genes.len());
+        let mut walks = Vector::new_with_size(genes.len());
+        for i in 0..genes.len() {
+            entrances.push(genes[i].entrances());
+            walks.push(None);
+        }
+        TravelDistances {
+            genome: genome,
+            metric: metric,
+            cache: cache,
+            site: genome.get_site(),
+            entrances: entrances,
+            // Leave a border of one tile around the genes to walk on
+            origin: Point::new(bounding_box.x - 1, bounding_box.y - 1),
+            grids: Vector::new(),
+            walks: walks,
+            levels: Vector::new(),
+        }
+    }
+    /// Gives the distance from gene from to gene to, between their nearest
+    /// entrances. Rooms on different levels are reached through the stairs,
+    /// and walks to the stairs are measured as Manhattan distances.
+    fn between(&mut self, from: usize, to: usize) -> f32 {
+        let genome = self.genome;
+        let genes = &genome.genes;
+        if genes[from].get_z() != genes[to].get_z() {
+            let stairs = genes[0].room_rect().center();
+            let levels = (genes[from].get_z() - genes[to].get_z()).abs() as f32;
+            return self.nearest(from, stairs) + levels * LEVEL_DISTANCE +
+                self.nearest(to, stairs);
+        }
+        if self.metric == DistanceMetric::Walking && from != to {
+            return self.walk(from, to);
+        }
+        let mut shortest = f32::INFINITY;
+        for i in 0..self.entrances[to].len() {
+            shortest = shortest.min(self.nearest(from, self.entrances[to][i]));
+        }
+        shortest
+    }
+    /// Gives the distance from the nearest entrance of a gene to a point,
+    /// measuring walks as Manhattan distances
+    fn nearest(&self, gene: usize, point: Point) -> f32 {
+        let mut shortest = f32::INFINITY;
+        for i in 0..self.entrances[gene].len() {
+            let entrance = self.entrances[gene][i];
+            let distance = match self.metric {
+                DistanceMetric::Euclidean => entrance.dist(point),
+                _ => paths::manhattan(entrance, point) as f32,
+            };
+            shortest = shortest.min(distance);
+        }
+        shortest
+    }
+    /// Gives the walking distance between two genes on the same level,
+    /// walking only if the distance isn't known yet
+    fn walk(&mut self, from: usize, to: usize) -> f32 {
+        let level = self.level_walks(self.genome.genes[from].get_z());
+        if let Some(distance) = self.levels[level].distance(from, to) {
+            return distance;
+        }
+        let distance = self.walk_on_grid(from, to);
+        self.levels[level].distances.push((from, to, distance));
+        distance
+    }
+    /// Gives the index of the walks of level z, starting them from the walks
+    /// that the cache remembers for the placement of the genes on the level
+    fn level_walks(&mut self, z: isize) -> usize {
+        if let Some(i) = self.levels.iter().position(|level| level.z == z) {
+            return i;
+        }
+        let bounding_box = self.genome.get_bounding_box();
+        let genes = &self.genome.genes;
+        let mut placement = Placement {
+            area: Rect::new(self.origin.x, self.origin.y, bounding_box.w + 2,
+                            bounding_box.h + 2),
+            genes: Vector::new(),
+        };
+        for i in 0..genes.len() {
+            if genes[i].get_z() == z {
+                placement.genes.push(genes[i]);
+            }
+        }
+        let distances = self.cache.known(&self.site, &placement);
+        self.levels.push(LevelWalks {
+            z: z,
+            placement: placement,
+            cached: distances.len(),
+            distances: distances,
+        });
+        self.levels.len() - 1
+    }
+    /// Walks from the entrances of a room out through its walls, or its fixed
+    /// doors, and on to the entrances of the other room (see exits).
+    /// Walks are the same both ways, so an already walked gene is walked
+    /// from.
+    fn walk_on_grid(&mut self, from: usize, to: usize) -> f32 {
+        let walked = self.walks[to].is_some() && self.walks[from].is_none();
+        let (from, to) = if walked {
+            (to, from)
+        } else {
+            (from, to)
+        };
+        let genome = self.genome;
+        let genes = &genome.genes;
+        let level = self.grid_index(genes[from].get_z());
+        let from_rect = self.local_rect(genes[from].room_rect());
+        let to_rect = self.local_rect(genes[to].room_rect());
+        let grid = &self.grids[level].1;
+        if self.walks[from].is_none() {
+            let starts = exits(grid, from_rect, &genes[from].get_shape(),
+                               &genes[from].get_doors());
+            self.walks[from] = Some(paths::distances_from(grid, &starts));
+        }
+        let walk = self.walks[from].as_ref().unwrap();
+        let goals = exits(grid, to_rect, &genes[to].get_shape(),
+                          &genes[to].get_doors());
+        let mut shortest: Option<isize> = None;
+        for i in 0..goals.len() {
+            let (exit, cost) = goals[i];
+            if let Some(walked) = *walk.get(exit.x as usize, exit.y as usize) {
+                let total = walked + cost;
+                shortest = Some(shortest.map_or(total, |s| s.min(total)));
+            }
+        }
+        match shortest {
+            Some(length) => length as f32,
+            None => UNREACHABLE_FACTOR * self.nearest_entrances(from, to),
+        }
+    }
+    /// Gives the steps along the grid between the nearest entrances of two
+    /// genes, ignoring what is in the way
+    fn nearest_entrances(&self, from: usize, to: usize) -> f32 {
+        let mut shortest = f32::INFINITY;
+        for i in 0..self.entrances[from].len() {
+            for j in 0..self.entrances[to].len() {
+                let steps = paths::manhattan(self.entrances[from][i],
+                                             self.entrances[to][j]);
+                shortest = shortest.min(steps as f32);
+            }
+        }
+        shortest
+    }
+    /// Moves a rectangle from layout coordinates to grid coordinates
+    fn local_rect(&self, rect: Rect) -> Rect {
+        Rect::new(rect.x - self.origin.x, rect.y - self.origin.y, rect.w, rect.h)
+    }
+    /// Gives the index of the grid of level z, building the grid if needed.
+    /// The rooms on the level, blocked tiles and tiles outside the bounds of
+    /// the site (see SiteGrid) can't be walked on.
+    fn grid_index(&mut self, z: isize) -> usize {
+        let index = self.grids.iter().position(|&(level, _)| level == z);
+        if let Some(index) = index {
+            return index;
+        }
+        let genome = self.genome;
+        let bounding_box = genome.get_bounding_box();
+        let mut grid = Matrix::new((bounding_box.w + 2) as usize,
+                                   (bounding_box.h + 2) as usize);
+        let genes = &genome.genes;
+        for i in 0..genes.len() {
+            if genes[i].get_z() != z {
+                continue;
+            }
+            let rect = self.local_rect(genes[i].room_rect());
+            let tiles = genes[i].get_shape().tiles(rect);
+            for t in 0..tiles.len() {
+                grid.set(tiles[t].x as usize, tiles[t].y as usize, true);
+            }
+        }
+        let site_grid = self.cache.site_grid(&self.site, z);
+        for x in 0..grid.w {
+            for y in 0..grid.h {
+                let tile = Point::new(x as isize + self.origin.x,
+                                      y as isize + self.origin.y);
+                if site_grid.is_blocked(tile) {
+                    grid.set(x, y, true);
+                }
+            }
+        }
+        self.grids.push((z, grid));
+        self.grids.len() - 1
+    }
+}
+
+impl<'a> Drop for TravelDistances<'a> {
+    /// Adds the new walks to the cache
+    fn drop(&mut self) {
+        for i in 0..self.levels.len() {
+            let level = &self.levels[i];
+            if level.distances.len() > level.cached {
+                self.cache.remember(&self.site, level.placement.clone(),
+                                    level.distances.clone());
+            }
+        }
+    }
+}
+
+/// Gives the tiles that walks leave a room from (see paths::exits) with the
+/// length of the walk to each of them from the nearest entrance of the room
+/// (see Doors::entrances): one step from a fixed door, or the steps from the
+/// center of the room
+fn exits(grid: &Matrix<bool>, room: Rect, shape: &Shape, doors: &Doors)
+    -> Vector<(Point, isize)> {
+    let tiles = paths::exits(grid, room, shape, doors);
+    let entrances = doors.entrances(room);
+    let mut exits = Vector::new_with_size(tiles.len());
+    for i in 0..tiles.len() {
+        let mut cost = isize::max_value();
+        for j in 0..entrances.len() {
+            cost = cost.min(paths::manhattan(entrances[j], tiles[i]));
+        }
+        exits.push((tiles[i], cost));
+    }
+    exits
+}
+
+impl DistanceFitness {
+    /// Creates a distance fitness that measures distances with given metric
+    pub fn new(metric: DistanceMetric) -> Self {
+        DistanceFitness {
+            metric: metric,
+            walks: Arc::new(WalkCache::new()),
+        }
+    }
+}
+
+impl FitnessFunction for DistanceFitness {
+    fn fitness(&self, genome: &Genome) -> f32 {
+        distance_fitness(genome, self.metric, &self.walks)
+    }
+}
+
+/// Scores a genome like DistanceFitness, remembering walks in given cache.
+/// Gives 1.0 if there are no attraction targets.
+fn distance_fitness(genome: &Genome, metric: DistanceMetric,
+                    walks: &WalkCache) -> f32 {
+    let targets = &genome.targets;
+    let mut distances = TravelDistances::new(genome, metric, walks);
+    let mut total_dist = 0.0;
+    let mut divisor = 0.0;
+    let mut attractions = 0;
+    for t in 0..targets.len() {
+        if !targets[t].is_attraction() {
+            continue;
+        }
+        attractions += 1;
+        let mut target_dist = 0.0;
+        let mut n = 0;
+        // Distances are the same both ways, so measure them from the side
+        // with fewer genes to walk from fewer genes
+        let target = &targets[t];
+        let (near, far) = if target.to_id.len() < target.from_id.len() {
+            (&target.to_id, &target.from_id)
+        } else {
+            (&target.from_id, &target.to_id)
+        };
+        for i in 0..near.len() {
+            for j in 0..far.len() {
+                target_dist += distances.between(near[i], far[j]);
+                n += 1;
+            }
+        }
+        target_dist *= targets[t].weight;
+        target_dist /= n as f32;
+        divisor += targets[t].weight;
+        total_dist += target_dist;
+    }
+    if attractions == 0 {
+        return 1.0;
+    }
+    let weighted_average = total_dist / divisor;
+    (10000.0 as f32).powf(attractions as f32 / weighted_average)
+        .min(MAX_DISTANCE_FITNESS)
+}
+
+impl FitnessFunction for AreaFitness {
+    fn fitness(&self, genome: &Genome) -> f32 {
+        let raw_fitness = genome.get_total_area() as f32 /
+            genome.get_bounding_box().area() as f32;
+        // Using up the whole bounding box would give infinity
+        (1.0 / raw_fitness.log10().abs()).min(MAX_AREA_FITNESS)
+    }
+}
+
+impl FitnessFunction for UtilizationFitness {
+    fn fitness(&self, genome: &Genome) -> f32 {
+        genome.get_total_area() as f32 /
+            genome.get_bounding_box().area() as f32
+    }
+}
+
+impl FitnessFunction for PerimeterFitness {
+    fn fitness(&self, genome: &Genome) -> f32 {
+        let bounding_box = genome.get_bounding_box();
+        let smallest = 4.0 * (genome.get_total_area() as f32).sqrt();
+        smallest / (2 * (bounding_box.w + bounding_box.h)) as f32
+    }
+}
+
+impl CompositeFitness {
+    /// Creates a composite of the parts with given weights, measuring the
+    /// distances with given metric
+    pub fn new(distance: f32, utilization: f32, perimeter: f32,
+               metric: DistanceMetric) -> Self {
+        CompositeFitness {
+            distance: distance,
+            utilization: utilization,
+            perimeter: perimeter,
+            metric: metric,
+            walks: Arc::new(WalkCache::new()),
+        }
+    }
+}
+
+impl FitnessFunction for CompositeFitness {
+    /// Without attraction targets, only the bounding box is scored. Large
+    /// weights could overflow the product, so it's kept finite.
+    fn fitness(&self, genome: &Genome) -> f32 {
+        let distance = distance_fitness(genome, self.metric, &self.walks);
+        (distance.powf(self.distance) *
+         UtilizationFitness.fitness(genome).powf(self.utilization) *
+         PerimeterFitness.fitness(genome).powf(self.perimeter)).min(f32::MAX)
+    }
+}
+
+impl WeightedFitness {
+    /// Creates a combination without any fitness functions in it
+    pub fn new() -> Self {
+        WeightedFitness { parts: Vector::new() }
+    }
+    /// Adds a fitness function with given weight to the combination
+    pub fn add(&mut self, weight: f32, function: Box<FitnessFunction>) {
+        self.parts.push((weight, function));
+    }
+}
+
+impl FitnessFunction for WeightedFitness {
+    /// Gives 1.0 if there are no fitness functions with a positive weight
+    fn fitness(&self, genome: &Genome) -> f32 {
+        let mut total = 0.0;
+        let mut divisor = 0.0;
+        for i in 0..self.parts.len() {
+            let (weight, ref function) = self.parts[i];
+            if weight > 0.0 {
+                total += weight * function.fitness(genome);
+                divisor += weight;
+            }
+        }
+        if divisor > 0.0 {
+            total / divisor
+        } else {
+            1.0
+        }
+    }
+}
+
+impl<T: ?Sized + FitnessFunction> FitnessFunction for Box<T> {
+    /// Boxed fitness functions score like the functions in them, so the
+    /// fitness function of a population can be picked at run time
+    fn fitness(&self, genome: &Genome) -> f32 {
+        (**self).fitness(genome)
+    }
+}
+
+/// Gives the fitness function used when none is chosen: DistanceFitness with
+/// given metric if there is at least one attraction target, otherwise
+/// AreaFitness
+pub fn default_for(targets: &Vector<Target>, metric: DistanceMetric)
+    -> Box<FitnessFunction> {
+    if targets.iter().any(|target| target.is_attraction()) {
+        Box::new(DistanceFitness::new(metric))
+    } else {
+        Box::new(AreaFitness)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::Arc;
+    use mapping::shapes::Rect;
+    use mapping::shapes::Direction::Left;
+    use mapping::doors::{Door, Doors};
+    use genetics::genes::{Chromosome, Gene, Genome, Target};
+    use mapping::site::Site;
+    use collections::Vector;
+
+    /// Gives the same fitness to every genome
+    struct ConstantFitness(f32);
+
+    impl FitnessFunction for ConstantFitness {
+        fn fitness(&self, _: &Genome) -> f32 {
+            self.0
+        }
+    }
+
+    #[test]
+    fn chromosome_uses_given_fitness_function() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(-2, -2, 5, 5), 0));
+        gene_vec.push(Gene::new(Rect::new(3, -2, 5, 5), 1));
+        let mut weighted = WeightedFitness::new();
+        weighted.add(3.0, Box::new(ConstantFitness(2.0)));
+        weighted.add(1.0, Box::new(ConstantFitness(6.0)));
+        weighted.add(0.0, Box::new(ConstantFitness(100.0)));
+        let chromosome = Chromosome::new_with_fitness(gene_vec, Vector::new(),
+            Arc::new(Site::empty()), Arc::new(weighted));
+        assert_eq!(3.0, chromosome.fitness);
+    }
+
+    #[test]
+    fn area_fitness_stays_finite() {
+        // A lone gene uses up all of its bounding box
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(0, 0, 5, 5), 0));
+        let chromosome = Chromosome::new_with_fitness(gene_vec, Vector::new(),
+            Arc::new(Site::empty()), Arc::new(AreaFitness));
+        assert_eq!(MAX_AREA_FITNESS, chromosome.fitness);
+    }
+
+    #[test]
+    fn distance_fitness_stays_finite() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(0, 0, 5, 5), 0));
+        gene_vec.push(Gene::new(Rect::new(5, 0, 5, 5), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        // Many targets between rooms 5 tiles apart, one of them heavy
+        let mut targets = Vector::new();
+        targets.push(Target::new(from.clone(), to.clone(), 1000.0));
+        for _ in 0..59 {
+            targets.push(Target::new(from.clone(), to.clone(), 1.0));
+        }
+        let chromosome = Chromosome::new(gene_vec, targets);
+        let distance = DistanceFitness::new(DistanceMetric::Euclidean);
+        assert_eq!(MAX_DISTANCE_FITNESS, distance.fitness(&chromosome));
+        let composite = CompositeFitness::new(10.0, 1.0, 1.0,
+                                              DistanceMetric::Euclidean);
+        let fitness = composite.fitness(&chromosome);
+        assert!(fitness > 0.0 && fitness.is_finite());
+    }
+
+    #[test]
+    fn composite_multiplies_weighted_parts() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(-2, -2, 4, 4), 0));
+        gene_vec.push(Gene::new(Rect::new(2, -2, 4, 2), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from, to, 1.0));
+        let chromosome = Chromosome::new(gene_vec, targets);
+        // 24 tiles of genes in a bounding box of 8x4
+        assert_eq!(0.75, UtilizationFitness.fitness(&chromosome));
+        let perimeter = 4.0 * (24.0 as f32).sqrt() / 24.0;
+        assert_eq!(perimeter, PerimeterFitness.fitness(&chromosome));
+        let distance = DistanceFitness::new(DistanceMetric::Euclidean)
+            .fitness(&chromosome);
+        assert_eq!(distance * 0.75 * 0.75,
+                   CompositeFitness::new(1.0, 2.0, 0.0,
+                                         DistanceMetric::Euclidean)
+                       .fitness(&chromosome));
+        assert_eq!(perimeter.powf(0.5),
+                   CompositeFitness::new(0.0, 0.0, 0.5, DistanceMetric::Walking)
+                       .fitness(&chromosome));
+    }
+
+    #[test]
+    fn default_depends_on_attraction_targets() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(-2, -2, 5, 5), 0));
+        gene_vec.push(Gene::new(Rect::new(3, -2, 5, 5), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new_repulsion(from.clone(), to.clone(), 1.0, 1.0));
+        let chromosome = Chromosome::new(gene_vec.clone(), targets.clone());
+        assert_eq!(AreaFitness.fitness(&chromosome),
+                   default_for(&targets, DistanceMetric::Euclidean)
+                       .fitness(&chromosome));
+        targets.push(Target::new(from, to, 1.0));
+        let chromosome = Chromosome::new(gene_vec, targets.clone());
+        // The centers are 5 tiles apart
+        assert_eq!((10000.0 as f32).powf(1.0 / 5.0),
+                   default_for(&targets, DistanceMetric::Euclidean)
+                       .fitness(&chromosome));
+    }
+
+    #[test]
+    fn distances_are_measured_from_fixed_doors() {
+        let mut doors = Doors::new();
+        doors.push(Door::At(Left, 2));
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(0, 0, 6, 6), 0));
+        gene_vec.push(Gene::new(Rect::new(10, 0, 6, 6), 1));
+        gene_vec[1].set_doors(doors);
+        let chromosome = Chromosome::new(gene_vec, Vector::new());
+        let cache = WalkCache::new();
+        // From the center of room 0 at (2, 2) to the door at (10, 2)
+        let mut distances = TravelDistances::new(
+            &chromosome, DistanceMetric::Euclidean, &cache);
+        assert_eq!(8.0, distances.between(0, 1));
+        let mut distances = TravelDistances::new(
+            &chromosome, DistanceMetric::Manhattan, &cache);
+        assert_eq!(8.0, distances.between(0, 1));
+        // Out of the right wall of room 0 at (5, 2) and in through the door
+        let mut distances = TravelDistances::new(
+            &chromosome, DistanceMetric::Walking, &cache);
+        assert_eq!(8.0, distances.between(0, 1));
+        assert_eq!(8.0, distances.between(1, 0));
+    }
+
+    #[test]
+    fn walking_distance_goes_around_rooms() {
+        // Room 1 is a long wall between rooms 0 and 2
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(0, 0, 5, 5), 0));
+        gene_vec.push(Gene::new(Rect::new(5, -10, 5, 25), 1));
+        gene_vec.push(Gene::new(Rect::new(10, 0, 5, 5), 2));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(2);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from, to, 1.0));
+        let chromosome = Chromosome::new(gene_vec, targets);
+        let cache = WalkCache::new();
+        let mut distances = TravelDistances::new(
+            &chromosome, DistanceMetric::Manhattan, &cache);
+        assert_eq!(10.0, distances.between(0, 2));
+        let mut distances = TravelDistances::new(
+            &chromosome, DistanceMetric::Walking, &cache);
+        // Out of the bottom wall of room 0, along the gap below room 1 and
+        // into the bottom wall of room 2
+        assert_eq!(34.0, distances.between(0, 2));
+        assert_eq!(34.0, distances.between(2, 0));
+        assert_eq!(0.0, distances.between(1, 1));
+        assert_eq!(10000.0_f32.powf(1.0 / 34.0),
+                   DistanceFitness::new(DistanceMetric::Walking)
+                       .fitness(&chromosome));
+    }
+
+    #[test]
+    fn walks_are_remembered_by_placement() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(0, 0, 5, 5), 0));
+        gene_vec.push(Gene::new(Rect::new(5, -10, 5, 25), 1));
+        gene_vec.push(Gene::new(Rect::new(10, 0, 5, 5), 2));
+        let chromosome = Chromosome::new(gene_vec.clone(), Vector::new());
+        let cache = WalkCache::new();
+        assert_eq!(34.0, TravelDistances::new(
+            &chromosome, DistanceMetric::Walking, &cache).between(0, 2));
+        {
+            // Change the remembered walk to see that it's used
+            let mut state = cache.state.lock().unwrap();
+            assert_eq!(1, state.walks.len());
+            for (_, distances) in state.walks.iter_mut() {
+                assert_eq!((0, 2, 34.0), distances[0]);
+                distances[0].2 = 99.0;
+            }
+        }
+        assert_eq!(99.0, TravelDistances::new(
+            &chromosome, DistanceMetric::Walking, &cache).between(2, 0));
+        // The same placement on another site is walked again
+        let elsewhere = Chromosome::new_on_site(gene_vec, Vector::new(),
+                                                Arc::new(Site::empty()));
+        assert_eq!(34.0, TravelDistances::new(
+            &elsewhere, DistanceMetric::Walking, &cache).between(0, 2));
+        assert_eq!(1, cache.state.lock().unwrap().walks.len());
+    }
+
+    #[test]
+    fn site_grid_blocks_tiles_outside_bounds() {
+        let mut site = Site::parse("...\n.#.\n...\n").unwrap();
+        site.set_bounds(Rect::new(-1, -1, 5, 3));
+        let grid = SiteGrid::new(&site, 0);
+        assert_eq!(Rect::new(-1, -1, 5, 3), grid.area);
+        assert!(grid.is_blocked(Point::new(0, 0)));
+        assert!(!grid.is_blocked(Point::new(2, 0)));
+        assert!(grid.is_blocked(Point::new(4, 0)));
+        assert!(grid.is_blocked(Point::new(-20, 30)));
+        let grid = SiteGrid::new(&site, 1);
+        assert!(!grid.is_blocked(Point::new(0, 0)));
+        assert!(grid.is_blocked(Point::new(4, 0)));
+        let grid = SiteGrid::new(&Site::empty(), 0);
+        assert!(!grid.is_blocked(Point::new(-20, 30)));
+    }
+}
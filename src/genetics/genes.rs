@@ -3,52 +3,173 @@
 
 use std::cmp::Ordering;
 use std::fmt::{Debug, Formatter, Result};
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::Arc;
 use rand::Rng;
 use rand::distributions::{IndependentSample, Range};
-use mapping::shapes::{Point, Rect, Direction};
+use mapping::shapes::{self, Point, Rect, Direction, Orientation};
 use mapping::shapes::Direction::{Left, Right, Up, Down};
 use mapping::rooms::{Room, Layout};
-use self::Mutation::{RotationMutation, PositionMutation};
+use mapping::doors::Doors;
+use mapping::masks::{self, Shape};
+use mapping::site::Site;
+use self::Mutation::{RotationMutation, FlipMutation, PositionMutation,
+	NudgeMutation, LevelMutation};
+use self::ChromosomeMutation::{GeneMutation, SwapMutation, SlideMutation};
 use collections::Vector;
+use super::config::{GaConfig, MutationWeights};
+use super::fitness::{self, DistanceMetric, FitnessFunction};
 
-// TODO: Get rid of this hardcoding
-/// The chance that, during mating, two genes will be switched
-pub const CROSSOVER_CHANCE: f32 = 0.5;
-/// The chance that, during mutation, a gene will mutate
-pub const MUTATION_CHANCE: f32 = 0.04;
-/// A list of all possible mutation types
-pub const MUTATIONS: [Mutation; 2] = [RotationMutation, PositionMutation];
+/// A list of all possible mutation types. Mutations that can't change a gene
+/// are left out when mutating it (see Genome::can_mutate).
+pub const MUTATIONS: [ChromosomeMutation; 8] = [GeneMutation(RotationMutation),
+	GeneMutation(FlipMutation(Flip::Horizontal)),
+	GeneMutation(FlipMutation(Flip::Vertical)), GeneMutation(PositionMutation),
+	GeneMutation(NudgeMutation), SwapMutation, SlideMutation,
+	GeneMutation(LevelMutation)];
+/// How many times a position mutation tries to find a spot that isn't blocked
+/// by the site
+const POSITION_TRIES: usize = 10;
+/// How many tiles a nudge mutation moves a gene at most along each axis
+pub const NUDGE_DISTANCE: isize = 3;
+/// How much each tile of a room outside the site's bounds reduces fitness
+const OVERFLOW_PENALTY: f32 = 1.0;
+/// How much each (weighted) tile that repelled rooms are too close to each
+/// other reduces fitness
+const SEPARATION_PENALTY: f32 = 1.0;
+/// How much each gene that isn't next to the genes it should be next to
+/// reduces fitness
+const ADJACENCY_PENALTY: f32 = 1.0;
+/// Length of the edge that adjacent genes share. The walls of their rooms face
+/// each other across the gap between rooms for three tiles, so that a door
+/// fits in the middle.
+pub const ADJACENT_CONTACT: isize = 4;
+/// Maximum number of z-levels; the allowed levels of a gene are stored as bits
+pub const MAX_LEVELS: usize = 32;
+/// Distance travelled when moving one z-level up or down the stairs
+pub const LEVEL_DISTANCE: f32 = 1.0;
 
-/// Possible mutation types; just plain old enums
+/// Mutation types that change a gene on its own (see Gene::mutate)
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub enum Mutation {
     RotationMutation,
-    PositionMutation
+    FlipMutation(Flip),
+    /// Moves the gene to a random spot of the allowed area
+    PositionMutation,
+    /// Moves the gene by a few tiles
+    NudgeMutation,
+    LevelMutation
 }
 
-/// Genes are rooms represented only by their bounding rectangle. A chromosome
-/// is made of these.
+/// All mutation types: the ones that change a gene on its own and the ones
+/// that move it with respect to the other genes of the chromosome (see
+/// Chromosome::mutate)
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ChromosomeMutation {
+    GeneMutation(Mutation),
+    /// Swaps the places of the gene and a gene of another room type
+    SwapMutation,
+    /// Slides the gene in a straight line until it's about to collide
+    SlideMutation,
+}
+
+impl Mutation {
+    /// Gives the weight of this mutation type in given weights. The two flips
+    /// share the flip weight.
+    pub fn weight(&self, weights: &MutationWeights) -> f32 {
+    	match *self {
+    		RotationMutation => weights.rotation,
+    		FlipMutation(_) => weights.flip / 2.0,
+    		PositionMutation => weights.position,
+    		NudgeMutation => weights.nudge,
+    		LevelMutation => weights.level,
+    	}
+    }
+}
+
+impl ChromosomeMutation {
+    /// Gives the weight of this mutation type in given weights
+    pub fn weight(&self, weights: &MutationWeights) -> f32 {
+    	match *self {
+    		GeneMutation(mutation) => mutation.weight(weights),
+    		SwapMutation => weights.swap,
+    		SlideMutation => weights.slide,
+    	}
+    }
+}
+
+/// The ways a room can be flipped over
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Flip {
+    /// Left side to the right and right side to the left
+    Horizontal,
+    /// Top side to the bottom and bottom side to the top
+    Vertical,
+}
+
+/// Genes are rooms represented only by their bounding rectangle and z-level.
+/// A chromosome is made of these. The room key tells which type of room the
+/// gene is, and allowed_levels has a bit set for each level the gene may be on.
+/// Pinned genes are never moved, rotated or moved to another level. The doors
+/// are on the walls of the room and turn with it, and so does the shape of the
+/// room inside the rectangle. The orientation tells how the room has been
+/// turned and flipped compared to its blueprint.
 #[derive(PartialEq, Eq, Copy, Clone, Debug)]
 pub struct Gene {
     rect: Rect,
     gene_id: isize,
+    room_key: usize,
+    z: isize,
+    allowed_levels: u32,
+    pinned: bool,
+    doors: Doors,
+    shape: Shape,
+    orientation: Orientation,
+}
+
+/// What a target wants from the distance between its genes
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum TargetKind {
+	/// The genes should be as close to each other as possible
+	Attract,
+	/// The genes should be at least given distance apart
+	Repel(f32),
+	/// Every from-gene must be right next to one of the to-genes
+	Adjacent,
 }
 
 /// Targets are the aims of the program and the measures of fitness; they
-/// determine which genes should be as close to each other as possible.
+/// determine which genes should be as close to each other as possible, or
+/// which ones should be kept apart.
 #[derive(PartialEq, Clone)]
 pub struct Target {
 	pub from_id: Vector<usize>,
 	pub to_id: Vector<usize>,
-	pub weight: f32
+	pub weight: f32,
+	pub kind: TargetKind,
 }
 
 /// Chromosomes are possible solutions. They handle the genetic operations.
-#[derive(PartialEq, Clone)]
-pub struct Chromosome {
+/// A population is parameterized over the type of its fitness function, which
+/// is shared by all of its chromosomes along with the site. The default type
+/// lets the fitness function be picked at run time (see fitness::default_for).
+/// A chromosome dereferences to its genome.
+pub struct Chromosome<F = Box<FitnessFunction>> {
+    genome: Genome,
+    fitness_function: Arc<F>,
+    pub fitness: f32,
+}
+
+/// Genome is the genes of a chromosome along with what they are measured
+/// against: the targets and the site. Fitness functions score genomes, so they
+/// don't depend on the type of fitness function a chromosome has.
+#[derive(Clone, PartialEq)]
+pub struct Genome {
     pub genes: Vector<Gene>, //TODO: Instead of pub, getters / setters?
     pub targets: Vector<Target>,
+    site: Arc<Site>,
     total_area: isize,
-    pub fitness: f32,
     bounding_box: Rect,
     bounding_box_fresh: bool,
 }
@@ -56,6 +177,17 @@ pub struct Chromosome {
 // Implement methods that manipulate the rectangle inside the gene
 impl_rect_methods!(Gene, rect);
 
+impl Hash for Gene {
+    /// Hashes where the gene is and how it's turned. Equal genes are placed
+    /// the same way, so they hash the same.
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.rect.hash(state);
+        self.gene_id.hash(state);
+        self.z.hash(state);
+        self.orientation.hash(state);
+    }
+}
+
 impl PartialOrd for Gene {
     /// Gives an ordering the genes by gene ID
     /// Uses Ord-trait's cmp() to do the comparison
@@ -74,38 +206,189 @@ impl Ord for Gene {
 impl Gene {
 	/// Constructor for gene
 	pub fn new(rect: Rect, gene_id: isize) -> Gene{
-		Gene{ rect: rect, gene_id: gene_id }
+		Gene::new_with_key(rect, gene_id, 0)
 	}
-    /// Mutates the gene: Selects a mutation type randomly and modifies the gene
-    /// accordingly.
-    /// # Panics
-    /// Panics if, for some reason, there's no available choices in MUTATIONS
-    fn mutate<R: Rng>(&mut self, allowed_area: Rect, rng: &mut R) {
-        let allowed_end = allowed_area.bottom_right();
-        let allowed_x = Range::new(allowed_area.x, allowed_end.x);
-        let allowed_y = Range::new(allowed_area.y, allowed_end.y);
-        match rng.choose(&MUTATIONS) {
-            Some(m) => {
-                match *m {
-                    RotationMutation => self.rot_in_place(),
-                    PositionMutation => self.set_center(
-                    	allowed_x.ind_sample(rng), allowed_y.ind_sample(rng))
-                    // TODO: Transition amount by chromosome fitness?
-                    // _ => panic!("Got a mutation type that's not yet implemented!")
-                    // Having a mutation type that's not implemented and still being able to compile
-                    // seems to be impossible. Rust <3
-                }
-            }
-            None => panic!("For some reason the mutation list was empty!"),
-        };
+	/// Constructor for a gene of given room type
+	pub fn new_with_key(rect: Rect, gene_id: isize, room_key: usize) -> Gene {
+		Gene::new_with_levels(rect, gene_id, room_key, 1)
+	}
+	/// Constructor for a gene of given room type that may be on the levels
+	/// whose bits are set in allowed_levels. The gene starts on the lowest
+	/// allowed level.
+	/// # Panics
+	/// Panics if no level is allowed
+	pub fn new_with_levels(rect: Rect, gene_id: isize, room_key: usize,
+		allowed_levels: u32) -> Gene {
+		assert!(allowed_levels != 0, "A gene must be allowed on some level!");
+		Gene {
+			rect: rect,
+			gene_id: gene_id,
+			room_key: room_key,
+			z: allowed_levels.trailing_zeros() as isize,
+			allowed_levels: allowed_levels,
+			pinned: false,
+			doors: Doors::new(),
+			shape: Shape::Rectangle,
+			orientation: Orientation::new(),
+		}
+	}
+	/// Pins or unpins the gene. A pinned gene stays where it is.
+	pub fn set_pinned(&mut self, pinned: bool) {
+		self.pinned = pinned;
+	}
+	/// Checks if the gene is pinned
+	pub fn is_pinned(&self) -> bool {
+		self.pinned
+	}
+	/// Sets the doors of the gene's room, as they are in its current rotation
+	pub fn set_doors(&mut self, doors: Doors) {
+		self.doors = doors;
+	}
+	/// Gives the doors of the gene's room
+	pub fn get_doors(&self) -> Doors {
+		self.doors
+	}
+	/// Sets the shape of the gene's room, as it is in its current rotation
+	pub fn set_shape(&mut self, shape: Shape) {
+		self.shape = shape;
+	}
+	/// Gives the shape of the gene's room
+	pub fn get_shape(&self) -> Shape {
+		self.shape
+	}
+	/// Gives the orientation of the gene's room compared to its blueprint
+	pub fn get_orientation(&self) -> Orientation {
+		self.orientation
+	}
+	/// Checks if the room of this gene collides with the room of the other
+	/// gene: they overlap or have no gap between them. Rooms with masks only
+	/// collide where they have tiles.
+	pub fn collides_with(&self, other: Gene) -> bool {
+		masks::collide(self.rect, &self.shape, other.rect, &other.shape)
+	}
+	/// Finds a blocked tile of the site under the room of this gene
+	fn blocked_tile_on(&self, site: &Site) -> Option<Point> {
+		site.blocked_tile_under(self.room_rect(), &self.shape, self.z)
+	}
+	/// Gives the key of the room type of this gene
+	pub fn get_key(&self) -> usize {
+		self.room_key
+	}
+	/// Gives the z-level of this gene
+	pub fn get_z(&self) -> isize {
+		self.z
+	}
+	/// Moves the gene to given z-level
+	/// # Panics
+	/// Panics if the gene isn't allowed on that level
+	pub fn set_z(&mut self, z: isize) {
+		assert!(self.is_allowed_level(z), "Level {} isn't allowed!", z);
+		self.z = z;
+	}
+	/// Checks if the gene may be on given z-level
+	pub fn is_allowed_level(&self, z: isize) -> bool {
+		z >= 0 && (z as usize) < MAX_LEVELS && self.allowed_levels & (1 << z) != 0
+	}
+	/// Moves the gene to a random one of its allowed levels
+	fn random_level<R: Rng>(&mut self, rng: &mut R) {
+		let count = self.allowed_levels.count_ones();
+		let mut nth = Range::new(0, count).ind_sample(rng);
+		for z in 0..MAX_LEVELS as isize {
+			if self.is_allowed_level(z) {
+				if nth == 0 {
+					self.z = z;
+					return;
+				}
+				nth -= 1;
+			}
+		}
+	}
+    /// Mutates the gene with given mutation type. A rotation mutation turns a
+    /// room with a mask by one to three quarter turns. A position mutation
+    /// tries a few positions to find one where the gene is inside the site's
+    /// bounds and not on blocked tiles. A nudge moves the gene by at most
+    /// NUDGE_DISTANCE tiles along each axis.
+    fn mutate<R: Rng>(&mut self, mutation: &Mutation, allowed_area: Rect,
+    	site: &Site, rng: &mut R) {
+        match *mutation {
+            RotationMutation if self.shape.is_rectangle() =>
+            	self.rot_in_place(),
+            RotationMutation => {
+            	for _ in 0..Range::new(1, 4).ind_sample(rng) {
+            		self.rot_in_place();
+            	}
+            },
+            FlipMutation(flip) => self.flip_in_place(flip),
+            PositionMutation => {
+            	let allowed_end = allowed_area.bottom_right();
+            	let allowed_x = Range::new(allowed_area.x, allowed_end.x);
+            	let allowed_y = Range::new(allowed_area.y, allowed_end.y);
+            	for _ in 0..POSITION_TRIES {
+            		self.set_center(allowed_x.ind_sample(rng),
+            			allowed_y.ind_sample(rng));
+            		if site.is_inside(self.room_rect()) &&
+            		   self.blocked_tile_on(site).is_none() {
+            			break;
+            		}
+            	}
+            },
+            NudgeMutation => {
+            	let step = Range::new(-NUDGE_DISTANCE, NUDGE_DISTANCE + 1);
+            	let (mut dx, mut dy) = (0, 0);
+            	while dx == 0 && dy == 0 {
+            		dx = step.ind_sample(rng);
+            		dy = step.ind_sample(rng);
+            	}
+            	let (x, y) = (self.get_x() + dx, self.get_y() + dy);
+            	self.set_x(x);
+            	self.set_y(y);
+            },
+            LevelMutation => self.random_level(rng),
+        }
+    }
+    /// Checks if given mutation can change the gene: flips only change genes
+    /// whose rooms look different flipped over, because of their masks or
+    /// doors, and level mutations genes that may be on several levels
+    fn can_mutate(&self, mutation: &Mutation) -> bool {
+    	match *mutation {
+    		FlipMutation(flip) => {
+    			let mut flipped = *self;
+    			flipped.flip_in_place(flip);
+    			flipped.shape != self.shape || flipped.doors != self.doors
+    		},
+    		LevelMutation => self.allowed_levels.count_ones() > 1,
+    		_ => true,
+    	}
     }
     /// Rotates the gene (switches it's rectangles width with its height)
     /// Returns a new, rotated gene
     fn rotate(&self) -> Gene {
-        let new_rect = self.rect.rotate();
-        Gene {
-            rect: new_rect,
-            gene_id: self.gene_id,
+        let mut gene = *self;
+        gene.rot_in_place();
+        gene
+    }
+    /// Rotates the gene in place: switches its rectangle's width with its
+    /// height and turns the doors and the shape clockwise with the room
+    pub fn rot_in_place(&mut self) {
+        self.doors = self.doors.rotate(self.room_rect().h);
+        self.shape = self.shape.rotate();
+        self.orientation = self.orientation.rotate();
+        self.rect = self.rect.rotate();
+    }
+    /// Mirrors the room of the gene from left to right in place, along with
+    /// its doors
+    pub fn mirror_in_place(&mut self) {
+        self.doors = self.doors.mirror(self.room_rect().w);
+        self.shape = self.shape.mirror();
+        self.orientation = self.orientation.mirror();
+    }
+    /// Flips the room of the gene over in place. A vertical flip is a mirror
+    /// image turned upside down.
+    pub fn flip_in_place(&mut self, flip: Flip) {
+        self.mirror_in_place();
+        if flip == Flip::Vertical {
+            self.rot_in_place();
+            self.rot_in_place();
         }
     }
     /// Creates a new gene that's a copy of this one but with a differing
@@ -117,34 +400,108 @@ impl Gene {
             w: self.rect.w,
             h: self.rect.h,
         };
-        Gene {
-            rect: new_rect,
-            gene_id: self.gene_id,
-        }
+        Gene { rect: new_rect, ..*self }
     }
-    /// Converts the gene into a room (shrinks it down a bit)
-    pub fn as_room(&self) -> Room {
+    /// Gives the rectangle of the room this gene stands for (the gene without
+    /// the gap between rooms)
+    pub fn room_rect(&self) -> Rect {
     	let mut new_rect = self.rect;
     	new_rect.w -= 1;
     	new_rect.h -= 1;
-    	Room::new(new_rect)
+    	new_rect
+    }
+    /// Checks if the room of this gene is right next to the room of the other
+    /// gene on the same level (see ADJACENT_CONTACT). Masked rooms must touch
+    /// with their tiles, not only with their bounding rectangles.
+    pub fn is_adjacent_to(&self, other: &Gene) -> bool {
+    	self.z == other.z &&
+    		masks::contact_length(self.rect, &self.shape, other.rect,
+    		                      &other.shape) >= ADJACENT_CONTACT
+    }
+    /// Gives the points that distances to the gene are measured from: the
+    /// entrances of its room (see Doors::entrances)
+    pub fn entrances(&self) -> Vector<Point> {
+    	self.doors.entrances(self.room_rect())
+    }
+    /// Converts the gene into a room (shrinks it down a bit)
+    pub fn as_room(&self) -> Room {
+    	let mut room = Room::new_on_level(self.room_rect(), self.room_key, self.z);
+    	room.set_pinned(self.pinned);
+    	room.set_doors(self.doors);
+    	room.set_shape(self.shape);
+    	room.set_orientation(self.orientation);
+    	room
     }
 }
 
 impl Target {
+	/// Creates a target that pulls the genes together
 	pub fn new(from: Vector<usize>, to: Vector<usize>, weight: f32) -> Target {
-		Target { from_id: from, to_id: to, weight: weight }
+		Target { from_id: from, to_id: to, weight: weight,
+			kind: TargetKind::Attract }
+	}
+	/// Creates a target that keeps the genes at least min_distance apart
+	pub fn new_repulsion(from: Vector<usize>, to: Vector<usize>, weight: f32,
+		min_distance: f32) -> Target {
+		Target { from_id: from, to_id: to, weight: weight,
+			kind: TargetKind::Repel(min_distance) }
+	}
+	/// Creates a target that requires every from-gene to be next to one of the
+	/// to-genes. The genes are also pulled together like by an attraction.
+	pub fn new_adjacency(from: Vector<usize>, to: Vector<usize>, weight: f32)
+		-> Target {
+		Target { from_id: from, to_id: to, weight: weight,
+			kind: TargetKind::Adjacent }
+	}
+	/// Checks if the target pulls its genes together
+	pub fn is_attraction(&self) -> bool {
+		match self.kind {
+			TargetKind::Repel(_) => false,
+			_ => true,
+		}
 	}
 }
 
-impl PartialOrd for Chromosome {
+impl<F> Clone for Chromosome<F> {
+	/// Clones the genome and shares the fitness function
+	fn clone(&self) -> Self {
+		Chromosome {
+			genome: self.genome.clone(),
+			fitness_function: self.fitness_function.clone(),
+			fitness: self.fitness,
+		}
+	}
+}
+
+impl<F> Deref for Chromosome<F> {
+	type Target = Genome;
+	fn deref(&self) -> &Genome {
+		&self.genome
+	}
+}
+
+impl<F> DerefMut for Chromosome<F> {
+	fn deref_mut(&mut self) -> &mut Genome {
+		&mut self.genome
+	}
+}
+
+impl<F> PartialOrd for Chromosome<F> {
 	/// Partial comparison to other chromosome based on chromosome's fitness
-    fn partial_cmp(&self, other: &Chromosome) -> Option<Ordering> {
+    fn partial_cmp(&self, other: &Chromosome<F>) -> Option<Ordering> {
         self.fitness.partial_cmp(&other.fitness)
     }
 }
 
-impl Debug for Chromosome {
+impl<F> PartialEq for Chromosome<F> {
+	/// Chromosomes are equal if their genomes and fitnesses are equal; fitness
+	/// functions can't be compared
+	fn eq(&self, other: &Chromosome<F>) -> bool {
+		self.genome == other.genome && self.fitness == other.fitness
+	}
+}
+
+impl<F> Debug for Chromosome<F> {
 	/// Debug output formatting for chromosome
 	fn fmt(&self, f: &mut Formatter) -> Result {
 		write!(f, "Chromosome with fitness {}, bounding box area {}, \
@@ -153,18 +510,37 @@ impl Debug for Chromosome {
 }
 
 impl Chromosome {
-    /// A constructor for the chromosome. Creates the chromosome and relaxes
-    /// it.
+    /// A constructor for the chromosome. Creates the chromosome on an empty
+    /// site with the default fitness function (see fitness::default_for) and
+    /// relaxes it.
     pub fn new(genes: Vector<Gene>, targets: Vector<Target>) -> Chromosome {
+        Chromosome::new_on_site(genes, targets, Arc::new(Site::empty()))
+    }
+    /// Creates the chromosome on given site with the default fitness function
+    /// and relaxes it
+    pub fn new_on_site(genes: Vector<Gene>, targets: Vector<Target>,
+        site: Arc<Site>) -> Chromosome {
+        let fitness_function = fitness::default_for(&targets,
+                                                    DistanceMetric::Euclidean);
+        Chromosome::new_with_fitness(genes, targets, site,
+            Arc::new(fitness_function))
+    }
+}
+
+impl<F: FitnessFunction> Chromosome<F> {
+    /// Creates the chromosome on given site, scored by given fitness function,
+    /// and relaxes it
+    pub fn new_with_fitness(genes: Vector<Gene>, targets: Vector<Target>,
+        site: Arc<Site>, fitness_function: Arc<F>) -> Chromosome<F> {
         let mut total_area = 0;
         for i in 0..genes.len() {
             total_area += genes[i].area();
         }
-        let mut new_chromosome = Chromosome {
+        let genome = Genome {
             genes: genes,
             targets: targets,
+            site: site,
             total_area: total_area,
-            fitness: 0.0,
             bounding_box: Rect {
                 x: 0,
                 y: 0,
@@ -173,13 +549,22 @@ impl Chromosome {
             },
             bounding_box_fresh: false,
         };
+        let mut new_chromosome = Chromosome {
+            genome: genome,
+            fitness_function: fitness_function,
+            fitness: 0.0,
+        };
         new_chromosome.relax();
         new_chromosome
     }
     /// Generates a more randomized (and perhaps more valid) initial chromosome
-    /// from given genes.
+    /// from given genes on given site, scored by given fitness function.
+    /// Pinned genes are left where they are, and places where a gene would be
+    /// on blocked tiles, over the stair shaft or outside the site's bounds are
+    /// skipped while there are other places to go.
     pub fn generate_initial<R: Rng>(genes: Vector<Gene>, targets: Vector<Target>,
-    	rng: &mut R) -> Chromosome {
+    	site: Arc<Site>, fitness_function: Arc<F>, rng: &mut R)
+    	-> Chromosome<F> {
         let mut shuffled_genes = genes.clone();
         shuffled_genes[0].set_center(0, 0);
         rng.shuffle(&mut shuffled_genes[1..]);
@@ -193,14 +578,33 @@ impl Chromosome {
         places_to_go.push((bottom_right_0.x, top_left_0.y, Right));
         places_to_go.push((top_left_0.x, bottom_right_0.y, Down));
         rng.shuffle(&mut places_to_go);
+        // The stair shaft goes through the center of gene 0 on every level
+        let shaft_center = shuffled_genes[0].room_rect().center();
+        let shaft = Rect::new(shaft_center.x, shaft_center.y, 1, 1);
         for i in 1..shuffled_genes.len() {
-            let place = places_to_go.remove(0); //TODO: Create an efficient queue
-            let mut x = place.0;
-            let mut y = place.1;
+            if shuffled_genes[i].pinned {
+                continue;
+            }
             if rng.next_f32() > 0.5 {
                 // 50% chance to rotate
                 shuffled_genes[i].rot_in_place();
             }
+            if shuffled_genes[i].allowed_levels.count_ones() > 1 {
+                shuffled_genes[i].random_level(rng);
+            }
+            let mut place = places_to_go.remove(0); //TODO: Create an efficient queue
+            while places_to_go.len() > 0 {
+                let rect = room_rect_in_place(&shuffled_genes[i], place);
+                let over_shaft = shuffled_genes[i].z != shuffled_genes[0].z &&
+                                 rect.collides_with(shaft);
+                if site.is_inside(rect) && !over_shaft &&
+                   site.blocked_tile_in(rect, shuffled_genes[i].z).is_none() {
+                    break;
+                }
+                place = places_to_go.remove(0);
+            }
+            let mut x = place.0;
+            let mut y = place.1;
             let x_variance = shuffled_genes[i].get_w() / 2;
             let y_variance = shuffled_genes[i].get_h() / 2;
             let x_var_range = Range::new(-x_variance, x_variance);
@@ -240,24 +644,156 @@ impl Chromosome {
         }
         shuffled_genes.sort();
 //        shuffled_genes.insert(0, genes[0].set_pos(0, 0));
-        Chromosome::new(shuffled_genes, targets)
+        Chromosome::new_with_fitness(shuffled_genes, targets, site,
+            fitness_function)
+    }
+    /// Returns the fitness function the chromosome is scored by
+    pub fn get_fitness_function(&self) -> Arc<F> {
+        self.fitness_function.clone()
+    }
+    /// Relaxes the genome (see Genome::relax) and recalculates the fitness
+    fn relax(&mut self) {
+        self.genome.relax();
+        self.calculate_fitness();
+    }
+    /// Calculates the fitness of the chromosome with its fitness function.
+    /// Chromosomes with repelled rooms too close to each other, rooms that
+    /// aren't next to the rooms they should be next to or rooms outside the
+    /// site's bounds are penalized.
+    pub fn calculate_fitness(&mut self) {
+    	if !self.bounding_box_fresh {
+    		self.genome.calculate_bounding_box();
+    	}
+    	self.fitness = self.fitness_function.fitness(&self.genome);
+    	let shortfall = self.separation_shortfall();
+    	if shortfall > 0.0 {
+    		self.fitness /= 1.0 + SEPARATION_PENALTY * shortfall;
+    	}
+    	let unmet = self.unmet_adjacencies().len();
+    	if unmet > 0 {
+    		self.fitness /= 1.0 + ADJACENCY_PENALTY * unmet as f32;
+    	}
+    	let overflow = self.overflow();
+    	if overflow > 0 {
+    		self.fitness /= 1.0 + OVERFLOW_PENALTY * overflow as f32;
+    	}
+    }
+    /// The mating function: Two children are created by swapping this
+    /// chromosomes genes with the partner chromosome's genes (aka. crossover).
+    /// The probability of a swap happening per gene is equal to the config's
+    /// crossover_chance
+    /// # Panics
+    /// Panics if trying to mate two genes of different lengths!
+    /// (Doing that is contrary to both natural evolution AND the word of God!)
+    pub fn mate<R: Rng>(&self, partner: &Chromosome<F>, config: &GaConfig,
+    	rng: &mut R) -> (Chromosome<F>, Chromosome<F>) {
+        if self.genes.len() != partner.genes.len() {
+            panic!("Tried to mate chromosomes with different lengths!
+      Shame on you!");
+        }
+        let mut my_childs_genes: Vector<Gene> = Vector::new();
+        let mut partners_childs_genes: Vector<Gene> = Vector::new();
+        for i in 0..self.genes.len() {
+            // Pinned genes are the same in both parents, so there's no use
+            // swapping them
+            if rng.next_f32() < config.crossover_chance &&
+               !self.genes[i].pinned {
+                partners_childs_genes.push(self.genes[i]);
+                my_childs_genes.push(partner.genes[i]);
+            } else {
+                my_childs_genes.push(self.genes[i]);
+                partners_childs_genes.push(partner.genes[i]);
+            }
+        }
+        let my_child = Chromosome::new_with_fitness(my_childs_genes,
+            self.targets.clone(), self.site.clone(),
+            self.fitness_function.clone());
+        let partners_child = Chromosome::new_with_fitness(partners_childs_genes,
+            self.targets.clone(), self.site.clone(),
+            self.fitness_function.clone());
+        // 		my_child.relax();
+        // 		partners_child.relax();
+        (my_child, partners_child)
     }
-    /// Relaxes the chromosome: moves every gene so that no two genes collide.
-    /// Recalculates fitness when done.
+    /// Mutates the chromosome: Mutates each gene that isn't pinned with
+    /// probability equal to the config's mutation_chance, choosing the
+    /// mutation type by the config's mutation_weights. Relaxes the genes at
+    /// the end.
+    pub fn mutate<R: Rng>(&mut self, config: &GaConfig, rng: &mut R) {
+        self.genome.mutate(config, rng);
+        self.relax();
+    }
+    /// Converts the chromosome into a layout; converts all the genes into rooms
+    /// and returns a new layout with the chromosome's targets and fitness
+    pub fn as_layout(&self) -> Layout {
+    	let mut rooms = Vector::new();
+    	for i in 0..self.genes.len() {
+    		rooms.push(self.genes[i].as_room());
+    	}
+    	let mut layout = Layout::new(rooms, self.targets.clone());
+    	layout.set_fitness(self.fitness);
+    	layout.set_site(self.site.clone());
+    	layout
+    }
+}
+
+impl Genome {
+    /// Returns the site the genome is on
+    pub fn get_site(&self) -> Arc<Site> {
+        self.site.clone()
+    }
+    /// Gives the total area of the genes
+    pub fn get_total_area(&self) -> isize {
+        self.total_area
+    }
+    /// Gives the smallest bounding box of the genes. It's up to date when the
+    /// fitness is calculated.
+    pub fn get_bounding_box(&self) -> Rect {
+        self.bounding_box
+    }
+    /// Relaxes the genome: moves every gene so that no two genes on the
+    /// same level collide and no room is on blocked tiles of the site or over
+    /// the stair shaft (see obstacle_under). Gene 0
+    /// and pinned genes stay in place and the other genes are moved around
+    /// them. A move that keeps the gene inside the site's bounds is preferred.
+    /// Genes with masks only collide where their rooms have tiles, so they can
+    /// be left in each other's empty corners.
     fn relax(&mut self) {
-    	//Leave gene 0 as first gene
-        self.genes[1..].sort_by(|a, b| a.origo_cmp(b));
+    	//Leave gene 0 as first gene, followed by the pinned genes
+        self.genes[1..].sort_by(|a, b| match b.pinned.cmp(&a.pinned) {
+        	Ordering::Equal => a.origo_cmp(b),
+        	order => order,
+        });
         for i in 1..self.genes.len() {
+        	if self.genes[i].pinned {
+        		continue;
+        	}
         	let mut j = 0;
         	let center_i = self.genes[i].center();
         	let top_left_i = self.genes[i].top_left();
         	let bottom_right_i = self.genes[i].bottom_right();
         	let x_dir = sign(center_i.x);
         	let y_dir = sign(center_i.y);
-            while j < i {
-                if self.genes[i].collides_with(self.genes[j]) {
-                    let top_left_j = self.genes[j].top_left();
-                    let bottom_right_j = self.genes[j].bottom_right();
+            loop {
+                let obstacle = if j < i {
+                    if self.genes[i].z == self.genes[j].z &&
+                       self.genes[i].collides_with(self.genes[j]) {
+                        Some(self.genes[j].rect)
+                    } else {
+                        j += 1;
+                        continue;
+                    }
+                } else {
+                    // Blocked tiles and the stair shaft are obstacles of one
+                    // tile
+                    match self.obstacle_under(&self.genes[i]) {
+                        Some(tile) => Some(Rect::new(tile.x, tile.y, 1, 1)),
+                        None => None,
+                    }
+                };
+                if let Some(obstacle) = obstacle {
+                    let top_left_j = obstacle.top_left();
+                    let bottom_right_j = obstacle.bottom_right();
                     let mut diff = Point::new(0, 0);
                     if x_dir < 0 {
                     	diff.x = top_left_j.x - bottom_right_i.x;
@@ -269,24 +805,143 @@ impl Chromosome {
                     } else {
                     	diff.y = bottom_right_j.y - top_left_i.y;
                     }
-                    if diff.x.abs() < diff.y.abs() {
-                        let new_x = self.genes[i].get_x() + diff.x;
-                        self.genes[i].set_x(new_x);
+                    let mut moved_x = self.genes[i];
+                    moved_x.set_x(self.genes[i].get_x() + diff.x);
+                    let mut moved_y = self.genes[i];
+                    moved_y.set_y(self.genes[i].get_y() + diff.y);
+                    let x_fits = self.site.is_inside(moved_x.room_rect());
+                    let y_fits = self.site.is_inside(moved_y.room_rect());
+                    self.genes[i] = if x_fits != y_fits {
+                        if x_fits { moved_x } else { moved_y }
+                    } else if diff.x.abs() < diff.y.abs() {
+                        moved_x
                     } else {
-                        let new_y = self.genes[i].get_y() + diff.y;
-                        self.genes[i].set_y(new_y);
-                    }
+                        moved_y
+                    };
                     j = 0;
                 } else {
-	                j += 1;
+                    break;
                 }
             }
         }
         self.genes.sort();
+        self.repair_adjacencies();
         self.bounding_box_fresh = false;
-        self.calculate_fitness();
     }
-    /// Calculates the smallest bounding box for this chromosome's genes
+    /// Repairs the adjacency targets after relaxing: every gene that isn't
+    /// next to any of the genes it should be next to is moved to the closest
+    /// free spot beside one of them. If the gene can't be moved (it's gene 0
+    /// or pinned), one of the other genes is moved next to it instead. Genes
+    /// that don't have a free spot are left where they are.
+    fn repair_adjacencies(&mut self) {
+    	let unmet = self.unmet_adjacencies();
+    	for k in 0..unmet.len() {
+    		let (t, from) = unmet[k];
+    		// An earlier move may have already repaired this one
+    		let met = self.targets[t].to_id.iter().any(|&to| {
+    			to != from && self.genes[from].is_adjacent_to(&self.genes[to])
+    		});
+    		if met {
+    			continue;
+    		}
+    		let mut best: Option<(f32, usize, Gene)> = None;
+    		for j in 0..self.targets[t].to_id.len() {
+    			let to = self.targets[t].to_id[j];
+    			let (moved, fixed) = if to == from {
+    				continue;
+    			} else if self.is_movable(from) {
+    				(from, to)
+    			} else if self.is_movable(to) {
+    				(to, from)
+    			} else {
+    				continue;
+    			};
+    			if let Some(gene) = self.spot_beside(moved, fixed) {
+    				let distance = gene.dist(self.genes[moved]);
+    				if best.map_or(true, |b| distance < b.0) {
+    					best = Some((distance, moved, gene));
+    				}
+    			}
+    		}
+    		if let Some((_, moved, gene)) = best {
+    			self.genes[moved] = gene;
+    		}
+    	}
+    }
+    /// Checks if the gene with given id may be moved by the repairs
+    fn is_movable(&self, id: usize) -> bool {
+    	id != 0 && !self.genes[id].pinned
+    }
+    /// Finds the spot closest to the current position of gene `moved` where it
+    /// is right next to gene `fixed` and doesn't collide with other genes, go
+    /// outside the site's bounds or stand on blocked tiles. Both orientations
+    /// of the gene are tried. Returns the gene moved to that spot.
+    fn spot_beside(&self, moved: usize, fixed: usize) -> Option<Gene> {
+    	let current = self.genes[moved].center();
+    	let rect = self.genes[fixed].rect;
+    	let mut gene = self.genes[moved];
+    	if !gene.is_allowed_level(self.genes[fixed].z) {
+    		return None;
+    	}
+    	gene.z = self.genes[fixed].z;
+    	let mut best: Option<(f32, Gene)> = None;
+    	for _ in 0..2 {
+    		let (w, h) = (gene.get_w(), gene.get_h());
+    		let mut spots = Vector::new();
+    		for y in rect.y - h + ADJACENT_CONTACT..rect.y + rect.h - ADJACENT_CONTACT + 1 {
+    			spots.push(Point::new(rect.x - w, y));
+    			spots.push(Point::new(rect.x + rect.w, y));
+    		}
+    		for x in rect.x - w + ADJACENT_CONTACT..rect.x + rect.w - ADJACENT_CONTACT + 1 {
+    			spots.push(Point::new(x, rect.y - h));
+    			spots.push(Point::new(x, rect.y + rect.h));
+    		}
+    		for i in 0..spots.len() {
+    			gene.set_x(spots[i].x);
+    			gene.set_y(spots[i].y);
+    			let distance = current.dist(gene.center());
+    			if best.map_or(true, |b| distance < b.0) &&
+    			   self.is_free_spot(&gene, moved) {
+    				best = Some((distance, gene));
+    			}
+    		}
+    		gene.rot_in_place();
+    	}
+    	best.map(|b| b.1)
+    }
+    /// Finds a tile under the room of given gene that the room can't be on: a
+    /// blocked tile of the site or, on the other levels than gene 0's, the
+    /// stair shaft in the center of the room of gene 0
+    fn obstacle_under(&self, gene: &Gene) -> Option<Point> {
+    	if let Some(tile) = gene.blocked_tile_on(&self.site) {
+    		return Some(tile);
+    	}
+    	let shaft = self.genes[0].room_rect().center();
+    	if gene.z != self.genes[0].z &&
+    	   gene.shape.contains(gene.room_rect(), shaft) {
+    		Some(shaft)
+    	} else {
+    		None
+    	}
+    }
+    /// Checks if given gene could take the place of the gene with given id:
+    /// it doesn't collide with the other genes on its level, go outside the
+    /// site's bounds or stand on blocked tiles or the stair shaft
+    fn is_free_spot(&self, gene: &Gene, id: usize) -> bool {
+    	let room_rect = gene.room_rect();
+    	if !self.site.is_inside(room_rect) ||
+    	   self.obstacle_under(gene).is_some() {
+    		return false;
+    	}
+    	for i in 0..self.genes.len() {
+    		if i != id && self.genes[i].z == gene.z &&
+    		   gene.collides_with(self.genes[i]) {
+    			return false;
+    		}
+    	}
+    	true
+    }
+    /// Calculates the smallest bounding box for the genes
     fn calculate_bounding_box(&mut self) {
         // TODO:Store this in struct, make
         let mut min_x = isize::max_value(); //this update as part of other fns
@@ -317,105 +972,226 @@ impl Chromosome {
         };
         self.bounding_box_fresh = true;
     }
-    /// Calculates the fitness of the chromosome. Uses calculate_distance_fitness
-    /// if there is at least one target, otherwise uses calculate_area_fitness
-    pub fn calculate_fitness(&mut self) {
-    	if self.targets.len() == 0 {
-    		self.calculate_area_fitness();
-    		println!("Whoops!");
-    	} else {
-    		self.calculate_distance_fitness();
+    /// Gives the number of room tiles outside the site's bounds. A chromosome
+    /// is only feasible if this is 0.
+    pub fn overflow(&self) -> isize {
+    	let mut overflow = 0;
+    	for i in 0..self.genes.len() {
+    		overflow += self.site.overflow(self.genes[i].room_rect());
     	}
+    	overflow
+    }
+    /// Gives the genes of adjacency targets that aren't next to any of the
+    /// genes they should be next to, as (target index, gene id) pairs
+    pub fn unmet_adjacencies(&self) -> Vector<(usize, usize)> {
+    	find_unmet_adjacencies(&self.targets,
+    		|from, to| self.genes[from].is_adjacent_to(&self.genes[to]))
     }
-    /// Calculates the fitness of the chromosome based on the weighted average
-    /// of distances specified by targets.
-    pub fn calculate_distance_fitness(&mut self) {
-    	let mut total_dist = 0.0;
-    	let mut divisor = 0.0;
+    /// Gives how much too close to each other the genes of repulsion targets
+    /// are: the average shortfall from the minimum distance of each target,
+    /// weighted by the target's weight and summed up. A gene is never too close
+    /// to itself.
+    pub fn separation_shortfall(&self) -> f32 {
+    	let mut total = 0.0;
     	for t in 0..self.targets.len() {
-    		let mut target_dist = 0.0;
+    		let min_distance = match self.targets[t].kind {
+    			TargetKind::Repel(min_distance) => min_distance,
+    			_ => continue,
+    		};
+    		let mut shortfall = 0.0;
     		let mut n = 0;
     		for i in 0..self.targets[t].from_id.len() {
     			for j in 0..self.targets[t].to_id.len() {
     				let from = self.targets[t].from_id[i];
     				let to = self.targets[t].to_id[j];
-    				target_dist += self.genes[from].dist(self.genes[to]);
+    				if from == to {
+    					continue;
+    				}
+    				let distance = self.travel_distance(from, to);
+    				shortfall += (min_distance - distance).max(0.0);
     				n += 1;
     			}
     		}
-    		target_dist *= self.targets[t].weight;
-    		target_dist /= n as f32;
-    		divisor += self.targets[t].weight;
-    		total_dist += target_dist;
+    		if n > 0 {
+    			total += self.targets[t].weight * shortfall / n as f32;
+    		}
     	}
-    	// TODO: Guard against division by zero
-    	let weighted_average = total_dist / divisor;
-    	self.fitness = (10000.0 as f32).powf(self.targets.len() as f32 / weighted_average);
-    }
-    /// Calculates this chromosome's fitness. Does this by comparing
-    /// area  used up by the genes to the area of the minimum bounding box
-    /// (so compact, rectangular designs flourish at the moment).
-    /// Calls calculate_bounding_box in the beginning if the bounding box is not
-    /// fresh.
-    pub fn calculate_area_fitness(&mut self) {
-        // TODO: Actual fitness calculation
-        if !self.bounding_box_fresh {
-            self.calculate_bounding_box();
-        }
-        let raw_fitness = self.total_area as f32 / self.bounding_box.area() as f32;
-        self.fitness = 1.0 / raw_fitness.log10().abs(); //TODO: What if raw_fitness = 1?
+    	total
     }
-    /// The mating function: Two children are created by swapping this
-    /// chromosomes genes with the partner chromosome's genes (aka. crossover).
-    /// The probability of a swap happening per gene is equal to
-    /// CROSSOVER_CHANCE
-    /// # Panics
-    /// Panics if trying to mate two genes of different lengths!
-    /// (Doing that is contrary to both natural evolution AND the word of God!)
-    pub fn mate<R: Rng>(&self, partner: &Chromosome, rng: &mut R) -> (Chromosome, Chromosome) {
-        if self.genes.len() != partner.genes.len() {
-            panic!("Tried to mate chromosomes with different lengths!
-      Shame on you!");
-        }
-        let mut my_childs_genes: Vector<Gene> = Vector::new();
-        let mut partners_childs_genes: Vector<Gene> = Vector::new();
-        for i in 0..self.genes.len() {
-            if rng.next_f32() < CROSSOVER_CHANCE {
-                partners_childs_genes.push(self.genes[i]);
-                my_childs_genes.push(partner.genes[i]);
-            } else {
-                my_childs_genes.push(self.genes[i]);
-                partners_childs_genes.push(partner.genes[i]);
-            }
-        }
-        let my_child = Chromosome::new(my_childs_genes, self.targets.clone());
-        let partners_child = Chromosome::new(partners_childs_genes, self.targets.clone());
-        // 		my_child.relax();
-        // 		partners_child.relax();
-        (my_child, partners_child)
+    /// Gives the straight line distance travelled between the nearest
+    /// entrances of the genes with given ids (see Gene::entrances). Genes on
+    /// different levels are connected by the stair shaft at the center of the
+    /// room of gene 0.
+    pub fn travel_distance(&self, from: usize, to: usize) -> f32 {
+    	let from_entrances = self.genes[from].entrances();
+    	let to_entrances = self.genes[to].entrances();
+    	if self.genes[from].z == self.genes[to].z {
+    		return shapes::nearest_dist(&from_entrances, &to_entrances);
+    	}
+    	let stairs = [self.genes[0].room_rect().center()];
+    	let levels = (self.genes[from].z - self.genes[to].z).abs() as f32;
+    	shapes::nearest_dist(&from_entrances, &stairs) +
+    		levels * LEVEL_DISTANCE + shapes::nearest_dist(&stairs, &to_entrances)
     }
-    /// Mutates the chromosome: Calls Gene::mutate for each gene with
-    /// probability equal to MUTATION_CHANCE. Relaxes the gene at the end.
-    pub fn mutate<R: Rng>(&mut self, rng: &mut R) {
+    /// Mutates each gene that isn't pinned with probability equal to the
+    /// config's mutation_chance, choosing the mutation type by the config's
+    /// mutation_weights. The genes are left unrelaxed.
+    fn mutate<R: Rng>(&mut self, config: &GaConfig, rng: &mut R) {
         if !self.bounding_box_fresh {
             self.calculate_bounding_box();
         }
+        // Genes move around the bounds if there are any, otherwise around the
+        // current bounding box
+        let allowed_area = self.site.get_bounds().unwrap_or(self.bounding_box);
         for i in 1..self.genes.len() {
-            if rng.next_f32() < MUTATION_CHANCE {
-                self.genes[i].mutate(self.bounding_box, rng);
+            if rng.next_f32() >= config.mutation_chance || self.genes[i].pinned {
+            	continue;
+            }
+            match self.choose_mutation(i, &config.mutation_weights, rng) {
+            	Some(GeneMutation(mutation)) => self.genes[i].mutate(&mutation,
+            		allowed_area, &self.site, rng),
+            	Some(SwapMutation) => self.swap(i, rng),
+            	Some(SlideMutation) => {
+            		let mut directions = [Left, Right, Up, Down];
+            		rng.shuffle(&mut directions);
+            		// Slide towards the first thing there is to slide against
+            		for d in 0..directions.len() {
+            			if let Some(gene) = self.slide(i, directions[d],
+            				allowed_area) {
+            				self.genes[i] = gene;
+            				break;
+            			}
+            		}
+            	},
+            	None => {},
             }
         }
-        self.relax();
     }
-    /// Converts the chromosome into a layout; converts all the genes into rooms
-    /// and returns a new layout
-    pub fn as_layout(&self) -> Layout {
-    	let mut rooms = Vector::new();
+    /// Chooses a random mutation type for the gene with given id, with
+    /// chances in proportion to the weights. Leaves out the types that can't
+    /// change the gene. Returns None if none of the types with weight can.
+    fn choose_mutation<R: Rng>(&self, id: usize, weights: &MutationWeights,
+    	rng: &mut R) -> Option<ChromosomeMutation> {
+    	let mut total = 0.0;
+    	let mut choices = Vector::new();
+    	for mutation in MUTATIONS.iter() {
+    		let weight = mutation.weight(weights);
+    		if weight > 0.0 && self.can_mutate(id, mutation) {
+    			total += weight;
+    			choices.push((weight, *mutation));
+    		}
+    	}
+    	let mut left = rng.next_f32() * total;
+    	for i in 0..choices.len() {
+    		if left < choices[i].0 {
+    			return Some(choices[i].1);
+    		}
+    		left -= choices[i].0;
+    	}
+    	// Rounding may leave a sliver over the last choice
+    	choices.last().map(|choice| choice.1)
+    }
+    /// Checks if given mutation can change the gene with given id. A swap
+    /// needs another gene of a different room type that may be moved.
+    fn can_mutate(&self, id: usize, mutation: &ChromosomeMutation) -> bool {
+    	match *mutation {
+    		GeneMutation(ref mutation) => self.genes[id].can_mutate(mutation),
+    		SwapMutation => self.swap_partners(id).len() > 0,
+    		SlideMutation => true,
+    	}
+    }
+    /// Gives the ids of the genes that may be moved and are of a different
+    /// room type than the gene with given id
+    fn swap_partners(&self, id: usize) -> Vector<usize> {
+    	let mut partners = Vector::new();
     	for i in 0..self.genes.len() {
-    		rooms.push(self.genes[i].as_room());
+    		if self.is_movable(i) &&
+    		   self.genes[i].room_key != self.genes[id].room_key {
+    			partners.push(i);
+    		}
+    	}
+    	partners
+    }
+    /// Swaps the centers of the gene with given id and a random gene of
+    /// another room type. Their levels are swapped too if both may be on the
+    /// other's level.
+    fn swap<R: Rng>(&mut self, id: usize, rng: &mut R) {
+    	let partners = self.swap_partners(id);
+    	let other = match rng.choose(&partners) {
+    		Some(&other) => other,
+    		None => return,
+    	};
+    	let (a, b) = (self.genes[id], self.genes[other]);
+    	let (a_center, b_center) = (a.center(), b.center());
+    	self.genes[id].set_center(b_center.x, b_center.y);
+    	self.genes[other].set_center(a_center.x, a_center.y);
+    	if a.is_allowed_level(b.z) && b.is_allowed_level(a.z) {
+    		self.genes[id].z = b.z;
+    		self.genes[other].z = a.z;
     	}
-    	Layout::new(rooms)
     }
+    /// Slides the gene with given id one tile at a time in given direction
+    /// for as long as it stays in a free spot (see is_free_spot). Returns the
+    /// gene where the next step would collide, or None if the room would
+    /// leave the allowed area before running into anything.
+    fn slide(&self, id: usize, direction: Direction, allowed_area: Rect)
+    	-> Option<Gene> {
+    	let (dx, dy) = match direction {
+    		Left => (-1, 0),
+    		Right => (1, 0),
+    		Up => (0, -1),
+    		Down => (0, 1),
+    	};
+    	let mut gene = self.genes[id];
+    	loop {
+    		let mut moved = gene;
+    		let (x, y) = (gene.get_x() + dx, gene.get_y() + dy);
+    		moved.set_x(x);
+    		moved.set_y(y);
+    		if !allowed_area.encloses(moved.room_rect()) {
+    			return None;
+    		}
+    		if !self.is_free_spot(&moved, id) {
+    			return Some(gene);
+    		}
+    		gene = moved;
+    	}
+    }
+}
+
+/// Finds the from-ids of adjacency targets that aren't adjacent to any of the
+/// to-ids, using given function to tell if two ids are adjacent. Returns
+/// (target index, from-id) pairs. Works for both genes and rooms.
+pub fn find_unmet_adjacencies<F>(targets: &Vector<Target>, adjacent: F)
+	-> Vector<(usize, usize)> where F: Fn(usize, usize) -> bool {
+	let mut unmet = Vector::new();
+	for t in 0..targets.len() {
+		if targets[t].kind != TargetKind::Adjacent {
+			continue;
+		}
+		for i in 0..targets[t].from_id.len() {
+			let from = targets[t].from_id[i];
+			if !targets[t].to_id.iter().any(|&to| to != from && adjacent(from, to)) {
+				unmet.push((t, from));
+			}
+		}
+	}
+	unmet
+}
+
+/// Gives the room rectangle of a gene placed in given place of
+/// generate_initial, before random variance
+fn room_rect_in_place(gene: &Gene, place: (isize, isize, Direction)) -> Rect {
+	let mut placed = *gene;
+	match place.2 {
+		Left => placed.set_x(place.0 - gene.get_w()),
+		_ => placed.set_x(place.0),
+	}
+	match place.2 {
+		Up => placed.set_y(place.1 - gene.get_h()),
+		_ => placed.set_y(place.1),
+	}
+	placed.room_rect()
 }
 
 fn sign(n: isize) -> isize {
@@ -434,7 +1210,13 @@ mod tests {
     use super::*;
     use mapping::shapes::Rect;
     use collections::Vector;
-    use rand::Rng;
+    use genetics::config::GaConfig;
+    use mapping::site::Site;
+    use mapping::masks::{Mask, Shape};
+    use mapping::masks::tests::lines;
+    use mapping::doors::Door;
+    use std::sync::Arc;
+    use rand::{Rng, SeedableRng, StdRng};
 
     /// Test random number generatror - gives back numbers that were given to it
     /// one by one.
@@ -492,10 +1274,7 @@ mod tests {
             w: 5,
             h: 7,
         };
-        let gene1 = Gene {
-            rect: rect1,
-            gene_id: 0,
-        };
+        let gene1 = Gene::new(rect1, 0);
         let gene2 = gene1.rotate();
         assert_eq!(7, gene2.get_w());
         assert_eq!(5, gene2.get_h());
@@ -505,10 +1284,7 @@ mod tests {
             w: 1,
             h: 9,
         };
-        let mut gene3 = Gene {
-            rect: rect2,
-            gene_id: 0,
-        };
+        let mut gene3 = Gene::new(rect2, 0);
         gene3.rot_in_place();
         assert_eq!(9, gene3.get_w());
         assert_eq!(1, gene3.get_h());
@@ -522,10 +1298,7 @@ mod tests {
             w: 5,
             h: 7,
         };
-        let mut gene1 = Gene {
-            rect: rect1,
-            gene_id: 0,
-        };
+        let mut gene1 = Gene::new(rect1, 0);
         gene1.set_x(9);
         gene1.set_y(10);
         assert_eq!(9, gene1.get_x());
@@ -540,20 +1313,14 @@ mod tests {
             w: 5,
             h: 7,
         };
-        let gene1 = Gene {
-            rect: rect1,
-            gene_id: 0,
-        };
+        let gene1 = Gene::new(rect1, 0);
         let rect2 = Rect {
             x: -2,
             y: -2,
             w: 3,
             h: 3,
         };
-        let gene2 = Gene {
-            rect: rect2,
-            gene_id: 1,
-        };
+        let gene2 = Gene::new(rect2, 1);
         let mut gene3 = gene1;
         gene3.set_x(4);
         let mut gene4 = gene2;
@@ -566,15 +1333,16 @@ mod tests {
         genes2.push(gene4);
         let chrom1 = Chromosome::new(genes1, Vector::new());
         let chrom2 = Chromosome::new(genes2, Vector::new());
-        let crossover_delta = CROSSOVER_CHANCE * 0.1;
+        let config = GaConfig::new();
+        let crossover_delta = config.crossover_chance * 0.1;
         let mut random_numbers = Vector::new();
-        random_numbers.push(CROSSOVER_CHANCE - crossover_delta);
-        random_numbers.push(CROSSOVER_CHANCE + crossover_delta);
+        random_numbers.push(config.crossover_chance - crossover_delta);
+        random_numbers.push(config.crossover_chance + crossover_delta);
         let mut rng = TestRng {
             numbers: random_numbers,
             indexes: Vector::new(),
         };
-        let (child1, child2) = chrom1.mate(&chrom2, &mut rng);
+        let (child1, child2) = chrom1.mate(&chrom2, &config, &mut rng);
         assert_eq!(gene1, child1.genes[0]);
         assert_eq!(gene4, child1.genes[1]);
         assert_eq!(gene3, child2.genes[0]);
@@ -604,6 +1372,173 @@ mod tests {
         }
     }
 
+    #[test]
+    fn genes_on_different_levels_may_overlap() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new_with_levels(Rect::new(-2, -2, 5, 5), 0, 0, 0b1));
+        // Overlaps gene 0, but leaves the stair shaft free
+        let mut upstairs = Gene::new_with_levels(Rect::new(1, -2, 5, 5), 1, 1, 0b110);
+        assert_eq!(1, upstairs.get_z());
+        upstairs.set_z(2);
+        gene_vec.push(upstairs);
+        gene_vec.push(Gene::new_with_levels(Rect::new(6, -2, 5, 5), 2, 1, 0b110));
+        let genes = Chromosome::new(gene_vec, Vector::new());
+        assert_eq!(Rect::new(1, -2, 5, 5), genes.genes[1].rect);
+        // From gene 2 to the stairs in the center of gene 0, down one level
+        // and back to gene 1 next to the stairs
+        assert_eq!(8.0 + LEVEL_DISTANCE + 3.0, genes.travel_distance(2, 1));
+        assert_eq!(LEVEL_DISTANCE + 8.0, genes.travel_distance(0, 2));
+    }
+
+    #[test]
+    fn relax_keeps_the_stair_shaft_free() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new_with_levels(Rect::new(-3, -3, 8, 8), 0, 0, 0b11));
+        // Right under gene 0, over the stairs
+        gene_vec.push(Gene::new_with_levels(Rect::new(-3, -3, 8, 8), 1, 1, 0b10));
+        let chromosome = Chromosome::new(gene_vec, Vector::new());
+        let shaft = chromosome.genes[0].room_rect().center();
+        let gene = chromosome.genes[1];
+        assert_eq!(1, gene.get_z());
+        assert!(!gene.get_shape().contains(gene.room_rect(), shaft));
+        assert_eq!(None, chromosome.obstacle_under(&gene));
+    }
+
+    #[test]
+    fn pinned_genes_are_never_moved() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(-2, -2, 5, 5), 0));
+        let mut pinned = Gene::new(Rect::new(4, -1, 4, 3), 1);
+        pinned.set_pinned(true);
+        gene_vec.push(pinned);
+        for i in 2..10 {
+            gene_vec.push(Gene::new(Rect::new(i - 5, 0, 4, 4), i));
+        }
+        let mut chromosome = Chromosome::new(gene_vec, Vector::new());
+        let mut config = GaConfig::new();
+        config.mutation_chance = 1.0;
+        let mut rng: StdRng = SeedableRng::from_seed(&[5][..]);
+        for _ in 0..20 {
+            chromosome.mutate(&config, &mut rng);
+            assert_eq!(pinned, chromosome.genes[1]);
+            for i in 0..chromosome.genes.len() {
+                for j in i + 1..chromosome.genes.len() {
+                    assert!(!chromosome.genes[i].collides_with(chromosome.genes[j]));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn relax_moves_genes_off_blocked_tiles() {
+        let site = Arc::new(Site::parse("......~~~~\n\
+                                         ..@...~~~~\n\
+                                         ......~~~~\n\
+                                         ......~~~~\n").unwrap());
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(-2, -1, 3, 3), 0));
+        for i in 1..6 {
+            gene_vec.push(Gene::new(Rect::new(2, i - 3, 4, 3), i));
+        }
+        let chromosome = Chromosome::new_on_site(gene_vec, Vector::new(),
+            site.clone());
+        for i in 0..chromosome.genes.len() {
+            let gene = chromosome.genes[i];
+            assert_eq!(None, site.blocked_tile_in(gene.room_rect(), gene.z));
+            for j in i + 1..chromosome.genes.len() {
+                assert!(!gene.collides_with(chromosome.genes[j]));
+            }
+        }
+    }
+
+    #[test]
+    fn relax_prefers_moves_inside_bounds_and_overflow_is_penalized() {
+        let mut site = Site::empty();
+        site.set_bounds(Rect::new(-10, -3, 20, 6));
+        let site = Arc::new(site);
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(-2, -2, 5, 5), 0));
+        gene_vec.push(Gene::new(Rect::new(-1, -1, 3, 4), 1));
+        let chromosome = Chromosome::new_on_site(gene_vec.clone(), Vector::new(),
+            site.clone());
+        // Moving down would be as short, but it would go outside the bounds
+        assert_eq!(Rect::new(3, -1, 3, 4), chromosome.genes[1].rect);
+        assert_eq!(0, chromosome.overflow());
+        gene_vec[1] = Gene::new(Rect::new(20, 0, 3, 3), 1);
+        let bounded = Chromosome::new_on_site(gene_vec.clone(), Vector::new(),
+            site);
+        let unbounded = Chromosome::new(gene_vec, Vector::new());
+        assert_eq!(4, bounded.overflow());
+        assert_eq!(unbounded.fitness / 5.0, bounded.fitness);
+    }
+
+    #[test]
+    fn separation_shortfall_is_penalized() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(-2, -2, 5, 5), 0));
+        gene_vec.push(Gene::new(Rect::new(3, -2, 5, 5), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from.clone(), to.clone(), 1.0));
+        let attracted = Chromosome::new(gene_vec.clone(), targets.clone());
+        assert_eq!(0.0, attracted.separation_shortfall());
+        targets.push(Target::new_repulsion(from, to, 2.0, 8.0));
+        let repelled = Chromosome::new(gene_vec, targets);
+        // The centers are 5 tiles apart, 3 short of the minimum distance
+        assert_eq!(6.0, repelled.separation_shortfall());
+        assert_eq!(attracted.fitness / 7.0, repelled.fitness);
+    }
+
+    #[test]
+    fn separations_are_measured_from_fixed_doors() {
+        let mut doors = Doors::new();
+        doors.push(Door::At(Left, 2));
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(0, 0, 6, 6), 0));
+        gene_vec.push(Gene::new(Rect::new(10, 0, 6, 6), 1));
+        gene_vec[1].set_doors(doors);
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new_repulsion(from, to, 1.0, 10.0));
+        let genes = Chromosome::new(gene_vec, targets);
+        // From the center of room 0 at (2, 2) to the door at (10, 2), where
+        // the centers are 10 tiles apart
+        assert_eq!(8.0, genes.travel_distance(0, 1));
+        assert_eq!(2.0, genes.separation_shortfall());
+        assert_eq!(8.0, genes.as_layout().travel_distance(0, 1));
+    }
+
+    #[test]
+    fn repair_puts_adjacent_genes_next_to_each_other() {
+        let mut gene_vec = Vector::new();
+        gene_vec.push(Gene::new(Rect::new(-2, -2, 5, 5), 0));
+        gene_vec.push(Gene::new_with_key(Rect::new(20, 20, 5, 5), 1, 1));
+        gene_vec.push(Gene::new_with_key(Rect::new(-30, 10, 6, 4), 2, 2));
+        gene_vec[1].set_pinned(true);
+        let mut targets = Vector::new();
+        let mut from = Vector::new();
+        from.push(2);
+        let mut to = Vector::new();
+        to.push(0);
+        targets.push(Target::new_adjacency(from, to.clone(), 1.0));
+        // Gene 1 is pinned, so gene 0 would have to move, but it can't either
+        let mut from = Vector::new();
+        from.push(1);
+        targets.push(Target::new_adjacency(from, to, 1.0));
+        let chromosome = Chromosome::new(gene_vec, targets);
+        assert!(chromosome.genes[2].is_adjacent_to(&chromosome.genes[0]));
+        assert_eq!(Rect::new(20, 20, 5, 5), chromosome.genes[1].rect);
+        let mut unmet = Vector::new();
+        unmet.push((1, 1));
+        assert!(unmet == chromosome.unmet_adjacencies());
+    }
+
     #[test]
     fn relax_keeps_gene_order() {
         let rect1 = Rect {
@@ -612,40 +1547,28 @@ mod tests {
             w: 5,
             h: 7,
         };
-        let gene1 = Gene {
-            rect: rect1,
-            gene_id: 0,
-        };
+        let gene1 = Gene::new(rect1, 0);
         let rect2 = Rect {
             x: 1,
             y: 0,
             w: 3,
             h: 3,
         };
-        let gene2 = Gene {
-            rect: rect2,
-            gene_id: 1,
-        };
+        let gene2 = Gene::new(rect2, 1);
         let rect3 = Rect {
             x: -2,
             y: 5,
             w: 5,
             h: 10,
         };
-        let gene3 = Gene {
-            rect: rect3,
-            gene_id: 2,
-        };
+        let gene3 = Gene::new(rect3, 2);
         let rect4 = Rect {
             x: 0,
             y: -7,
             w: 12,
             h: 8,
         };
-        let gene4 = Gene {
-            rect: rect4,
-            gene_id: 3,
-        };
+        let gene4 = Gene::new(rect4, 3);
         let mut gene_vec = Vector::new();
         gene_vec.push(gene1);
         gene_vec.push(gene2);
@@ -666,50 +1589,38 @@ mod tests {
             w: 5,
             h: 7,
         };
-        let gene1 = Gene {
-            rect: rect1,
-            gene_id: 0,
-        };
+        let gene1 = Gene::new(rect1, 0);
         let rect2 = Rect {
             x: 1,
             y: 0,
             w: 3,
             h: 3,
         };
-        let gene2 = Gene {
-            rect: rect2,
-            gene_id: 1,
-        };
+        let gene2 = Gene::new(rect2, 1);
         let rect3 = Rect {
             x: -2,
             y: 5,
             w: 5,
             h: 10,
         };
-        let gene3 = Gene {
-            rect: rect3,
-            gene_id: 2,
-        };
+        let gene3 = Gene::new(rect3, 2);
         let rect4 = Rect {
             x: 0,
             y: -7,
             w: 12,
             h: 8,
         };
-        let gene4 = Gene {
-            rect: rect4,
-            gene_id: 3,
-        };
+        let gene4 = Gene::new(rect4, 3);
         let mut gene_vec = Vector::new();
         gene_vec.push(gene1);
         gene_vec.push(gene2);
         gene_vec.push(gene3);
         gene_vec.push(gene4);
-        let mut genes = Chromosome {
+        let mut genes = Genome {
 		    genes: gene_vec,
 		    targets: Vector::new(),
+		    site: Arc::new(Site::empty()),
 		    total_area: gene1.area() + gene2.area() + gene3.area() + gene4.area(),
-		    fitness: 0.0,
 		    bounding_box: Rect::new(0, 0, 0, 0),
 		    bounding_box_fresh: false,
 		};
@@ -723,4 +1634,84 @@ mod tests {
                    genes.bounding_box);
     }
 
+    #[test]
+    fn relax_lets_masked_genes_interlock() {
+        let rows = lines(&["###...", "###...", "######", "######"]);
+        let l = Shape::Masked(Mask::parse(&rows).unwrap());
+        let mut gene0 = Gene::new(Rect::new(0, 0, 7, 5), 0);
+        gene0.set_shape(l);
+        // Turned upside down, the other L reaches over the empty corner
+        let mut gene1 = Gene::new(Rect::new(4, -2, 7, 5), 1);
+        gene1.set_shape(l);
+        gene1.rot_in_place();
+        gene1.rot_in_place();
+        let mut genes = Vector::new();
+        genes.push(gene0);
+        genes.push(gene1);
+        let chromosome = Chromosome::new(genes.clone(), Vector::new());
+        assert_eq!(genes[1].rect, chromosome.genes[1].rect);
+        // Moved one tile closer, the rooms touch and are pushed apart
+        genes[1].set_x(3);
+        let chromosome = Chromosome::new(genes, Vector::new());
+        assert!(!chromosome.genes[0].collides_with(chromosome.genes[1]));
+        assert!(chromosome.genes[1].rect != Rect::new(3, -2, 7, 5));
+        let mut mirrored = gene0;
+        mirrored.mirror_in_place();
+        assert_eq!(gene0.rect, mirrored.rect);
+        assert_eq!(l.mirror(), mirrored.get_shape());
+    }
+
+    #[test]
+    fn flips_are_tracked_in_orientation() {
+        let rows = lines(&["###...", "###...", "######", "######"]);
+        let mut gene = Gene::new(Rect::new(0, 0, 7, 5), 0);
+        assert!(!gene.can_mutate(&FlipMutation(Flip::Horizontal)));
+        assert!(!gene.can_mutate(&LevelMutation));
+        gene.set_shape(Shape::Masked(Mask::parse(&rows).unwrap()));
+        assert!(gene.can_mutate(&FlipMutation(Flip::Horizontal)));
+        gene.flip_in_place(Flip::Vertical);
+        match gene.get_shape() {
+            Shape::Masked(mask) => assert_eq!(
+                &["######", "######", "###...", "###..."], &mask.lines()[..]),
+            Shape::Rectangle => panic!("The mask was lost!"),
+        }
+        assert_eq!(Orientation { quarter_turns: 2, mirrored: true },
+                   gene.get_orientation());
+        gene.rot_in_place();
+        assert_eq!(Rect::new(0, 0, 5, 7), gene.rect);
+        assert_eq!(270, gene.as_room().get_orientation().degrees());
+    }
+
+    #[test]
+    fn slides_stop_before_contact_and_swaps_need_other_types() {
+        let mut genes = Vector::new();
+        genes.push(Gene::new_with_key(Rect::new(0, 0, 5, 5), 0, 0));
+        genes.push(Gene::new_with_key(Rect::new(20, 1, 4, 4), 1, 1));
+        genes.push(Gene::new_with_key(Rect::new(20, 10, 4, 4), 2, 2));
+        let mut chromosome = Chromosome::new(genes.clone(), Vector::new());
+        let area = Rect::new(-30, -30, 60, 60);
+        assert_eq!(Rect::new(5, 1, 4, 4),
+                   chromosome.slide(1, Left, area).unwrap().rect);
+        // Nothing is in the way, so the gene isn't slid off to the edge
+        assert_eq!(None, chromosome.slide(2, Down, area));
+        // Gene 2 stops right below gene 1
+        assert_eq!(Rect::new(20, 5, 4, 4),
+                   chromosome.slide(2, Up, area).unwrap().rect);
+        let mut weights = GaConfig::new().mutation_weights;
+        weights.rotation = 0.0;
+        weights.flip = 0.0;
+        weights.position = 0.0;
+        weights.nudge = 0.0;
+        weights.slide = 0.0;
+        weights.level = 0.0;
+        let mut rng: StdRng = SeedableRng::from_seed(&[3][..]);
+        assert_eq!(Some(SwapMutation),
+                   chromosome.choose_mutation(1, &weights, &mut rng));
+        chromosome.swap(1, &mut rng);
+        assert_eq!(Point::new(22, 12), chromosome.genes[1].center());
+        assert_eq!(Point::new(22, 3), chromosome.genes[2].center());
+        genes[2].room_key = 1;
+        let chromosome = Chromosome::new(genes, Vector::new());
+        assert_eq!(None, chromosome.choose_mutation(1, &weights, &mut rng));
+    }
 }
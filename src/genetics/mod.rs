@@ -1,4 +1,7 @@
 //! This module contains functionality closely related to the inner workings of
 //! the genetic algorithm.
 pub mod genes;
-pub mod breeding;
\ No newline at end of file
+pub mod fitness;
+pub mod breeding;
+pub mod config;
+pub mod pool;
\ No newline at end of file
@@ -0,0 +1,208 @@
+//! This module contains a pool of worker threads that breed children for a
+//! population in parallel.
+
+use rand::Rng;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Sender, Receiver};
+use std::thread::{self, JoinHandle};
+use super::genes::Chromosome;
+use super::config::GaConfig;
+use super::fitness::FitnessFunction;
+use super::breeding;
+use collections::Vector;
+
+/// A share of children to breed, sent to a worker thread
+struct Job<F> {
+    population: Arc<Vector<Chromosome<F>>>,
+    amount: usize,
+    seed: usize,
+    config: GaConfig,
+    index: usize,
+    reply: Sender<(usize, Vector<Chromosome<F>>)>,
+}
+
+/// BreedingPool is a set of worker threads that stay alive between
+/// generations. The children of a generation are split evenly between the
+/// workers, and each share is bred with its own random number generator. A
+/// pool breeds populations of chromosomes with fitness functions of type F.
+pub struct BreedingPool<F> {
+    jobs: Option<Sender<Job<F>>>,
+    workers: Vector<JoinHandle<()>>,
+}
+
+impl<F: FitnessFunction + 'static> BreedingPool<F> {
+    /// Creates a pool with given number of worker threads (at least one)
+    pub fn new(threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel();
+        let receiver = Arc::new(Mutex::new(receiver));
+        let mut workers = Vector::new();
+        for _ in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            workers.push(thread::spawn(move || work(receiver)));
+        }
+        BreedingPool {
+            jobs: Some(sender),
+            workers: workers,
+        }
+    }
+    /// Returns the number of worker threads
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+    /// Breeds given amount of children from a population that has been sorted
+    /// from most fit to least fit. The seeds of the workers are drawn from rng,
+    /// so the result only depends on rng and the size of the pool.
+    /// # Panics
+    /// Panics if a worker panics while breeding its share
+    pub fn breed_children<R: Rng>(&self, population: Arc<Vector<Chromosome<F>>>,
+        amount: usize, config: &GaConfig, rng: &mut R)
+        -> Vector<Chromosome<F>> {
+        let (reply, results) = mpsc::channel();
+        let shares = self.size();
+        let jobs = self.jobs.as_ref().unwrap();
+        for i in 0..shares {
+            let job = Job {
+                population: population.clone(),
+                amount: amount / shares + if i < amount % shares { 1 } else { 0 },
+                seed: rng.gen(),
+                config: *config,
+                index: i,
+                reply: reply.clone(),
+            };
+            jobs.send(job).unwrap();
+        }
+        // Only the jobs hold senders now, so if a worker dies with its job,
+        // collect finds out instead of waiting forever
+        drop(reply);
+        collect(results, shares)
+    }
+}
+
+impl<F> Drop for BreedingPool<F> {
+    /// Closes the job queue and waits for the workers to finish
+    fn drop(&mut self) {
+        self.jobs = None;
+        while let Some(worker) = self.workers.pop() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// Runs jobs from the queue until the queue is closed
+fn work<F: FitnessFunction>(jobs: Arc<Mutex<Receiver<Job<F>>>>) {
+    loop {
+        let job = match jobs.lock().unwrap().recv() {
+            Ok(job) => job,
+            Err(_) => return,
+        };
+        let mut rng = breeding::seeded_rng(job.seed);
+        let children = {
+            let candidates = breeding::candidates(&job.population);
+            breeding::breed_children(&candidates, job.amount, &job.config,
+                &mut rng)
+        };
+        // The pool only stops listening if it panicked, so ignore failure
+        let _ = job.reply.send((job.index, children));
+    }
+}
+
+/// Waits for the results of all shares and joins them in the order of the
+/// shares, regardless of which worker finished first
+/// # Panics
+/// Panics if a share is never sent back because its worker panicked
+fn collect<F>(results: Receiver<(usize, Vector<Chromosome<F>>)>, shares: usize)
+    -> Vector<Chromosome<F>> {
+    let mut received: Vector<Option<Vector<Chromosome<F>>>> = Vector::new();
+    for _ in 0..shares {
+        received.push(None);
+    }
+    for _ in 0..shares {
+        let (index, children) = match results.recv() {
+            Ok(result) => result,
+            Err(_) => panic!("A breeding worker panicked!"),
+        };
+        received[index] = Some(children);
+    }
+    let mut children = Vector::new();
+    for i in 0..shares {
+        let share = received[i].take().unwrap();
+        for j in 0..share.len() {
+            children.push(share[j].clone());
+        }
+    }
+    children
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use mapping::shapes::Rect;
+    use genetics::genes::{Chromosome, Gene, Genome};
+    use genetics::breeding::{self, seeded_rng};
+    use genetics::config::GaConfig;
+    use genetics::fitness::{AreaFitness, FitnessFunction};
+    use mapping::site::Site;
+    use collections::Vector;
+
+    /// Fitness function that panics once it's armed
+    struct PanickingFitness {
+        armed: AtomicBool,
+    }
+
+    impl FitnessFunction for PanickingFitness {
+        fn fitness(&self, _: &Genome) -> f32 {
+            if self.armed.load(Ordering::SeqCst) {
+                panic!("The fitness function panicked!");
+            }
+            1.0
+        }
+    }
+
+    fn population(size: usize) -> Vector<Chromosome<AreaFitness>> {
+        let mut genes = Vector::new();
+        for i in 0..6 {
+            let rect = Rect { x: 0, y: 0, w: (i * 5) % 7 + 3, h: (i * 3) % 5 + 3 };
+            genes.push(Gene::new(rect, i));
+        }
+        breeding::generate_initial_population(genes, Vector::new(),
+            Arc::new(Site::empty()), Arc::new(AreaFitness), size,
+            &mut seeded_rng(7))
+    }
+
+    #[test]
+    fn pool_breeds_requested_amount_deterministically() {
+        let pool = BreedingPool::new(3);
+        assert_eq!(3, pool.size());
+        let config = GaConfig::new();
+        let parents = Arc::new(population(20));
+        let children1 = pool.breed_children(parents.clone(), 17, &config,
+            &mut seeded_rng(99));
+        let children2 = pool.breed_children(parents.clone(), 17, &config,
+            &mut seeded_rng(99));
+        assert_eq!(17, children1.len());
+        for i in 0..children1.len() {
+            assert!(children1[i].genes == children2[i].genes);
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "A breeding worker panicked!")]
+    fn pool_reports_workers_that_panic() {
+        let fitness_function = Arc::new(PanickingFitness {
+            armed: AtomicBool::new(false),
+        });
+        let mut genes = Vector::new();
+        genes.push(Gene::new(Rect::new(0, 0, 4, 4), 0));
+        let mut parents = Vector::new();
+        parents.push(Chromosome::new_with_fitness(genes, Vector::new(),
+            Arc::new(Site::empty()), fitness_function.clone()));
+        // The children can't be scored, so the workers panic
+        fitness_function.armed.store(true, Ordering::SeqCst);
+        let pool = BreedingPool::new(2);
+        pool.breed_children(Arc::new(parents), 4, &GaConfig::new(),
+            &mut seeded_rng(1));
+    }
+}
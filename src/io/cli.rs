@@ -0,0 +1,327 @@
+//! This module contains parsing of command line arguments.
+
+use std::str::FromStr;
+use io::ui::Verbosity;
+use genetics::config::{self, GaConfig};
+
+/// Usage instructions printed with --help or when the arguments are invalid
+pub const USAGE: &'static str = "\
+Usage: dwarfimain [OPTIONS]
+
+Options that are left out will be asked interactively.
+
+Options:
+    -i, --input FILE         Blueprint JSON file to read
+    -o, --output FILE        File to save the finished layout to
+    --site FILE              Site map of blocked tiles to fit the layout on
+    --json FILE              Also save the layout as JSON to FILE
+    --svg FILE               Also save the layout as an SVG image to FILE
+    --svg-targets            Draw the targets as lines in the SVG image
+    --quickfort FILE         Also save a Quickfort #dig blueprint to FILE
+    --quickfort-build FILE   Also save a Quickfort #build blueprint to FILE
+    -p, --population SIZE    Population size
+    -g, --generations N      Number of generations to breed
+    -s, --seed SEED          Seed for the random number generator
+    --crossover-chance C     Chance of swapping a gene during mating
+    --mutation-chance C      Chance of mutating a gene
+    --keep-alive P           Share of population kept for the next generation
+    --purge P                Share of population killed in a purge
+    --purge-interval N       Generations of stagnation before a purge
+    -t, --threads N          Number of threads to breed on
+    --islands N              Number of islands to split the population into
+    --migration-interval N   Generations between migrations between islands
+    --migrants N             Chromosomes migrating from each island
+    -q, --quiet              Don't print progress
+    -v, --verbose            Print progress on every generation
+    -h, --help               Print this help";
+
+/// Options given on the command line. Options that weren't given are None.
+#[derive(PartialEq, Debug)]
+pub struct Options {
+    pub input: Option<String>,
+    pub output: Option<String>,
+    pub site: Option<String>,
+    pub json: Option<String>,
+    pub svg: Option<String>,
+    pub svg_targets: bool,
+    pub quickfort: Option<String>,
+    pub quickfort_build: Option<String>,
+    pub pop_size: Option<usize>,
+    pub generations: Option<usize>,
+    pub seed: Option<usize>,
+    pub crossover_chance: Option<f32>,
+    pub mutation_chance: Option<f32>,
+    pub keep_alive_percentage: Option<f32>,
+    pub purge_percentage: Option<f32>,
+    pub purge_interval: Option<usize>,
+    pub threads: Option<usize>,
+    pub islands: Option<usize>,
+    pub migration_interval: Option<usize>,
+    pub migrants: Option<usize>,
+    pub verbosity: Verbosity,
+    pub help: bool,
+}
+
+impl Options {
+    /// Creates options where nothing has been given
+    pub fn new() -> Self {
+        Options {
+            input: None,
+            output: None,
+            site: None,
+            json: None,
+            svg: None,
+            svg_targets: false,
+            quickfort: None,
+            quickfort_build: None,
+            pop_size: None,
+            generations: None,
+            seed: None,
+            crossover_chance: None,
+            mutation_chance: None,
+            keep_alive_percentage: None,
+            purge_percentage: None,
+            purge_interval: None,
+            threads: None,
+            islands: None,
+            migration_interval: None,
+            migrants: None,
+            verbosity: Verbosity::Normal,
+            help: false,
+        }
+    }
+    /// Overrides the parameters of the config with the ones given on the
+    /// command line
+    pub fn apply_to(&self, config: &mut GaConfig) {
+        if let Some(value) = self.crossover_chance {
+            config.crossover_chance = value;
+        }
+        if let Some(value) = self.mutation_chance {
+            config.mutation_chance = value;
+        }
+        if let Some(value) = self.keep_alive_percentage {
+            config.keep_alive_percentage = value;
+        }
+        if let Some(value) = self.purge_percentage {
+            config.purge_percentage = value;
+        }
+        if let Some(value) = self.purge_interval {
+            config.purge_interval = value;
+        }
+        if let Some(value) = self.threads {
+            config.threads = value;
+        }
+        if let Some(value) = self.islands {
+            config.islands = value;
+        }
+        if let Some(value) = self.migration_interval {
+            config.migration_interval = value;
+        }
+        if let Some(value) = self.migrants {
+            config.migrants = value;
+        }
+    }
+}
+
+/// Parses command line arguments (without the program name) into options.
+/// Returns a human-readable reason if the arguments are invalid.
+pub fn parse(args: &[String]) -> Result<Options, String> {
+    let mut options = Options::new();
+    let mut i = 0;
+    while i < args.len() {
+        let arg = args[i].as_str();
+        match arg {
+            "-h" | "--help" => options.help = true,
+            "-q" | "--quiet" => options.verbosity = Verbosity::Quiet,
+            "-v" | "--verbose" => options.verbosity = Verbosity::Verbose,
+            "-i" | "--input" => {
+                options.input = Some(try!(value_of(args, i)));
+                i += 1;
+            }
+            "-o" | "--output" => {
+                options.output = Some(try!(value_of(args, i)));
+                i += 1;
+            }
+            "--site" => {
+                options.site = Some(try!(value_of(args, i)));
+                i += 1;
+            }
+            "--json" => {
+                options.json = Some(try!(value_of(args, i)));
+                i += 1;
+            }
+            "--svg" => {
+                options.svg = Some(try!(value_of(args, i)));
+                i += 1;
+            }
+            "--svg-targets" => options.svg_targets = true,
+            "--quickfort" => {
+                options.quickfort = Some(try!(value_of(args, i)));
+                i += 1;
+            }
+            "--quickfort-build" => {
+                options.quickfort_build = Some(try!(value_of(args, i)));
+                i += 1;
+            }
+            "-p" | "--population" => {
+                options.pop_size = Some(try!(count_value_of(args, i)));
+                i += 1;
+            }
+            "-g" | "--generations" => {
+                options.generations = Some(try!(count_value_of(args, i)));
+                i += 1;
+            }
+            "-s" | "--seed" => {
+                options.seed = Some(try!(parsed_value_of(args, i)));
+                i += 1;
+            }
+            "--crossover-chance" => {
+                options.crossover_chance = Some(try!(chance_value_of(args, i)));
+                i += 1;
+            }
+            "--mutation-chance" => {
+                options.mutation_chance = Some(try!(chance_value_of(args, i)));
+                i += 1;
+            }
+            "--keep-alive" => {
+                options.keep_alive_percentage =
+                    Some(try!(chance_value_of(args, i)));
+                i += 1;
+            }
+            "--purge" => {
+                options.purge_percentage = Some(try!(chance_value_of(args, i)));
+                i += 1;
+            }
+            "--purge-interval" => {
+                options.purge_interval = Some(try!(count_value_of(args, i)));
+                i += 1;
+            }
+            "-t" | "--threads" => {
+                options.threads = Some(try!(count_value_of(args, i)));
+                i += 1;
+            }
+            "--islands" => {
+                options.islands = Some(try!(count_value_of(args, i)));
+                i += 1;
+            }
+            "--migration-interval" => {
+                options.migration_interval = Some(try!(count_value_of(args, i)));
+                i += 1;
+            }
+            "--migrants" => {
+                options.migrants = Some(try!(parsed_value_of(args, i)));
+                i += 1;
+            }
+            _ => return Err(format!("Unknown argument \"{}\"", arg)),
+        }
+        i += 1;
+    }
+    Ok(options)
+}
+
+/// Returns the value following the flag at index i
+fn value_of(args: &[String], i: usize) -> Result<String, String> {
+    if i + 1 < args.len() {
+        Ok(args[i + 1].clone())
+    } else {
+        Err(format!("{} needs a value", args[i]))
+    }
+}
+
+/// Returns the value following the flag at index i parsed to any type that
+/// satisfies FromStr
+fn parsed_value_of<T: FromStr>(args: &[String], i: usize) -> Result<T, String> {
+    let value = try!(value_of(args, i));
+    match value.parse() {
+        Ok(result) => Ok(result),
+        Err(_) => Err(format!("Invalid value \"{}\" for {}", value, args[i])),
+    }
+}
+
+/// Returns the value following the flag at index i parsed as a chance or a
+/// percentage (between 0.0 and 1.0)
+fn chance_value_of(args: &[String], i: usize) -> Result<f32, String> {
+    let value = try!(parsed_value_of(args, i));
+    if config::is_valid_chance(value) {
+        Ok(value)
+    } else {
+        Err(format!("{} must be between 0.0 and 1.0", args[i]))
+    }
+}
+
+/// Returns the value following the flag at index i parsed as a count that
+/// must be at least 1
+fn count_value_of(args: &[String], i: usize) -> Result<usize, String> {
+    let value = try!(parsed_value_of(args, i));
+    if value > 0 {
+        Ok(value)
+    } else {
+        Err(format!("{} must be at least 1", args[i]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use io::ui::Verbosity;
+    use genetics::config::GaConfig;
+
+    fn to_args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|a| String::from(*a)).collect()
+    }
+
+    #[test]
+    fn parse_reads_all_options() {
+        let args = to_args(&["-i", "in.json", "--output", "out.txt", "-p", "500",
+                             "--generations", "1000", "-s", "42", "-q",
+                             "--json", "out.json", "--svg", "out.svg",
+                             "--svg-targets", "--site", "embark.txt"]);
+        let options = parse(&args).unwrap();
+        assert_eq!(Some(String::from("in.json")), options.input);
+        assert_eq!(Some(String::from("out.txt")), options.output);
+        assert_eq!(Some(String::from("out.json")), options.json);
+        assert_eq!(Some(String::from("out.svg")), options.svg);
+        assert!(options.svg_targets);
+        assert_eq!(Some(String::from("embark.txt")), options.site);
+        assert_eq!(Some(500), options.pop_size);
+        assert_eq!(Some(1000), options.generations);
+        assert_eq!(Some(42), options.seed);
+        assert_eq!(Verbosity::Quiet, options.verbosity);
+        assert!(!options.help);
+    }
+
+    #[test]
+    fn parse_leaves_missing_options_empty() {
+        let options = parse(&to_args(&["-v"])).unwrap();
+        assert_eq!(None, options.input);
+        assert_eq!(None, options.pop_size);
+        assert_eq!(Verbosity::Verbose, options.verbosity);
+    }
+
+    #[test]
+    fn parse_rejects_invalid_arguments() {
+        assert!(parse(&to_args(&["--population", "lots"])).is_err());
+        assert!(parse(&to_args(&["-p", "0"])).is_err());
+        assert!(parse(&to_args(&["--generations", "0"])).is_err());
+        assert!(parse(&to_args(&["--input"])).is_err());
+        assert!(parse(&to_args(&["--frobnicate"])).is_err());
+        assert!(parse(&to_args(&["--mutation-chance", "1.5"])).is_err());
+        assert!(parse(&to_args(&["--purge-interval", "0"])).is_err());
+        assert!(parse(&to_args(&["--threads", "0"])).is_err());
+        assert!(parse(&to_args(&["--islands", "0"])).is_err());
+    }
+
+    #[test]
+    fn apply_to_overrides_given_parameters() {
+        let args = to_args(&["--mutation-chance", "0.25", "--purge-interval",
+                             "50", "-t", "4", "--islands", "3"]);
+        let mut config = GaConfig::new();
+        parse(&args).unwrap().apply_to(&mut config);
+        assert_eq!(0.25, config.mutation_chance);
+        assert_eq!(50, config.purge_interval);
+        assert_eq!(4, config.threads);
+        assert_eq!(3, config.islands);
+        assert_eq!(GaConfig::new().crossover_chance, config.crossover_chance);
+    }
+}
@@ -0,0 +1,198 @@
+//! This module contains exporting finished layouts in a structured (JSON)
+//! format, so that other tools don't have to parse the character matrix.
+
+use std::io::Error;
+use rustc_serialize::json;
+use mapping::rooms::Layout;
+use mapping::masks::Shape;
+use genetics::genes::TargetKind;
+use io::output;
+
+/// A room of an exported layout. Coordinates are relative to the top left
+/// corner of the layout, the same way as in the saved character matrix.
+#[derive(PartialEq, Debug, RustcDecodable, RustcEncodable)]
+pub struct RoomExport {
+    /// Key of the room's type
+    pub key: usize,
+    /// Which one of the rooms with the same key this is, starting from 0
+    pub index: usize,
+    pub x: isize,
+    pub y: isize,
+    /// The z-level of the room, 0 being the top level
+    pub z: isize,
+    pub w: isize,
+    pub h: isize,
+    /// Clockwise rotation in degrees compared to the room's blueprint (0, 90,
+    /// 180 or 270), after mirroring it if it's mirrored
+    pub rotation: usize,
+    /// Whether the room is mirrored from left to right compared to its
+    /// blueprint
+    pub mirrored: bool,
+    /// Whether the room was pinned in place by the blueprint
+    pub pinned: bool,
+    /// The wall tiles of the room's fixed doors as (x, y); empty if the room
+    /// can have doors anywhere
+    pub doors: Vec<(isize, isize)>,
+    /// The rows of the room's mask as the room is placed, or None if the room
+    /// is a rectangle
+    pub mask: Option<Vec<String>>,
+}
+
+/// A target of an exported layout along with the distance achieved for it
+#[derive(PartialEq, Debug, RustcDecodable, RustcEncodable)]
+pub struct TargetExport {
+    pub from_key: usize,
+    pub to_key: usize,
+    pub weight: f32,
+    /// Average travel distance between the nearest entrances (fixed doors,
+    /// otherwise centers) of the rooms of the target, through the stairs for
+    /// rooms on different levels
+    pub distance: f32,
+    /// Minimum distance between the rooms if the target keeps them apart
+    pub min_distance: Option<f32>,
+    /// Whether every from-room must be next to a to-room
+    pub adjacent: bool,
+    /// Number of room pairs that are closer than the minimum distance, or
+    /// number of from-rooms that aren't next to any to-room
+    pub violations: usize,
+}
+
+/// LayoutExport is a description of a finished layout that can be encoded to
+/// JSON
+#[derive(PartialEq, Debug, RustcDecodable, RustcEncodable)]
+pub struct LayoutExport {
+    /// Fitness of the solution the genetic algorithm found. The layout is
+    /// compacted and connected after that, so its rooms and distances may
+    /// score differently.
+    pub ga_fitness: f32,
+    pub width: isize,
+    pub height: isize,
+    // Vec instead of the custom vector for the same reason as in Blueprint
+    pub rooms: Vec<RoomExport>,
+    pub targets: Vec<TargetExport>,
+}
+
+impl LayoutExport {
+    /// Describes a layout
+    pub fn new(layout: &Layout) -> Self {
+        let bounding_box = layout.calculate_bounding_box();
+        let layout_rooms = layout.get_rooms();
+        let mut rooms: Vec<RoomExport> = Vec::new();
+        for i in 0..layout_rooms.len() {
+            let room = &layout_rooms[i];
+            let key = room.get_key();
+            let index = rooms.iter().filter(|r| r.key == key).count();
+            let orientation = room.get_orientation();
+            rooms.push(RoomExport {
+                key: key,
+                index: index,
+                x: room.get_x() - bounding_box.x,
+                y: room.get_y() - bounding_box.y,
+                z: room.get_z(),
+                w: room.get_w(),
+                h: room.get_h(),
+                rotation: orientation.degrees(),
+                mirrored: orientation.mirrored,
+                pinned: room.is_pinned(),
+                doors: room.door_tiles().iter()
+                    .map(|d| (d.x - bounding_box.x, d.y - bounding_box.y))
+                    .collect(),
+                mask: match room.get_shape() {
+                    Shape::Masked(mask) => Some(mask.lines().to_vec()),
+                    Shape::Rectangle => None,
+                },
+            });
+        }
+        let layout_targets = layout.get_targets();
+        let distances = layout.target_distances();
+        let violated = layout.violated_separations();
+        let unmet = layout.unmet_adjacencies();
+        let mut targets = Vec::new();
+        for i in 0..layout_targets.len() {
+            let target = &layout_targets[i];
+            targets.push(TargetExport {
+                from_key: layout_rooms[target.from_id[0]].get_key(),
+                to_key: layout_rooms[target.to_id[0]].get_key(),
+                weight: target.weight,
+                distance: distances[i],
+                min_distance: match target.kind {
+                    TargetKind::Repel(min_distance) => Some(min_distance),
+                    _ => None,
+                },
+                adjacent: target.kind == TargetKind::Adjacent,
+                violations: violated.iter().filter(|v| v.0 == i).count() +
+                    unmet.iter().filter(|u| u.0 == i).count(),
+            });
+        }
+        LayoutExport {
+            ga_fitness: layout.get_fitness(),
+            width: bounding_box.w,
+            height: bounding_box.h,
+            rooms: rooms,
+            targets: targets,
+        }
+    }
+    /// Encodes the description as pretty-printed JSON
+    pub fn to_json(&self) -> String {
+        format!("{}", json::as_pretty_json(self))
+    }
+}
+
+/// Saves a JSON description of a layout to a file
+pub fn save_json(layout: &Layout, filename: String) -> Result<(), Error> {
+    output::save(LayoutExport::new(layout).to_json(), filename)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use rustc_serialize::json;
+    use mapping::shapes::{Orientation, Rect};
+    use mapping::rooms::{Layout, Room};
+    use genetics::genes::Target;
+    use collections::Vector;
+
+    #[test]
+    fn export_describes_rooms_and_targets() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(-2, -1, 3, 3), 0));
+        let mut flipped = Room::new(Rect::new(2, -1, 5, 3), 1);
+        flipped.set_orientation(Orientation { quarter_turns: 2, mirrored: true });
+        rooms.push(flipped);
+        let mut turned = Room::new(Rect::new(-2, 3, 5, 3), 1);
+        turned.rot_in_place();
+        rooms.push(turned);
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        to.push(2);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from.clone(), to.clone(), 2.0));
+        targets.push(Target::new_repulsion(from, to, 1.0, 6.0));
+        let mut layout = Layout::new(rooms, targets);
+        layout.set_fitness(12.5);
+        let export = LayoutExport::new(&layout);
+        assert_eq!(12.5, export.ga_fitness);
+        assert_eq!((9, 9), (export.width, export.height));
+        assert_eq!(RoomExport { key: 1, index: 1, x: 0, y: 4, z: 0, w: 3, h: 5,
+                                rotation: 90, mirrored: false, pinned: false,
+                                doors: vec![], mask: None },
+                   export.rooms[2]);
+        assert_eq!((0, 0, 0), (export.rooms[0].index, export.rooms[0].x,
+                               export.rooms[0].rotation));
+        assert_eq!((180, true), (export.rooms[1].rotation,
+                                 export.rooms[1].mirrored));
+        assert_eq!(2, export.targets.len());
+        assert_eq!((0, 1), (export.targets[0].from_key, export.targets[0].to_key));
+        assert_eq!(5.0, export.targets[0].distance);
+        assert_eq!((None, 0), (export.targets[0].min_distance,
+                               export.targets[0].violations));
+        // Both rooms are 5 tiles away from room 0
+        assert_eq!((Some(6.0), 2), (export.targets[1].min_distance,
+                                    export.targets[1].violations));
+        let decoded: LayoutExport = json::decode(&export.to_json()).unwrap();
+        assert_eq!(export, decoded);
+    }
+}
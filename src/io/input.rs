@@ -5,30 +5,492 @@
 use collections::Vector;
 use std::collections::HashMap; 
 //Won't be using own implementation as this is basically UI code
-use genetics::genes::{Gene, Target};
+use genetics::genes::{self, Gene, Target};
+use mapping::rooms::{self, RoomType};
+use genetics::config::{self, GaConfig, MutationWeights};
+use genetics::fitness::{self, CompositeFitness, DistanceMetric,
+                        FitnessFunction};
 use mapping::shapes::Rect;
-use std::io::Read;
+use mapping::shapes::Direction::{Left, Right, Up, Down};
+use mapping::doors::{self, Door, Doors};
+use mapping::masks::{self, Mask, Shape};
+use mapping::site::{Site, SiteError};
+use std::io::{self, Read};
 use std::fs::File;
 use std::path::Path;
+use std::fmt::{self, Display, Formatter};
 use rustc_serialize::json;
 
+/// Reasons for why reading a blueprint can fail
+#[derive(Debug)]
+pub enum ReadError {
+	/// The file couldn't be opened or read
+	Io(io::Error),
+	/// The file isn't a blueprint in valid JSON
+	Json(json::DecoderError),
+	/// The file is a blueprint, but it doesn't pass Blueprint.validate()
+	Invalid(Vec<BlueprintError>),
+	/// The file isn't a valid site map
+	Site(SiteError),
+}
+
+/// Problems that Blueprint.validate() can find in a blueprint. Room and target
+/// indexes are positions in the blueprint's rooms- and targets-lists,
+/// starting from 0.
+#[derive(PartialEq, Debug)]
+pub enum BlueprintError {
+	/// The room type has the same key as an earlier room type
+	DuplicateKey { room: usize, key: usize },
+	/// The room type's width or height is less than 1
+	InvalidSize { room: usize, key: usize },
+	/// The room type's amount is 0
+	ZeroAmount { room: usize, key: usize },
+	/// There's no room type with key 0
+	MissingKeyZero,
+	/// The room type with key 0 has an amount other than 1
+	InvalidKeyZeroAmount { room: usize, amount: usize },
+	/// The target refers to a room key that no room type has
+	UnknownKey { target: usize, key: usize },
+	/// The room type's glyph is reserved for walls, corridors or empty space
+	ReservedGlyph { room: usize, key: usize },
+	/// The room type's glyph is already used by an earlier room type
+	DuplicateGlyph { room: usize, key: usize },
+	/// The target's weight is not a positive number
+	InvalidWeight { target: usize },
+	/// The target's minimum distance is not a positive number
+	InvalidMinDistance { target: usize },
+	/// The target both keeps the rooms apart and puts them next to each other
+	ConflictingTarget { target: usize },
+	/// The rooms of the adjacency target can never be next to each other:
+	/// they have no level in common, or the target is from a room type to
+	/// itself and there's only one room of that type
+	UnsatisfiableAdjacency { target: usize },
+	/// The genetic algorithm parameter with given name is out of its range
+	InvalidParameter { name: &'static str },
+	/// The objective weight with given name is not a non-negative number
+	InvalidObjectiveWeight { name: &'static str },
+	/// The mutation weight with given name is not a non-negative number
+	InvalidMutationWeight { name: &'static str },
+	/// There's no distance metric with the given name
+	UnknownDistanceMetric,
+	/// The number of levels is 0 or larger than genes::MAX_LEVELS
+	InvalidLevelCount { levels: usize },
+	/// The room type's list of allowed levels is empty
+	NoLevels { room: usize, key: usize },
+	/// The door has an unknown side, or it's on a corner or off the wall
+	InvalidDoor { room: usize, key: usize, door: usize },
+	/// The room type has more than doors::MAX_DOORS doors
+	TooManyDoors { room: usize, key: usize },
+	/// The room type's mask can't be parsed, its tiles aren't connected or
+	/// its size isn't the width and height of the room
+	InvalidMask { room: usize, key: usize },
+	/// The room type is allowed on a level that the blueprint doesn't have
+	InvalidLevel { room: usize, key: usize, level: usize },
+	/// The room type with key 0 has pins; it's always at the origin
+	PinnedKeyZero { room: usize },
+	/// The room type has more pins than its amount
+	TooManyPins { room: usize, key: usize },
+	/// The pinned room overlaps or touches room 0 or another pinned room on
+	/// the same level
+	OverlappingPin { room: usize, key: usize, pin: usize },
+	/// The build area is smaller than 1x1 or room 0 doesn't fit inside it
+	InvalidArea,
+	/// The pinned room is outside the build area
+	PinOutsideArea { room: usize, key: usize, pin: usize },
+	/// The pinned room is on blocked tiles of the site map
+	PinOnBlockedTile { room: usize, key: usize, pin: usize },
+	/// The room with key 0 is on blocked tiles of the site map at the origin
+	KeyZeroOnBlockedTile { room: usize },
+}
+
+impl Display for ReadError {
+	/// Human-readable description of the error
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match *self {
+			ReadError::Io(ref reason) => write!(f, "{}", reason),
+			ReadError::Json(ref reason) => write!(f, "invalid blueprint: {}",
+				reason),
+			ReadError::Invalid(ref errors) => {
+				try!(write!(f, "invalid blueprint:"));
+				for error in errors {
+					try!(write!(f, "\n  - {}", error));
+				}
+				Ok(())
+			}
+			ReadError::Site(ref reason) => write!(f, "invalid site map: {}",
+				reason),
+		}
+	}
+}
+
+impl Display for BlueprintError {
+	/// Human-readable description of the problem
+	fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+		match *self {
+			BlueprintError::DuplicateKey { room, key } => write!(f,
+				"room {}: key {} is already used by another room", room, key),
+			BlueprintError::InvalidSize { room, key } => write!(f,
+				"room {} (key {}): width and height must be at least 1", room,
+				key),
+			BlueprintError::ZeroAmount { room, key } => write!(f,
+				"room {} (key {}): amount must be at least 1", room, key),
+			BlueprintError::MissingKeyZero => write!(f,
+				"there must be a room with key 0"),
+			BlueprintError::InvalidKeyZeroAmount { room, amount } => write!(f,
+				"room {} (key 0): amount must be exactly 1, not {}", room,
+				amount),
+			BlueprintError::UnknownKey { target, key } => write!(f,
+				"target {}: there's no room with key {}", target, key),
+			BlueprintError::ReservedGlyph { room, key } => write!(f,
+				"room {} (key {}): glyph is reserved for walls, corridors or \
+				empty space", room, key),
+			BlueprintError::DuplicateGlyph { room, key } => write!(f,
+				"room {} (key {}): glyph is already used by another room",
+				room, key),
+			BlueprintError::InvalidWeight { target } => write!(f,
+				"target {}: weight must be a positive number", target),
+			BlueprintError::InvalidMinDistance { target } => write!(f,
+				"target {}: min_distance must be a positive number", target),
+			BlueprintError::ConflictingTarget { target } => write!(f,
+				"target {}: rooms can't be both adjacent and kept apart",
+				target),
+			BlueprintError::UnsatisfiableAdjacency { target } => write!(f,
+				"target {}: the rooms can never be next to each other", target),
+			BlueprintError::InvalidParameter { name } => write!(f,
+				"parameters: {} is out of range", name),
+			BlueprintError::InvalidObjectiveWeight { name } => write!(f,
+				"objective: {} must be zero or a positive number", name),
+			BlueprintError::InvalidMutationWeight { name } => write!(f,
+				"parameters: mutation weight {} must be zero or a positive \
+				number", name),
+			BlueprintError::UnknownDistanceMetric => write!(f,
+				"distance_metric must be \"euclidean\", \"manhattan\" or \
+				\"walking\""),
+			BlueprintError::InvalidLevelCount { levels } => write!(f,
+				"levels must be between 1 and {}, not {}", genes::MAX_LEVELS,
+				levels),
+			BlueprintError::NoLevels { room, key } => write!(f,
+				"room {} (key {}): levels must list at least one level", room,
+				key),
+			BlueprintError::InvalidLevel { room, key, level } => write!(f,
+				"room {} (key {}): there's no level {}", room, key, level),
+			BlueprintError::InvalidDoor { room, key, door } => write!(f,
+				"room {} (key {}): door {} must be on a side of the room and \
+				not on a corner or off the mask", room, key, door),
+			BlueprintError::TooManyDoors { room, key } => write!(f,
+				"room {} (key {}): a room can have at most {} doors", room, key,
+				doors::MAX_DOORS),
+			BlueprintError::InvalidMask { room, key } => write!(f,
+				"room {} (key {}): mask must have as many rows as the height \
+				and columns as the width of the room (at most {}), and \
+				connected '{}' tiles on every row and column", room, key,
+				masks::MAX_MASK_SIZE, masks::MASK_TILE),
+			BlueprintError::PinnedKeyZero { room } => write!(f,
+				"room {} (key 0): can't be pinned, it's always at the origin",
+				room),
+			BlueprintError::TooManyPins { room, key } => write!(f,
+				"room {} (key {}): there are more pins than rooms", room, key),
+			BlueprintError::OverlappingPin { room, key, pin } => write!(f,
+				"room {} (key {}): pin {} overlaps or touches another fixed \
+				room", room, key, pin),
+			BlueprintError::InvalidArea => write!(f,
+				"area must be at least 1x1 and room 0 must fit inside it"),
+			BlueprintError::PinOutsideArea { room, key, pin } => write!(f,
+				"room {} (key {}): pin {} is outside the area", room, key,
+				pin),
+			BlueprintError::PinOnBlockedTile { room, key, pin } => write!(f,
+				"room {} (key {}): pin {} is on blocked tiles of the site map",
+				room, key, pin),
+			BlueprintError::KeyZeroOnBlockedTile { room } => write!(f,
+				"room {} (key 0): is on blocked tiles of the site map at the \
+				origin", room),
+		}
+	}
+}
+
 /// A blueprint of a single target that the algorithm will aim for. Will be
-/// transformed into Target by Blueprint.compile()
+/// transformed into Target by Blueprint.compile(). If min_distance is given,
+/// the target keeps the rooms at least that far apart instead of pulling them
+/// together. If adjacent is true, every room of from_key must be right next to
+/// a room of to_key.
 #[derive(Copy, Clone, PartialEq, RustcDecodable, RustcEncodable)]
 pub struct TargetBlueprint {
     from_key: usize,
     to_key: usize,
     weight: f32,
+    min_distance: Option<f32>,
+    adjacent: Option<bool>,
+}
+
+/// A fixed position for one room. X and y are the top left corner of the room
+/// in the same coordinates as the layout, where the center of room 0 is at
+/// (0, 0). If rotated is true, the room's width and height are swapped. Level
+/// defaults to the lowest level the room is allowed on.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, RustcDecodable, RustcEncodable)]
+pub struct PinBlueprint {
+    pub x: isize,
+    pub y: isize,
+    pub rotated: Option<bool>,
+    pub level: Option<usize>,
+}
+
+/// A door of a room type. Side is "top", "bottom", "left", "right" or "any".
+/// Offset counts tiles from the left end of the top and bottom walls, or from
+/// the top end of the side walls, and defaults to the middle of the wall. A
+/// door on "any" side can be anywhere on the walls.
+#[derive(Clone, PartialEq, Eq, Debug, RustcDecodable, RustcEncodable)]
+pub struct DoorBlueprint {
+    pub side: String,
+    pub offset: Option<isize>,
 }
 
 /// A "blueprint" for a type of room; will be transformed into Gene by 
-/// Blueprint.compile()
-#[derive(Copy, Clone, PartialEq, Eq, RustcDecodable, RustcEncodable)]
+/// Blueprint.compile(). Name and glyph are optional and only used in output.
+/// Levels lists the z-levels the rooms may be on; if it's left out, the rooms
+/// may be on any level. Pins fix the positions of the first rooms of the type;
+/// the algorithm never moves pinned rooms. Doors are placed on the walls of
+/// the room as it's given here; rooms without doors can have them anywhere.
+/// A mask gives the shape of a room that isn't a rectangle, one string per row
+/// of tiles (see masks::Mask::parse).
+#[derive(Clone, PartialEq, Eq, RustcDecodable, RustcEncodable)]
 pub struct RoomBlueprint {
     key: usize,
     width: isize, //TODO: Refactor to usize
     height: isize,
     amount: usize,
+    name: Option<String>,
+    glyph: Option<char>,
+    levels: Option<Vec<usize>>,
+    pins: Option<Vec<PinBlueprint>>,
+    doors: Option<Vec<DoorBlueprint>>,
+    mask: Option<Vec<String>>,
+}
+
+impl TargetBlueprint {
+	/// Creates a new target blueprint
+	pub fn new(from_key: usize, to_key: usize, weight: f32) -> Self {
+		TargetBlueprint { from_key: from_key, to_key: to_key, weight: weight,
+			min_distance: None, adjacent: None }
+	}
+	/// Creates a new blueprint of a target that keeps rooms apart
+	pub fn new_repulsion(from_key: usize, to_key: usize, weight: f32,
+		min_distance: f32) -> Self {
+		TargetBlueprint { from_key: from_key, to_key: to_key, weight: weight,
+			min_distance: Some(min_distance), adjacent: None }
+	}
+	/// Creates a new blueprint of a target that puts rooms next to each other
+	pub fn new_adjacency(from_key: usize, to_key: usize, weight: f32) -> Self {
+		TargetBlueprint { from_key: from_key, to_key: to_key, weight: weight,
+			min_distance: None, adjacent: Some(true) }
+	}
+	/// Checks if the target puts its rooms next to each other
+	fn is_adjacency(&self) -> bool {
+		self.adjacent.unwrap_or(false)
+	}
+}
+
+impl DoorBlueprint {
+	/// Creates a new door blueprint on given side
+	pub fn new(side: &str, offset: Option<isize>) -> Self {
+		DoorBlueprint { side: side.to_string(), offset: offset }
+	}
+	/// Returns the door on a room of given size (walls included), or None if
+	/// the side is unknown
+	fn door(&self, width: isize, height: isize) -> Option<Door> {
+		let middle = |length: isize| self.offset.unwrap_or(length / 2);
+		match &self.side[..] {
+			"top" => Some(Door::At(Up, middle(width))),
+			"bottom" => Some(Door::At(Down, middle(width))),
+			"left" => Some(Door::At(Left, middle(height))),
+			"right" => Some(Door::At(Right, middle(height))),
+			"any" => Some(Door::Anywhere),
+			_ => None,
+		}
+	}
+}
+
+impl RoomBlueprint {
+	/// Creates a new room blueprint without a name or a glyph
+	pub fn new(key: usize, width: isize, height: isize, amount: usize) -> Self {
+		RoomBlueprint {
+			key: key,
+			width: width,
+			height: height,
+			amount: amount,
+			name: None,
+			glyph: None,
+			levels: None,
+			pins: None,
+			doors: None,
+			mask: None,
+		}
+	}
+	/// Creates a new room blueprint with given rooms pinned in place
+	pub fn new_pinned(key: usize, width: isize, height: isize, amount: usize,
+		pins: Vec<PinBlueprint>) -> Self {
+		let mut room = RoomBlueprint::new(key, width, height, amount);
+		room.pins = Some(pins);
+		room
+	}
+	/// Returns the levels these rooms may be on as bits set in a mask, given
+	/// the number of levels in the blueprint
+	pub fn allowed_levels(&self, level_count: usize) -> u32 {
+		match self.levels {
+			Some(ref levels) => {
+				let mut mask = 0;
+				// Levels that don't fit in the mask are reported by validate
+				for &level in levels.iter().filter(|&&l| l < genes::MAX_LEVELS) {
+					mask |= 1 << level;
+				}
+				mask
+			}
+			None if level_count >= genes::MAX_LEVELS => !0,
+			None => (1 << level_count) - 1,
+		}
+	}
+	/// Checks that the levels listed for these rooms exist
+	fn has_valid_levels(&self, level_count: usize) -> bool {
+		match self.levels {
+			Some(ref levels) => levels.iter().all(|&level| level < level_count),
+			None => true,
+		}
+	}
+	/// Creates a new room blueprint with given doors
+	pub fn new_with_doors(key: usize, width: isize, height: isize,
+		amount: usize, doors: Vec<DoorBlueprint>) -> Self {
+		let mut room = RoomBlueprint::new(key, width, height, amount);
+		room.doors = Some(doors);
+		room
+	}
+	/// Returns the doors of these rooms. Doors that don't fit on the room are
+	/// left out; validate reports them.
+	pub fn doors(&self) -> Doors {
+		let mut doors = Doors::new();
+		if let Some(ref blueprints) = self.doors {
+			for door in blueprints {
+				match door.door(self.width, self.height) {
+					Some(door) if self.has_room_for(&door) => {
+						doors.push(door);
+					}
+					_ => {}
+				}
+			}
+		}
+		doors
+	}
+	/// Checks that the door is on a wall of these rooms, and not on a corner
+	/// or on a part of the rectangle that the mask leaves out
+	fn has_room_for(&self, door: &Door) -> bool {
+		let rect = Rect::new(0, 0, self.width, self.height);
+		door.fits(self.width, self.height) &&
+			door.tile(rect).map_or(true, |tile| self.shape().contains(rect, tile))
+	}
+	/// Creates a new room blueprint whose rooms have the shape of given mask
+	pub fn new_with_mask(key: usize, width: isize, height: isize,
+		amount: usize, mask: Vec<String>) -> Self {
+		let mut room = RoomBlueprint::new(key, width, height, amount);
+		room.mask = Some(mask);
+		room
+	}
+	/// Returns the shape of these rooms: the mask if one is given, otherwise
+	/// a rectangle. An invalid mask is left out; validate reports it.
+	pub fn shape(&self) -> Shape {
+		match self.mask.as_ref().and_then(|lines| Mask::parse(lines)) {
+			Some(mask) if mask.get_w() == self.width &&
+			              mask.get_h() == self.height => Shape::Masked(mask),
+			_ => Shape::Rectangle,
+		}
+	}
+	/// Returns this room blueprint as a room type for the output
+	pub fn room_type(&self) -> RoomType {
+		let name = match self.name {
+			Some(ref name) => name.clone(),
+			None => format!("Room {}", self.key),
+		};
+		let glyph = self.glyph.unwrap_or(rooms::default_glyph(self.key));
+		RoomType::new(self.key, name, glyph)
+	}
+	/// Returns the gene of the room of key 0 centered on the origin, given the
+	/// number of levels in the blueprint
+	fn origin_gene(&self, level_count: usize) -> Gene {
+		let rect = Rect::new(0, 0, self.width + 1, self.height + 1);
+		let mut gene = Gene::new_with_levels(rect, 0, 0,
+			self.allowed_levels(level_count).max(1));
+		gene.set_shape(self.shape());
+		gene.set_center(0, 0);
+		gene
+	}
+	/// Returns the gene of the room fixed by given pin, given the number of
+	/// levels in the blueprint
+	fn pinned_gene(&self, pin: &PinBlueprint, id: isize, level_count: usize)
+		-> Gene {
+		let level = match pin.level {
+			Some(level) => level,
+			None => self.allowed_levels(level_count).trailing_zeros() as usize,
+		};
+		let rect = Rect::new(pin.x, pin.y, self.width + 1, self.height + 1);
+		let mut gene = Gene::new_with_levels(rect, id, self.key, 1 << level);
+		gene.set_doors(self.doors());
+		gene.set_shape(self.shape());
+		if pin.rotated.unwrap_or(false) {
+			gene.rot_in_place();
+		}
+		gene.set_pinned(true);
+		gene
+	}
+}
+
+/// The maximum area a layout may take. X and y are the top left corner of the
+/// area as an offset from the center of room 0; if they are left out, the area
+/// is centered on room 0.
+#[derive(Copy, Clone, PartialEq, Eq, Debug, RustcDecodable, RustcEncodable)]
+pub struct AreaBlueprint {
+    pub width: isize,
+    pub height: isize,
+    pub x: Option<isize>,
+    pub y: Option<isize>,
+}
+
+/// Optional genetic algorithm parameters given in a blueprint. Parameters that
+/// are left out get their default values from GaConfig::new()
+#[derive(Copy, Clone, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct ParameterBlueprint {
+    pub crossover_chance: Option<f32>,
+    pub mutation_chance: Option<f32>,
+    pub keep_alive_percentage: Option<f32>,
+    pub purge_percentage: Option<f32>,
+    pub purge_interval: Option<usize>,
+    pub threads: Option<usize>,
+    pub islands: Option<usize>,
+    pub migration_interval: Option<usize>,
+    pub migrants: Option<usize>,
+    /// Relative probabilities of the mutation types
+    pub mutation_weights: Option<MutationWeightBlueprint>,
+}
+
+/// Optional relative probabilities of the mutation types (see
+/// config::MutationWeights). Weights that are left out get their default
+/// values.
+#[derive(Copy, Clone, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct MutationWeightBlueprint {
+    pub rotation: Option<f32>,
+    pub flip: Option<f32>,
+    pub position: Option<f32>,
+    pub nudge: Option<f32>,
+    pub swap: Option<f32>,
+    pub slide: Option<f32>,
+    pub level: Option<f32>,
+}
+
+/// Optional weights of the parts of the composite objective (see
+/// fitness::CompositeFitness). If the objective is given, weights that are left
+/// out get their default values; otherwise the layout is only scored by target
+/// distances, or by area if there are no targets.
+#[derive(Copy, Clone, PartialEq, RustcDecodable, RustcEncodable)]
+pub struct ObjectiveBlueprint {
+    pub distance: Option<f32>,
+    pub utilization: Option<f32>,
+    pub perimeter: Option<f32>,
 }
 
 /// Blueprint is a collection of blueprints for rooms and targets that can be
@@ -41,23 +503,385 @@ pub struct Blueprint {
     // except this UI code, honest, gov'nor.
     pub rooms: Vec<RoomBlueprint>,
     pub targets: Vec<TargetBlueprint>,
+    pub parameters: Option<ParameterBlueprint>,
+    /// Number of z-levels the layout may span; 1 if left out
+    pub levels: Option<usize>,
+    /// Area the layout must fit in; unbounded if left out
+    pub area: Option<AreaBlueprint>,
+    /// Weights of the composite objective; not used if left out
+    pub objective: Option<ObjectiveBlueprint>,
+    /// How the distances of attraction targets are measured: "euclidean",
+    /// "manhattan" or "walking"; euclidean if left out
+    pub distance_metric: Option<String>,
 }
 
 impl Blueprint {
+	/// Creates a new blueprint with given rooms and targets and default
+	/// parameters
+	pub fn new(rooms: Vec<RoomBlueprint>, targets: Vec<TargetBlueprint>) -> Self {
+		Blueprint {
+			rooms: rooms,
+			targets: targets,
+			parameters: None,
+			levels: None,
+			area: None,
+			objective: None,
+			distance_metric: None,
+		}
+	}
+	/// Gives the area the layout must fit in, in layout coordinates
+	pub fn area(&self) -> Option<Rect> {
+		self.area.map(|area| Rect::new(
+			area.x.unwrap_or(-area.width / 2),
+			area.y.unwrap_or(-area.height / 2),
+			area.width, area.height))
+	}
+	/// Gives the number of z-levels the layout may span
+	pub fn level_count(&self) -> usize {
+		self.levels.unwrap_or(1)
+	}
+	/// Gives the metric that the distances of attraction targets are measured
+	/// with. Unknown metrics are reported by validate, and fall back to the
+	/// Euclidean metric here.
+	pub fn distance_metric(&self) -> DistanceMetric {
+		self.distance_metric.as_ref()
+			.and_then(|name| DistanceMetric::from_name(name))
+			.unwrap_or(DistanceMetric::Euclidean)
+	}
+	/// Checks that the blueprint makes sense: room keys are unique, rooms have
+	/// a positive size and amount, there's exactly one room of key 0, rooms
+	/// are only allowed on existing levels, pinned rooms don't overlap and fit
+	/// in the area and targets refer to existing rooms with positive weights.
+	/// Returns all the problems found.
+	pub fn validate(&self) -> Result<(), Vec<BlueprintError>> {
+		let mut errors = Vec::new();
+		let level_count = self.level_count();
+		if level_count == 0 || level_count > genes::MAX_LEVELS {
+			errors.push(BlueprintError::InvalidLevelCount {
+				levels: level_count });
+		}
+		if let Some(area) = self.area {
+			if area.width < 1 || area.height < 1 {
+				errors.push(BlueprintError::InvalidArea);
+			}
+		}
+		let mut keys = HashMap::new();
+		let mut glyphs = HashMap::new();
+		for i in 0..self.rooms.len() {
+			let room = &self.rooms[i];
+			if keys.contains_key(&room.key) {
+				errors.push(BlueprintError::DuplicateKey {
+					room: i, key: room.key });
+			} else {
+				keys.insert(room.key, i);
+			}
+			// Default glyphs may repeat (see rooms::default_glyph), so only
+			// the glyphs given in the blueprint have to be unique
+			if let Some(glyph) = room.glyph {
+				if rooms::is_reserved_glyph(glyph) {
+					errors.push(BlueprintError::ReservedGlyph {
+						room: i, key: room.key });
+				} else if glyphs.contains_key(&glyph) {
+					errors.push(BlueprintError::DuplicateGlyph {
+						room: i, key: room.key });
+				} else {
+					glyphs.insert(glyph, i);
+				}
+			}
+			if room.width < 1 || room.height < 1 {
+				errors.push(BlueprintError::InvalidSize { room: i, key: room.key });
+			}
+			if room.key == 0 {
+				if room.amount != 1 {
+					errors.push(BlueprintError::InvalidKeyZeroAmount {
+						room: i, amount: room.amount });
+				}
+			} else if room.amount == 0 {
+				errors.push(BlueprintError::ZeroAmount { room: i, key: room.key });
+			}
+			if let Some(ref levels) = room.levels {
+				if levels.len() == 0 {
+					errors.push(BlueprintError::NoLevels { room: i, key: room.key });
+				}
+				for &level in levels {
+					if level >= level_count.min(genes::MAX_LEVELS) {
+						errors.push(BlueprintError::InvalidLevel {
+							room: i, key: room.key, level: level });
+					}
+				}
+			}
+			if room.mask.is_some() && room.shape().is_rectangle() {
+				errors.push(BlueprintError::InvalidMask { room: i, key: room.key });
+			}
+			if let Some(ref doors) = room.doors {
+				for (d, door) in doors.iter().enumerate() {
+					let fits = door.door(room.width, room.height)
+						.map_or(false, |door| room.has_room_for(&door));
+					if !fits {
+						errors.push(BlueprintError::InvalidDoor {
+							room: i, key: room.key, door: d });
+					}
+				}
+				if doors.len() > doors::MAX_DOORS {
+					errors.push(BlueprintError::TooManyDoors {
+						room: i, key: room.key });
+				}
+			}
+		}
+		if !keys.contains_key(&0) {
+			errors.push(BlueprintError::MissingKeyZero);
+		}
+		self.validate_pins(&mut errors);
+		for i in 0..self.targets.len() {
+			let target = self.targets[i];
+			if !keys.contains_key(&target.from_key) {
+				errors.push(BlueprintError::UnknownKey {
+					target: i, key: target.from_key });
+			}
+			if !keys.contains_key(&target.to_key) {
+				errors.push(BlueprintError::UnknownKey {
+					target: i, key: target.to_key });
+			}
+			if !(target.weight > 0.0) || !target.weight.is_finite() {
+				errors.push(BlueprintError::InvalidWeight { target: i });
+			}
+			if let Some(min_distance) = target.min_distance {
+				if !(min_distance > 0.0) || !min_distance.is_finite() {
+					errors.push(BlueprintError::InvalidMinDistance { target: i });
+				}
+			}
+			if target.is_adjacency() {
+				if target.min_distance.is_some() {
+					errors.push(BlueprintError::ConflictingTarget { target: i });
+				}
+				let rooms = (keys.get(&target.from_key), keys.get(&target.to_key));
+				if let (Some(&from), Some(&to)) = rooms {
+					if !self.can_be_adjacent(from, to) {
+						errors.push(BlueprintError::UnsatisfiableAdjacency {
+							target: i });
+					}
+				}
+			}
+		}
+		let config = self.config();
+		let chances = [("crossover_chance", config.crossover_chance),
+			("mutation_chance", config.mutation_chance),
+			("keep_alive_percentage", config.keep_alive_percentage),
+			("purge_percentage", config.purge_percentage)];
+		for &(name, value) in chances.iter() {
+			if !config::is_valid_chance(value) {
+				errors.push(BlueprintError::InvalidParameter { name: name });
+			}
+		}
+		if config.purge_interval == 0 {
+			errors.push(BlueprintError::InvalidParameter {
+				name: "purge_interval" });
+		}
+		let counts = [("threads", config.threads),
+			("islands", config.islands),
+			("migration_interval", config.migration_interval)];
+		for &(name, value) in counts.iter() {
+			if value == 0 {
+				errors.push(BlueprintError::InvalidParameter { name: name });
+			}
+		}
+		let weights = config.mutation_weights;
+		let weights = [("rotation", weights.rotation), ("flip", weights.flip),
+			("position", weights.position), ("nudge", weights.nudge),
+			("swap", weights.swap), ("slide", weights.slide),
+			("level", weights.level)];
+		for &(name, weight) in weights.iter() {
+			if !(weight >= 0.0) || !weight.is_finite() {
+				errors.push(BlueprintError::InvalidMutationWeight {
+					name: name });
+			}
+		}
+		if let Some(objective) = self.objective {
+			let weights = [("distance", objective.distance),
+				("utilization", objective.utilization),
+				("perimeter", objective.perimeter)];
+			for &(name, weight) in weights.iter() {
+				if let Some(weight) = weight {
+					if !(weight >= 0.0) || !weight.is_finite() {
+						errors.push(BlueprintError::InvalidObjectiveWeight {
+							name: name });
+					}
+				}
+			}
+		}
+		if let Some(ref name) = self.distance_metric {
+			if DistanceMetric::from_name(name).is_none() {
+				errors.push(BlueprintError::UnknownDistanceMetric);
+			}
+		}
+		if errors.len() == 0 {
+			Ok(())
+		} else {
+			Err(errors)
+		}
+	}
+	/// Checks if rooms of the room types at given indexes can ever be next to
+	/// each other. Rooms with invalid levels are let through; they are
+	/// reported elsewhere.
+	fn can_be_adjacent(&self, from: usize, to: usize) -> bool {
+		let level_count = self.level_count();
+		let (from, to) = (&self.rooms[from], &self.rooms[to]);
+		if level_count == 0 || level_count > genes::MAX_LEVELS ||
+		   !from.has_valid_levels(level_count) ||
+		   !to.has_valid_levels(level_count) {
+			return true;
+		}
+		let common = from.allowed_levels(level_count) &
+			to.allowed_levels(level_count);
+		common != 0 && (from.key != to.key || from.amount > 1)
+	}
+	/// Checks the pins of the rooms. Overlaps are only looked for when the
+	/// pins are otherwise valid.
+	fn validate_pins(&self, errors: &mut Vec<BlueprintError>) {
+		let level_count = self.level_count().min(genes::MAX_LEVELS);
+		let mut fixed: Vec<Gene> = Vec::new();
+		for i in 0..self.rooms.len() {
+			let room = &self.rooms[i];
+			if room.key == 0 && room.width > 0 && room.height > 0 &&
+				room.has_valid_levels(level_count) {
+				let gene = room.origin_gene(level_count);
+				if !self.area_contains(&gene) &&
+				   !errors.contains(&BlueprintError::InvalidArea) {
+					errors.push(BlueprintError::InvalidArea);
+				}
+				fixed.push(gene);
+			}
+		}
+		for i in 0..self.rooms.len() {
+			let room = &self.rooms[i];
+			let pins = match room.pins {
+				Some(ref pins) if pins.len() > 0 => pins,
+				_ => continue,
+			};
+			if room.key == 0 {
+				errors.push(BlueprintError::PinnedKeyZero { room: i });
+				continue;
+			}
+			if !room.has_valid_levels(level_count) {
+				continue;
+			}
+			if pins.len() > room.amount {
+				errors.push(BlueprintError::TooManyPins {
+					room: i, key: room.key });
+			}
+			let allowed = room.allowed_levels(level_count);
+			for p in 0..pins.len() {
+				if let Some(level) = pins[p].level {
+					if level >= level_count.min(genes::MAX_LEVELS) ||
+					   allowed & (1 << level) == 0 {
+						errors.push(BlueprintError::InvalidLevel {
+							room: i, key: room.key, level: level });
+						continue;
+					}
+				} else if allowed == 0 {
+					continue;
+				}
+				if room.width < 1 || room.height < 1 {
+					continue;
+				}
+				let gene = room.pinned_gene(&pins[p], 0, level_count);
+				if !self.area_contains(&gene) {
+					errors.push(BlueprintError::PinOutsideArea {
+						room: i, key: room.key, pin: p });
+				}
+				if fixed.iter().any(|other| other.get_z() == gene.get_z() &&
+					other.collides_with(gene)) {
+					errors.push(BlueprintError::OverlappingPin {
+						room: i, key: room.key, pin: p });
+				} else {
+					fixed.push(gene);
+				}
+			}
+		}
+	}
+	/// Checks that room 0 at the origin and the pinned rooms of a valid
+	/// blueprint (see validate) aren't on blocked tiles of given site. These
+	/// rooms are never moved, so the layout couldn't fit the site otherwise.
+	/// The site isn't known when the blueprint is read, so it's checked
+	/// separately. Returns all the problems found.
+	pub fn validate_on_site(&self, site: &Site)
+		-> Result<(), Vec<BlueprintError>> {
+		let mut errors = Vec::new();
+		for i in 0..self.rooms.len() {
+			let room = &self.rooms[i];
+			if room.key == 0 {
+				let gene = room.origin_gene(self.level_count());
+				let blocked = site.blocked_tile_under(gene.room_rect(),
+					&gene.get_shape(), gene.get_z());
+				if blocked.is_some() {
+					errors.push(BlueprintError::KeyZeroOnBlockedTile { room: i });
+				}
+			}
+			let pins = match room.pins {
+				Some(ref pins) => pins,
+				None => continue,
+			};
+			for p in 0..pins.len() {
+				let gene = room.pinned_gene(&pins[p], 0, self.level_count());
+				let blocked = site.blocked_tile_under(gene.room_rect(),
+					&gene.get_shape(), gene.get_z());
+				if blocked.is_some() {
+					errors.push(BlueprintError::PinOnBlockedTile {
+						room: i, key: room.key, pin: p });
+				}
+			}
+		}
+		if errors.is_empty() {
+			Ok(())
+		} else {
+			Err(errors)
+		}
+	}
+	/// Checks if the room of given gene is inside the area. Everything is
+	/// inside if there's no area.
+	fn area_contains(&self, gene: &Gene) -> bool {
+		match self.area() {
+			Some(area) => {
+				let bottom_right = gene.bottom_right();
+				// The gene has a gap of one tile after the room
+				gene.get_x() >= area.x && gene.get_y() >= area.y &&
+					bottom_right.x - 1 <= area.x + area.w &&
+					bottom_right.y - 1 <= area.y + area.h
+			}
+			None => true,
+		}
+	}
 	/// Creates two vectors, one containing genes and one containing targets, as
 	/// specified by the gene- and target blueprints inside this blueprint.
+	/// The room of key 0 always becomes gene 0, on the first of its levels.
+	/// Pinned rooms become pinned genes at their fixed positions.
+	/// # Panics
+	/// Panics if the blueprint is invalid (see validate)
 	pub fn compile(&self) -> (Vector<Gene>, Vector<Target>) {
 		let mut key_to_id = HashMap::new();
 		let mut genes = Vector::new(); //TODO: new_with_size?
 		let mut targets = Vector::new();
 		let mut current_id: usize = 0;
-		for i in 0..self.rooms.len() {
+		for i in self.key_zero_first() {
 			let mut ids = Vector::new();
-			let room = self.rooms[i];
+			let room = &self.rooms[i];
 			// Gene size is blueprint size + 1 (for room between genes)
 			let rect = Rect::new(0, 0, room.width + 1, room.height + 1);
-			for _ in 0..self.rooms[i].amount {
-				genes.push(Gene::new(rect, current_id as isize));
+			let pins = match room.pins {
+				Some(ref pins) => &pins[..],
+				None => &[],
+			};
+			for j in 0..room.amount {
+				if j < pins.len() {
+					genes.push(room.pinned_gene(&pins[j], current_id as isize,
+						self.level_count()));
+				} else {
+					let mut gene = Gene::new_with_levels(rect, current_id as isize,
+						room.key, room.allowed_levels(self.level_count()));
+					gene.set_doors(room.doors());
+					gene.set_shape(room.shape());
+					genes.push(gene);
+				}
 				ids.push(current_id);
 				current_id += 1;
 			}
@@ -67,26 +891,132 @@ impl Blueprint {
 			let target = self.targets[i];
 			let from = key_to_id.get(&target.from_key).unwrap().clone();
 			let to = key_to_id.get(&target.to_key).unwrap().clone();
-			targets.push(Target::new(from, to, target.weight));
+			targets.push(match target.min_distance {
+				Some(min_distance) => Target::new_repulsion(from, to,
+					target.weight, min_distance),
+				None if target.is_adjacency() => Target::new_adjacency(from, to,
+					target.weight),
+				None => Target::new(from, to, target.weight),
+			});
 		}
 		(genes, targets)
 	}
+	/// Returns the room types of this blueprint (with their names and
+	/// glyphs) in the same order as the rooms are listed
+	pub fn room_types(&self) -> Vector<RoomType> {
+		let mut types = Vector::new_with_size(self.rooms.len());
+		for i in 0..self.rooms.len() {
+			types.push(self.rooms[i].room_type());
+		}
+		types
+	}
+	/// Creates the fitness function that layouts of this blueprint are scored
+	/// by, given the compiled targets: a CompositeFitness if the blueprint has
+	/// an objective, otherwise the default for the targets
+	pub fn fitness_function(&self, targets: &Vector<Target>)
+		-> Box<FitnessFunction> {
+		match self.objective {
+			Some(objective) => Box::new(CompositeFitness::new(
+				objective.distance.unwrap_or(fitness::DEFAULT_DISTANCE_WEIGHT),
+				objective.utilization.unwrap_or(
+					fitness::DEFAULT_UTILIZATION_WEIGHT),
+				objective.perimeter.unwrap_or(
+					fitness::DEFAULT_PERIMETER_WEIGHT),
+				self.distance_metric())),
+			None => fitness::default_for(targets, self.distance_metric()),
+		}
+	}
+	/// Creates a genetic algorithm config from the parameters of this
+	/// blueprint. Parameters that aren't given are left to their defaults.
+	pub fn config(&self) -> GaConfig {
+		let mut config = GaConfig::new();
+		if let Some(parameters) = self.parameters {
+			if let Some(value) = parameters.crossover_chance {
+				config.crossover_chance = value;
+			}
+			if let Some(value) = parameters.mutation_chance {
+				config.mutation_chance = value;
+			}
+			if let Some(value) = parameters.keep_alive_percentage {
+				config.keep_alive_percentage = value;
+			}
+			if let Some(value) = parameters.purge_percentage {
+				config.purge_percentage = value;
+			}
+			if let Some(value) = parameters.purge_interval {
+				config.purge_interval = value;
+			}
+			if let Some(value) = parameters.threads {
+				config.threads = value;
+			}
+			if let Some(value) = parameters.islands {
+				config.islands = value;
+			}
+			if let Some(value) = parameters.migration_interval {
+				config.migration_interval = value;
+			}
+			if let Some(value) = parameters.migrants {
+				config.migrants = value;
+			}
+			if let Some(weights) = parameters.mutation_weights {
+				let defaults = config.mutation_weights;
+				config.mutation_weights = MutationWeights {
+					rotation: weights.rotation.unwrap_or(defaults.rotation),
+					flip: weights.flip.unwrap_or(defaults.flip),
+					position: weights.position.unwrap_or(defaults.position),
+					nudge: weights.nudge.unwrap_or(defaults.nudge),
+					swap: weights.swap.unwrap_or(defaults.swap),
+					slide: weights.slide.unwrap_or(defaults.slide),
+					level: weights.level.unwrap_or(defaults.level),
+				};
+			}
+		}
+		config
+	}
+	/// Returns the indexes of the room blueprints so that the room of key 0
+	/// comes first
+	fn key_zero_first(&self) -> Vec<usize> {
+		let mut order: Vec<usize> = (0..self.rooms.len()).collect();
+		order.sort_by_key(|i| self.rooms[*i].key != 0);
+		order
+	}
 }
 
 /// Read a blueprint from a JSON file with given filename
-pub fn read(filename: String) -> Blueprint {
+pub fn read(filename: String) -> Result<Blueprint, ReadError> {
+    let path = Path::new(&filename);
+    let mut file = match File::open(&path) {
+        Err(reason) => return Err(ReadError::Io(reason)),
+        Ok(file) => file
+    };
+    let mut data = String::new();
+    match file.read_to_string(&mut data) {
+        Err(reason) => return Err(ReadError::Io(reason)),
+        Ok(_) => {}
+    }
+    let blueprint: Blueprint = match json::decode(&data) {
+        Err(reason) => return Err(ReadError::Json(reason)),
+        Ok(blueprint) => blueprint
+    };
+    match blueprint.validate() {
+        Err(errors) => Err(ReadError::Invalid(errors)),
+        Ok(_) => Ok(blueprint)
+    }
+}
+
+/// Reads a site map from given text file (see Site::parse)
+pub fn read_site(filename: String) -> Result<Site, ReadError> {
     let path = Path::new(&filename);
     let mut file = match File::open(&path) {
-        Err(reason) => panic!("Couldn't open {}: {:?}", filename, reason),
+        Err(reason) => return Err(ReadError::Io(reason)),
         Ok(file) => file
     };
     let mut data = String::new();
     match file.read_to_string(&mut data) {
-        Err(reason) => panic!("Couldn't read {}: {:?}", filename, reason),
+        Err(reason) => return Err(ReadError::Io(reason)),
         Ok(_) => {}
     }
-    let blueprint: Blueprint = json::decode(&data).unwrap();
-    blueprint
+    Site::parse(&data).map_err(ReadError::Site)
 }
 
 /// Checks if a file exists
@@ -100,54 +1030,36 @@ mod tests {
 
     use super::*;
     use io::output::save;
+    use genetics::genes::{Chromosome, TargetKind};
+    use genetics::config::GaConfig;
+    use mapping::masks::tests::lines;
     use rustc_serialize::json;
 
     #[test]
     fn read_works() {
-        let tbp1 = TargetBlueprint {
-            from_key: 0,
-            to_key: 1,
-            weight: 1.2,
-        };
-        let tbp2 = TargetBlueprint {
-            from_key: 1,
-            to_key: 2,
-            weight: 1.1,
-        };
-        let rbp0 = RoomBlueprint {
-            key: 0,
-            width: 3,
-            height: 3,
-            amount: 1,
-        };
-        let rbp1 = RoomBlueprint {
-            key: 1,
-            width: 5,
-            height: 5,
-            amount: 3,
-        };
-        let rbp2 = RoomBlueprint {
-            key: 2,
-            width: 6,
-            height: 2,
-            amount: 2,
-        };
-        let bp = Blueprint {
-            rooms: vec![rbp0, rbp1, rbp2],
-            targets: vec![tbp1, tbp2],
-        };
+        let tbp1 = TargetBlueprint::new(0, 1, 1.2);
+        let tbp2 = TargetBlueprint::new(1, 2, 1.1);
+        let rbp0 = RoomBlueprint::new(0, 3, 3, 1);
+        let rbp1 = RoomBlueprint::new(1, 5, 5, 3);
+        let rbp2 = RoomBlueprint::new(2, 6, 2, 2);
+        let mut rbp3 = RoomBlueprint::new(3, 4, 4, 1);
+        rbp3.name = Some(String::from("Dining hall"));
+        rbp3.glyph = Some('d');
+        let bp = Blueprint::new(vec![rbp0.clone(), rbp1.clone(), rbp2.clone(),
+                                     rbp3.clone()], vec![tbp1, tbp2]);
         let encoded_bp = json::encode(&bp).unwrap();
         match save(encoded_bp, String::from("test.json")) {
         	Ok(_) => {},
         	Err(reason) => panic!("{:?}", reason)
         };
-        let read_bp = read(String::from("test.json"));
+        let read_bp = read(String::from("test.json")).unwrap();
         assert_eq!(read_bp.rooms.len(), bp.rooms.len());
         assert_eq!(read_bp.targets.len(), bp.targets.len());
         for i in 0..read_bp.rooms.len() {
         	assert!(read_bp.rooms[i] == rbp0 ||
         		read_bp.rooms[i] == rbp1 ||
-        		read_bp.rooms[i] == rbp2
+        		read_bp.rooms[i] == rbp2 ||
+        		read_bp.rooms[i] == rbp3
         	)
         }
         for i in 0..read_bp.targets.len() {
@@ -156,4 +1068,343 @@ mod tests {
         	)
         }
     }
+
+    #[test]
+    fn read_fails_gracefully() {
+        match read(String::from("this_file_does_not_exist.json")) {
+            Err(ReadError::Io(_)) => {},
+            _ => panic!("Reading a missing file should give an IO error")
+        }
+        match save(String::from("{\"rooms\": ["), String::from("broken.json")) {
+        	Ok(_) => {},
+        	Err(reason) => panic!("{:?}", reason)
+        };
+        match read(String::from("broken.json")) {
+            Err(ReadError::Json(_)) => {},
+            _ => panic!("Reading broken JSON should give a JSON error")
+        }
+    }
+
+    #[test]
+    fn validate_accepts_valid_blueprint() {
+        let bp = Blueprint::new(vec![
+                RoomBlueprint::new(1, 5, 5, 3),
+                RoomBlueprint::new(0, 3, 3, 1),
+            ], vec![TargetBlueprint::new(0, 1, 1.0),
+                    TargetBlueprint::new_repulsion(1, 1, 2.0, 12.0),
+                    TargetBlueprint::new_adjacency(1, 1, 1.0)]);
+        assert_eq!(Ok(()), bp.validate());
+        let (genes, targets) = bp.compile();
+        assert_eq!(4, genes[0].get_w());
+        assert_eq!(TargetKind::Attract, targets[0].kind);
+        assert_eq!(TargetKind::Repel(12.0), targets[1].kind);
+        assert_eq!(TargetKind::Adjacent, targets[2].kind);
+    }
+
+    #[test]
+    fn default_glyphs_may_repeat() {
+        let mut rbp2 = RoomBlueprint::new(2, 2, 2, 1);
+        rbp2.glyph = Some('.');
+        let bp = Blueprint::new(vec![
+                RoomBlueprint::new(0, 2, 2, 1),
+                RoomBlueprint::new(1, 2, 2, 1),
+                rbp2,
+                RoomBlueprint::new(62, 2, 2, 1),
+                RoomBlueprint::new(63, 2, 2, 1),
+            ], vec![]);
+        // Keys 62 and 63 both fall back to the same glyph as key 2 is given
+        assert_eq!(Ok(()), bp.validate());
+    }
+
+    #[test]
+    fn validate_finds_all_errors() {
+        let mut rbp3 = RoomBlueprint::new(3, 2, 2, 1);
+        rbp3.glyph = Some('#');
+        let mut rbp2 = RoomBlueprint::new(2, -1, 5, 1);
+        rbp2.glyph = Some('x');
+        let mut rbp4 = RoomBlueprint::new(4, 2, 2, 1);
+        rbp4.glyph = Some('x');
+        let mut tbp3 = TargetBlueprint::new_adjacency(2, 2, 1.0);
+        tbp3.min_distance = Some(3.0);
+        let mut bp = Blueprint::new(vec![
+                RoomBlueprint::new(1, 5, 0, 3),
+                RoomBlueprint::new(1, 5, 5, 0),
+                rbp2,
+                rbp3,
+                rbp4,
+            ], vec![
+                TargetBlueprint::new(1, 2, 1.0),
+                TargetBlueprint::new(7, 1, -1.0),
+                TargetBlueprint::new_repulsion(2, 2, 1.0, 0.0),
+                tbp3,
+            ]);
+        bp.parameters = Some(ParameterBlueprint {
+            crossover_chance: None,
+            mutation_chance: Some(1.5),
+            keep_alive_percentage: None,
+            purge_percentage: None,
+            purge_interval: Some(0),
+            threads: Some(0),
+            islands: None,
+            migration_interval: Some(0),
+            migrants: Some(5),
+            mutation_weights: Some(MutationWeightBlueprint {
+                rotation: None,
+                flip: Some(0.0),
+                position: None,
+                nudge: Some(-1.0),
+                swap: None,
+                slide: None,
+                level: None,
+            }),
+        });
+        bp.objective = Some(ObjectiveBlueprint {
+            distance: Some(1.0),
+            utilization: Some(-0.5),
+            perimeter: None,
+        });
+        bp.distance_metric = Some("taxicab".to_string());
+        let errors = bp.validate().unwrap_err();
+        assert_eq!(vec![
+            BlueprintError::InvalidSize { room: 0, key: 1 },
+            BlueprintError::DuplicateKey { room: 1, key: 1 },
+            BlueprintError::ZeroAmount { room: 1, key: 1 },
+            BlueprintError::InvalidSize { room: 2, key: 2 },
+            BlueprintError::ReservedGlyph { room: 3, key: 3 },
+            BlueprintError::DuplicateGlyph { room: 4, key: 4 },
+            BlueprintError::MissingKeyZero,
+            BlueprintError::UnknownKey { target: 1, key: 7 },
+            BlueprintError::InvalidWeight { target: 1 },
+            BlueprintError::InvalidMinDistance { target: 2 },
+            BlueprintError::ConflictingTarget { target: 3 },
+            BlueprintError::UnsatisfiableAdjacency { target: 3 },
+            BlueprintError::InvalidParameter { name: "mutation_chance" },
+            BlueprintError::InvalidParameter { name: "purge_interval" },
+            BlueprintError::InvalidParameter { name: "threads" },
+            BlueprintError::InvalidParameter { name: "migration_interval" },
+            BlueprintError::InvalidMutationWeight { name: "nudge" },
+            BlueprintError::InvalidObjectiveWeight { name: "utilization" },
+            BlueprintError::UnknownDistanceMetric,
+        ], errors);
+    }
+
+    #[test]
+    fn doors_are_validated_and_compiled() {
+        let doors = vec![DoorBlueprint::new("top", Some(1)),
+                         DoorBlueprint::new("left", None)];
+        let mut rbp1 = RoomBlueprint::new_with_doors(1, 4, 5, 2, doors);
+        rbp1.pins = Some(vec![
+            PinBlueprint { x: 3, y: 3, rotated: Some(true), level: None }]);
+        let bp = Blueprint::new(vec![RoomBlueprint::new(0, 3, 3, 1), rbp1],
+                                vec![]);
+        assert!(bp.validate().is_ok());
+        let (genes, _) = bp.compile();
+        // The left door defaults to the middle of the wall
+        assert_eq!(&[Door::At(Up, 1), Door::At(Left, 2)],
+                   genes[2].get_doors().as_slice());
+        // The pinned room is rotated, and its doors with it
+        assert_eq!(&[Door::At(Right, 1), Door::At(Up, 2)],
+                   genes[1].get_doors().as_slice());
+        let bp = Blueprint::new(vec![
+                RoomBlueprint::new(0, 3, 3, 1),
+                RoomBlueprint::new_with_doors(1, 4, 5, 1, vec![
+                    DoorBlueprint::new("top", Some(3)),
+                    DoorBlueprint::new("middle", None),
+                    DoorBlueprint::new("any", None),
+                    DoorBlueprint::new("any", None),
+                    DoorBlueprint::new("any", None),
+                ]),
+            ], vec![]);
+        assert_eq!(vec![
+            BlueprintError::InvalidDoor { room: 1, key: 1, door: 0 },
+            BlueprintError::InvalidDoor { room: 1, key: 1, door: 1 },
+            BlueprintError::TooManyDoors { room: 1, key: 1 },
+        ], bp.validate().unwrap_err());
+    }
+
+    #[test]
+    fn masks_are_validated_and_compiled() {
+        let mask = lines(&["###..", "###..", "#####", "#####"]);
+        let mut rbp1 = RoomBlueprint::new_with_mask(1, 5, 4, 1, mask.clone());
+        rbp1.pins = Some(vec![
+            PinBlueprint { x: 3, y: 3, rotated: Some(true), level: None }]);
+        let bp = Blueprint::new(vec![RoomBlueprint::new(0, 3, 3, 1), rbp1],
+                                vec![]);
+        assert!(bp.validate().is_ok());
+        let (genes, _) = bp.compile();
+        // The pinned room is rotated, and its mask with it
+        match genes[1].get_shape() {
+            Shape::Masked(mask) => assert_eq!(
+                &["####", "####", "####", "##..", "##.."], &mask.lines()[..]),
+            Shape::Rectangle => panic!("The mask was left out!"),
+        }
+        assert!(genes[0].get_shape().is_rectangle());
+        let mut rbp3 = RoomBlueprint::new_with_mask(3, 5, 4, 1, mask.clone());
+        rbp3.doors = Some(vec![DoorBlueprint::new("right", Some(1)),
+                               DoorBlueprint::new("bottom", Some(3))]);
+        let bp = Blueprint::new(vec![
+                RoomBlueprint::new(0, 3, 3, 1),
+                RoomBlueprint::new_with_mask(1, 4, 4, 1, mask),
+                RoomBlueprint::new_with_mask(2, 2, 2, 1,
+                    vec!["#.".to_string(), ".#".to_string()]),
+                rbp3,
+            ], vec![]);
+        assert_eq!(vec![
+            BlueprintError::InvalidMask { room: 1, key: 1 },
+            BlueprintError::InvalidMask { room: 2, key: 2 },
+            BlueprintError::InvalidDoor { room: 3, key: 3, door: 0 },
+        ], bp.validate().unwrap_err());
+    }
+
+    #[test]
+    fn levels_are_validated_and_compiled() {
+        let mut rbp0 = RoomBlueprint::new(0, 3, 3, 1);
+        rbp0.levels = Some(vec![1]);
+        let rbp1 = RoomBlueprint::new(1, 4, 4, 1);
+        let mut rbp2 = RoomBlueprint::new(2, 4, 4, 1);
+        rbp2.levels = Some(vec![0, 3]);
+        let mut rbp3 = RoomBlueprint::new(3, 4, 4, 1);
+        rbp3.levels = Some(vec![]);
+        let mut bp = Blueprint::new(vec![rbp0, rbp1, rbp2, rbp3], vec![]);
+        bp.levels = Some(3);
+        assert_eq!(vec![
+            BlueprintError::InvalidLevel { room: 2, key: 2, level: 3 },
+            BlueprintError::NoLevels { room: 3, key: 3 },
+        ], bp.validate().unwrap_err());
+        bp.rooms.truncate(2);
+        assert!(bp.validate().is_ok());
+        let (genes, _) = bp.compile();
+        assert_eq!(1, genes[0].get_z());
+        assert!(!genes[0].is_allowed_level(0));
+        assert!(genes[1].is_allowed_level(2));
+        assert!(!genes[1].is_allowed_level(3));
+        bp.levels = Some(0);
+        assert_eq!(vec![
+            BlueprintError::InvalidLevelCount { levels: 0 },
+            BlueprintError::InvalidLevel { room: 0, key: 0, level: 1 },
+        ], bp.validate().unwrap_err());
+        // Levels beyond genes::MAX_LEVELS are errors, not overflows
+        bp.levels = Some(40);
+        bp.rooms[1].levels = Some(vec![35]);
+        assert_eq!(vec![
+            BlueprintError::InvalidLevelCount { levels: 40 },
+            BlueprintError::InvalidLevel { room: 1, key: 1, level: 35 },
+        ], bp.validate().unwrap_err());
+        assert_eq!(0, bp.rooms[1].allowed_levels(40));
+    }
+
+    #[test]
+    fn pins_are_validated_and_compiled() {
+        let pin = |x, y| PinBlueprint { x: x, y: y, rotated: None, level: None };
+        let mut rbp0 = RoomBlueprint::new(0, 3, 3, 1);
+        rbp0.pins = Some(vec![pin(5, 5)]);
+        let rbp1 = RoomBlueprint::new_pinned(1, 4, 2, 3, vec![
+            pin(1, -1),
+            PinBlueprint { x: 3, y: 3, rotated: Some(true), level: None },
+            pin(-8, -8),
+        ]);
+        let rbp2 = RoomBlueprint::new_pinned(2, 2, 2, 1, vec![pin(2, 4),
+            pin(9, 9)]);
+        let bp = Blueprint::new(vec![rbp0, rbp1.clone(), rbp2], vec![]);
+        assert_eq!(vec![
+            BlueprintError::PinnedKeyZero { room: 0 },
+            BlueprintError::OverlappingPin { room: 1, key: 1, pin: 0 },
+            BlueprintError::TooManyPins { room: 2, key: 2 },
+            BlueprintError::OverlappingPin { room: 2, key: 2, pin: 0 },
+        ], bp.validate().unwrap_err());
+        let bp = Blueprint::new(vec![RoomBlueprint::new(0, 3, 3, 1),
+            RoomBlueprint::new_pinned(1, 4, 2, 3, vec![
+                PinBlueprint { x: 3, y: 3, rotated: Some(true), level: None },
+                pin(-8, -8),
+            ])], vec![]);
+        assert!(bp.validate().is_ok());
+        let (genes, _) = bp.compile();
+        assert!(!genes[0].is_pinned());
+        assert!(genes[1].is_pinned() && genes[2].is_pinned());
+        assert!(!genes[3].is_pinned());
+        let rect = |gene: &Gene| (gene.get_x(), gene.get_y(), gene.get_w(),
+            gene.get_h());
+        assert_eq!((3, 3, 3, 5), rect(&genes[1]));
+        assert_eq!((-8, -8, 5, 3), rect(&genes[2]));
+    }
+
+    #[test]
+    fn pins_are_validated_on_the_site() {
+        let bp = Blueprint::new(vec![RoomBlueprint::new(0, 3, 3, 1),
+            RoomBlueprint::new_pinned(1, 2, 2, 2, vec![
+                PinBlueprint { x: 3, y: -1, rotated: None, level: None },
+                PinBlueprint { x: -6, y: -1, rotated: None, level: None },
+            ])], vec![]);
+        assert!(bp.validate().is_ok());
+        assert!(bp.validate_on_site(&Site::empty()).is_ok());
+        // The rock is under the first pinned room, right of the origin
+        let site = Site::parse(".........\n.........\n.....@..#\n").unwrap();
+        assert_eq!(vec![
+            BlueprintError::PinOnBlockedTile { room: 1, key: 1, pin: 0 },
+        ], bp.validate_on_site(&site).unwrap_err());
+        // Room 0 is never moved off the rock next to the origin
+        let site = Site::parse("...\n#@.\n...\n").unwrap();
+        assert_eq!(vec![
+            BlueprintError::KeyZeroOnBlockedTile { room: 0 },
+        ], bp.validate_on_site(&site).unwrap_err());
+    }
+
+    #[test]
+    fn area_is_validated() {
+        let pin = PinBlueprint { x: 6, y: 0, rotated: None, level: None };
+        let mut bp = Blueprint::new(vec![RoomBlueprint::new(0, 3, 3, 1),
+            RoomBlueprint::new_pinned(1, 4, 4, 1, vec![pin])], vec![]);
+        bp.area = Some(AreaBlueprint { width: 10, height: 8, x: None, y: None });
+        assert_eq!(Some(Rect::new(-5, -4, 10, 8)), bp.area());
+        assert_eq!(vec![
+            BlueprintError::PinOutsideArea { room: 1, key: 1, pin: 0 },
+        ], bp.validate().unwrap_err());
+        bp.area = Some(AreaBlueprint { width: 12, height: 8, x: Some(-2),
+            y: Some(-4) });
+        assert!(bp.validate().is_ok());
+        bp.area = Some(AreaBlueprint { width: 12, height: 8, x: Some(0),
+            y: None });
+        assert_eq!(vec![BlueprintError::InvalidArea], bp.validate().unwrap_err());
+        bp.area = Some(AreaBlueprint { width: 0, height: 8, x: None, y: None });
+        assert_eq!(vec![
+            BlueprintError::InvalidArea,
+            BlueprintError::PinOutsideArea { room: 1, key: 1, pin: 0 },
+        ], bp.validate().unwrap_err());
+    }
+
+    #[test]
+    fn objective_uses_defaults_for_missing_weights() {
+        let bp: Blueprint = json::decode("{\"rooms\": [{\"key\": 0, \
+            \"width\": 3, \"height\": 3, \"amount\": 1}, {\"key\": 1, \
+            \"width\": 4, \"height\": 2, \"amount\": 2}], \"targets\": [{\
+            \"from_key\": 0, \"to_key\": 1, \"weight\": 1.0}], \
+            \"objective\": {\"distance\": 2.0}, \
+            \"distance_metric\": \"walking\"}").unwrap();
+        assert!(bp.validate().is_ok());
+        assert_eq!(DistanceMetric::Walking, bp.distance_metric());
+        let (genes, targets) = bp.compile();
+        let chromosome = Chromosome::new(genes, targets.clone());
+        let composite = CompositeFitness::new(2.0,
+            fitness::DEFAULT_UTILIZATION_WEIGHT,
+            fitness::DEFAULT_PERIMETER_WEIGHT, DistanceMetric::Walking);
+        assert_eq!(composite.fitness(&chromosome),
+                   bp.fitness_function(&targets).fitness(&chromosome));
+    }
+
+    #[test]
+    fn config_uses_defaults_for_missing_parameters() {
+        let bp: Blueprint = json::decode("{\"rooms\": [], \"targets\": [], \
+            \"parameters\": {\"mutation_chance\": 0.2, \
+            \"mutation_weights\": {\"swap\": 3.0}}}").unwrap();
+        let config = bp.config();
+        assert_eq!(0.2, config.mutation_chance);
+        assert_eq!(3.0, config.mutation_weights.swap);
+        assert_eq!(GaConfig::new().mutation_weights.nudge,
+                   config.mutation_weights.nudge);
+        assert_eq!(GaConfig::new().crossover_chance, config.crossover_chance);
+        assert_eq!(GaConfig::new().purge_interval, config.purge_interval);
+        let bp: Blueprint = json::decode("{\"rooms\": [], \"targets\": []}")
+            .unwrap();
+        assert!(bp.config() == GaConfig::new());
+    }
 }
@@ -1,4 +1,8 @@
 //! This module contains interaction with files and the user.
 pub mod output;
 pub mod input;
-pub mod ui;
\ No newline at end of file
+pub mod ui;
+pub mod cli;
+pub mod export;
+pub mod svg;
+pub mod quickfort;
\ No newline at end of file
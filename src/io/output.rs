@@ -2,10 +2,49 @@
 
 use std::io::{Write, Error};
 use std::fs::File;
-use collections::Matrix;
+use collections::{Matrix, Vector};
 
 /// Saves a character matrix to a file
 pub fn save_matrix(matrix: Matrix<char>, filename: String) -> Result<(), Error>{
+	save(matrix_as_string(&matrix), filename)
+}
+
+/// Saves a character matrix to a file with a legend (one entry per line)
+/// below it
+pub fn save_matrix_with_legend(matrix: Matrix<char>, legend: &Vector<String>,
+	filename: String) -> Result<(), Error> {
+	let mut output = matrix_as_string(&matrix);
+	output.push_str("\r\n");
+	for i in 0..legend.len() {
+		output.push_str(&legend[i]);
+		output.push_str("\r\n");
+	}
+	save(output, filename)
+}
+
+/// Saves the character matrices of the levels of a layout to a file, each
+/// under a heading that tells its level, with a legend below them. If there's
+/// only one level, the heading is left out.
+pub fn save_levels_with_legend(levels: &Vector<(isize, Matrix<char>)>,
+	legend: &Vector<String>, filename: String) -> Result<(), Error> {
+	let mut output = String::new();
+	for i in 0..levels.len() {
+		if levels.len() > 1 {
+			output.push_str(&format!("Level {}:\r\n", levels[i].0));
+		}
+		output.push_str(&matrix_as_string(&levels[i].1));
+		output.push_str("\r\n");
+	}
+	for i in 0..legend.len() {
+		output.push_str(&legend[i]);
+		output.push_str("\r\n");
+	}
+	save(output, filename)
+}
+
+/// Converts a character matrix to a string with DOS line changes. Empty cells
+/// become spaces.
+fn matrix_as_string(matrix: &Matrix<char>) -> String {
 	let mut output = String::new();
 	for y in 0..matrix.h {
 		for x in 0..matrix.w {
@@ -18,7 +57,7 @@ pub fn save_matrix(matrix: Matrix<char>, filename: String) -> Result<(), Error>{
 		output.push('\r'); // DOS-compatible line change
 		output.push('\n');
 	}
-	save(output, filename)
+	output
 }
 
 /// Save given string to a file.
@@ -0,0 +1,193 @@
+//! This module contains exporting finished layouts as Quickfort CSV
+//! blueprints that can be applied in Dwarf Fortress.
+
+use std::io::Error;
+use collections::Matrix;
+use mapping::rooms::{Layout, WALL_CHAR, CORRIDOR_CHAR, STAIRS_CHAR};
+use io::output;
+
+/// The kinds of Quickfort blueprints that can be made of a layout
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Mode {
+    /// Digs out room floors and corridors; the rock left between them forms
+    /// the walls
+    Dig,
+    /// Constructs the walls of the rooms, with doors where corridors enter
+    /// them. Useful when building above ground.
+    Build,
+}
+
+/// What a tile of the layout is, as far as Quickfort is concerned
+#[derive(Copy, Clone, PartialEq, Debug)]
+enum Tile {
+    Empty,
+    Wall,
+    Floor,
+    Corridor,
+    Door,
+    Stairs,
+}
+
+/// Creates a Quickfort CSV blueprint of a layout. The cursor starts from the
+/// center of room 0 on the top level; lower levels follow, each one z-level
+/// further down. Levels without rooms get only the stair shaft, so that it's
+/// dug all the way through. The shaft is a down stair on the top level, an up
+/// stair on the bottom level and an up/down stair in between.
+pub fn blueprint(layout: &Layout, mode: Mode) -> String {
+    let bounding_box = layout.calculate_bounding_box();
+    let rooms = layout.get_rooms();
+    let mut csv = match mode {
+        Mode::Dig => String::from("#dig"),
+        Mode::Build => String::from("#build"),
+    };
+    if rooms.len() > 0 {
+        let start = bounding_box.top_left().diff(rooms[0].center());
+        // Quickfort counts the start position from 1
+        csv.push_str(&format!(" start({};{})", start.x + 1, start.y + 1));
+    }
+    csv.push_str(" Generated by dwarfitect\r\n");
+    let levels = layout.shaft_levels();
+    for l in 0..levels.len() {
+        if l > 0 {
+            csv.push_str("#>\r\n");
+        }
+        let matrix = layout.as_level_matrix(levels[l]);
+        let stairs = if l == 0 {
+            "j"
+        } else if l + 1 == levels.len() {
+            "u"
+        } else {
+            "i"
+        };
+        for y in 0..matrix.h {
+            for x in 0..matrix.w {
+                if x > 0 {
+                    csv.push(',');
+                }
+                csv.push_str(designation(tile_at(&matrix, x, y), mode, stairs));
+            }
+            csv.push_str("\r\n");
+        }
+    }
+    csv
+}
+
+/// Saves a Quickfort CSV blueprint of a layout to a file
+pub fn save_blueprint(layout: &Layout, mode: Mode, filename: String)
+    -> Result<(), Error> {
+    output::save(blueprint(layout, mode), filename)
+}
+
+/// Gives the Quickfort designation of a tile in given mode, digging stair
+/// tiles with given stair designation
+fn designation(tile: Tile, mode: Mode, stairs: &'static str) -> &'static str {
+    match (mode, tile) {
+        (Mode::Dig, Tile::Floor) | (Mode::Dig, Tile::Corridor) |
+        (Mode::Dig, Tile::Door) => "d",
+        (Mode::Dig, Tile::Stairs) => stairs,
+        (Mode::Build, Tile::Wall) => "Cw",
+        (Mode::Build, Tile::Door) => "d",
+        _ => "",
+    }
+}
+
+/// Finds out what the tile at (x, y) of a layout's char matrix is. Corridor
+/// tiles next to a room floor are where the corridor goes through a wall, so
+/// they become doors.
+fn tile_at(matrix: &Matrix<char>, x: usize, y: usize) -> Tile {
+    let tile = tile_of(matrix.get(x, y));
+    if tile != Tile::Corridor {
+        return tile;
+    }
+    let neighbours = [(x.wrapping_sub(1), y), (x + 1, y),
+                      (x, y.wrapping_sub(1)), (x, y + 1)];
+    for &(nx, ny) in neighbours.iter() {
+        if nx < matrix.w && ny < matrix.h &&
+           tile_of(matrix.get(nx, ny)) == Tile::Floor {
+            return Tile::Door;
+        }
+    }
+    Tile::Corridor
+}
+
+/// Tells what kind of tile a character of a layout's char matrix is
+fn tile_of(cell: &Option<char>) -> Tile {
+    match *cell {
+        None => Tile::Empty,
+        Some(c) if c == WALL_CHAR => Tile::Wall,
+        Some(c) if c == CORRIDOR_CHAR => Tile::Corridor,
+        Some(c) if c == STAIRS_CHAR => Tile::Stairs,
+        Some(_) => Tile::Floor,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use mapping::shapes::Rect;
+    use mapping::rooms::{Layout, Room};
+    use genetics::genes::Target;
+    use collections::Vector;
+
+    fn connected_layout() -> Layout {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(7, 0, 5, 5), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from, to, 1.0));
+        let mut layout = Layout::new(rooms, targets);
+        assert_eq!(0, layout.connect());
+        layout
+    }
+
+    fn cells(csv: &str, designation: &str) -> usize {
+        csv.lines().skip(1)
+            .map(|line| line.split(',').filter(|c| *c == designation).count())
+            .sum()
+    }
+
+    #[test]
+    fn dig_blueprint_digs_floors_and_corridors() {
+        let layout = connected_layout();
+        let corridor_tiles = layout.get_corridors()[0].tiles.len();
+        let csv = blueprint(&layout, Mode::Dig);
+        assert!(csv.starts_with("#dig start(3;3)"));
+        let width = layout.calculate_bounding_box().w as usize;
+        assert_eq!(width, csv.lines().nth(1).unwrap().split(',').count());
+        assert_eq!(9 + 9 + corridor_tiles, cells(&csv, "d"));
+    }
+
+    #[test]
+    fn levels_are_dug_one_below_another_with_stairs() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new_on_level(Rect::new(0, 0, 5, 5), 0, 0));
+        rooms.push(Room::new_on_level(Rect::new(0, 0, 5, 5), 1, 2));
+        let layout = Layout::new(rooms, Vector::new());
+        let csv = blueprint(&layout, Mode::Dig);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(1 + 5 + 1 + 5 + 1 + 5, lines.len());
+        assert_eq!("#>", lines[6]);
+        assert_eq!("#>", lines[12]);
+        // The shaft goes down from the top level and up from the bottom one,
+        // and the skipped level has only the shaft
+        assert_eq!(1, cells(&csv, "j"));
+        assert_eq!(1, cells(&csv, "i"));
+        assert_eq!(1, cells(&csv, "u"));
+        assert_eq!(",d,j,d,", lines[3]);
+        assert_eq!(",,i,,", lines[9]);
+        assert_eq!(",d,u,d,", lines[15]);
+    }
+
+    #[test]
+    fn build_blueprint_builds_walls_with_doors() {
+        let csv = blueprint(&connected_layout(), Mode::Build);
+        assert!(csv.starts_with("#build"));
+        assert_eq!(2, cells(&csv, "d"));
+        assert_eq!(16 + 16 - 2, cells(&csv, "Cw"));
+    }
+}
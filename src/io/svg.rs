@@ -0,0 +1,293 @@
+//! This module contains rendering finished layouts as SVG images.
+
+use std::io::Error;
+use mapping::rooms::Layout;
+use mapping::shapes::Point;
+use io::output;
+
+/// Size of one tile in the image, in pixels
+pub const TILE_SIZE: f32 = 16.0;
+/// Color of room outlines and labels
+const INK_COLOR: &'static str = "#333333";
+/// Color of corridor tiles
+const CORRIDOR_COLOR: &'static str = "#bbbbbb";
+/// Color of target lines
+const TARGET_COLOR: &'static str = "#cc3333";
+/// Width of the line drawn for the target with the largest weight, in pixels
+const MAX_TARGET_WIDTH: f32 = 4.0;
+/// Height of the caption above each level when there are several, in pixels
+const CAPTION_HEIGHT: f32 = 32.0;
+
+/// Renders a layout as an SVG image. Rooms are drawn as rectangles colored by
+/// their key and labelled with the names of their types; rooms with masks are
+/// filled tile by tile and outlined along their walls. If show_targets is
+/// true, lines are drawn between the rooms of each attraction target that are
+/// on the same level, thicker for larger weights. Each level that the stair
+/// shaft goes through is drawn under the previous one with a caption.
+pub fn render(layout: &Layout, show_targets: bool) -> String {
+    let bounding_box = layout.calculate_bounding_box();
+    let levels = layout.shaft_levels();
+    let caption = if levels.len() > 1 { CAPTION_HEIGHT } else { 0.0 };
+    let panel_height = bounding_box.h as f32 * TILE_SIZE + caption;
+    let width = bounding_box.w as f32 * TILE_SIZE;
+    let height = panel_height * levels.len().max(1) as f32;
+    let mut svg = format!("<svg xmlns=\"http://www.w3.org/2000/svg\" \
+                           width=\"{}\" height=\"{}\" viewBox=\"0 0 {} {}\">\n",
+                          width, height, width, height);
+    let origin_x = bounding_box.x as f32;
+    let origin_y = bounding_box.y as f32;
+    for l in 0..levels.len() {
+        let z = levels[l];
+        let top = panel_height * l as f32 + caption;
+        // Convert tile coordinates of the layout to pixels on this level
+        let px = |x: isize| (x as f32 - origin_x) * TILE_SIZE;
+        let py = |y: isize| (y as f32 - origin_y) * TILE_SIZE + top;
+        if levels.len() > 1 {
+            svg.push_str(&format!("<text x=\"0\" y=\"{}\" font-size=\"{}\" \
+                                   font-family=\"sans-serif\" fill=\"{}\">\
+                                   Level {}</text>\n",
+                                  top - caption / 3.0, TILE_SIZE, INK_COLOR, z));
+        }
+        let corridors = layout.get_corridors();
+        for i in 0..corridors.len() {
+            if corridors[i].z != z {
+                continue;
+            }
+            let tiles = &corridors[i].tiles;
+            for j in 0..tiles.len() {
+                svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" \
+                                       height=\"{}\" fill=\"{}\"/>\n",
+                                      px(tiles[j].x), py(tiles[j].y),
+                                      TILE_SIZE, TILE_SIZE, CORRIDOR_COLOR));
+            }
+        }
+        let rooms = layout.get_rooms();
+        for i in 0..rooms.len() {
+            let room = &rooms[i];
+            if room.get_z() != z {
+                continue;
+            }
+            let x = px(room.get_x());
+            let y = py(room.get_y());
+            let w = room.get_w() as f32 * TILE_SIZE;
+            let h = room.get_h() as f32 * TILE_SIZE;
+            if room.get_shape().is_rectangle() {
+                svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" \
+                                       height=\"{}\" fill=\"{}\" \
+                                       stroke=\"{}\"/>\n",
+                                      x, y, w, h, color_for(room.get_key()),
+                                      INK_COLOR));
+            } else {
+                let tiles = room.tiles();
+                let mut path = String::new();
+                for j in 0..tiles.len() {
+                    path.push_str(&format!("M{} {}h{}v{}h-{}z", px(tiles[j].x),
+                                           py(tiles[j].y), TILE_SIZE, TILE_SIZE,
+                                           TILE_SIZE));
+                }
+                svg.push_str(&format!("<path d=\"{}\" fill=\"{}\"/>\n", path,
+                                      color_for(room.get_key())));
+                // Outline the room along the tile edges that face outside
+                let mut outline = String::new();
+                for j in 0..tiles.len() {
+                    let (tx, ty) = (tiles[j].x, tiles[j].y);
+                    let edges = [(0, -1, 0.0, 0.0, 'h'),
+                                 (0, 1, 0.0, TILE_SIZE, 'h'),
+                                 (-1, 0, 0.0, 0.0, 'v'),
+                                 (1, 0, TILE_SIZE, 0.0, 'v')];
+                    for &(dx, dy, ox, oy, line) in edges.iter() {
+                        if !room.contains(Point::new(tx + dx, ty + dy)) {
+                            outline.push_str(&format!("M{} {}{}{}", px(tx) + ox,
+                                                      py(ty) + oy, line,
+                                                      TILE_SIZE));
+                        }
+                    }
+                }
+                svg.push_str(&format!("<path d=\"{}\" fill=\"none\" \
+                                       stroke=\"{}\"/>\n", outline, INK_COLOR));
+            }
+            svg.push_str(&format!("<text x=\"{}\" y=\"{}\" font-size=\"{}\" \
+                                   font-family=\"sans-serif\" fill=\"{}\" \
+                                   text-anchor=\"middle\" \
+                                   dominant-baseline=\"middle\">{}</text>\n",
+                                  x + w / 2.0, y + h / 2.0, TILE_SIZE * 0.75,
+                                  INK_COLOR,
+                                  escape(&layout.name_for(room.get_key()))));
+        }
+        if let Some(stairs) = layout.stairs() {
+            svg.push_str(&format!("<rect x=\"{}\" y=\"{}\" width=\"{}\" \
+                                   height=\"{}\" fill=\"{}\"/>\n",
+                                  px(stairs.x), py(stairs.y), TILE_SIZE,
+                                  TILE_SIZE, INK_COLOR));
+        }
+        if show_targets {
+            let targets = layout.get_targets();
+            let mut max_weight = 0.0;
+            for i in 0..targets.len() {
+                if targets[i].is_attraction() {
+                    max_weight = targets[i].weight.max(max_weight);
+                }
+            }
+            for t in 0..targets.len() {
+                if !targets[t].is_attraction() {
+                    continue;
+                }
+                let width = MAX_TARGET_WIDTH * targets[t].weight / max_weight;
+                for i in 0..targets[t].from_id.len() {
+                    for j in 0..targets[t].to_id.len() {
+                        let from = &rooms[targets[t].from_id[i]];
+                        let to = &rooms[targets[t].to_id[j]];
+                        if from.get_z() != z || to.get_z() != z {
+                            continue;
+                        }
+                        svg.push_str(&format!("<line x1=\"{}\" y1=\"{}\" \
+                                               x2=\"{}\" y2=\"{}\" stroke=\"{}\" \
+                                               stroke-width=\"{}\" \
+                                               stroke-opacity=\"0.6\"/>\n",
+                            px(from.get_x()) + from.get_w() as f32 * TILE_SIZE / 2.0,
+                            py(from.get_y()) + from.get_h() as f32 * TILE_SIZE / 2.0,
+                            px(to.get_x()) + to.get_w() as f32 * TILE_SIZE / 2.0,
+                            py(to.get_y()) + to.get_h() as f32 * TILE_SIZE / 2.0,
+                            TARGET_COLOR, width));
+                    }
+                }
+            }
+        }
+    }
+    svg.push_str("</svg>\n");
+    svg
+}
+
+/// Saves a layout as an SVG image
+pub fn save_svg(layout: &Layout, show_targets: bool, filename: String)
+    -> Result<(), Error> {
+    output::save(render(layout, show_targets), filename)
+}
+
+/// Gives the fill color of rooms with given key. Hues are spread with the
+/// golden angle so that rooms with consecutive keys look different.
+fn color_for(key: usize) -> String {
+    let hue = (key as f32 * 137.508) % 360.0;
+    format!("hsl({}, 60%, 75%)", hue.round())
+}
+
+/// Escapes characters that have a special meaning in XML
+fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use mapping::shapes::Rect;
+    use mapping::rooms::{Layout, Room, RoomType};
+    use mapping::masks::{Mask, Shape};
+    use mapping::masks::tests::lines;
+    use genetics::genes::Target;
+    use collections::Vector;
+
+    fn test_layout() -> Layout {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(-2, -1, 3, 3), 0));
+        rooms.push(Room::new(Rect::new(2, -1, 5, 3), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from, to, 2.0));
+        let mut layout = Layout::new(rooms, targets);
+        let mut types = Vector::new();
+        types.push(RoomType::new(1, String::from("Food & drink"), 'f'));
+        layout.set_room_types(types);
+        layout
+    }
+
+    #[test]
+    fn render_draws_labelled_rooms_scaled_to_bounding_box() {
+        let svg = render(&test_layout(), false);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("viewBox=\"0 0 144 48\""));
+        assert_eq!(2, svg.matches("<rect").count());
+        assert!(svg.contains("<rect x=\"64\" y=\"0\" width=\"80\" height=\"48\""));
+        assert!(svg.contains(">Room 0</text>"));
+        assert!(svg.contains(">Food &amp; drink</text>"));
+        assert!(!svg.contains("<line"));
+    }
+
+    #[test]
+    fn render_draws_each_level_under_the_previous_one() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new_on_level(Rect::new(0, 0, 3, 3), 0, 0));
+        rooms.push(Room::new_on_level(Rect::new(0, 0, 5, 3), 1, 1));
+        let layout = Layout::new(rooms, Vector::new());
+        let svg = render(&layout, true);
+        assert!(svg.contains("viewBox=\"0 0 80 160\""));
+        assert!(svg.contains(">Level 0</text>"));
+        assert!(svg.contains(">Level 1</text>"));
+        assert!(svg.contains("<rect x=\"0\" y=\"112\" width=\"80\" height=\"48\""));
+    }
+
+    #[test]
+    fn render_draws_levels_with_only_the_stair_shaft() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new_on_level(Rect::new(0, 0, 3, 3), 0, 0));
+        rooms.push(Room::new_on_level(Rect::new(0, 0, 5, 3), 1, 2));
+        let layout = Layout::new(rooms, Vector::new());
+        let svg = render(&layout, false);
+        assert!(svg.contains(">Level 1</text>"));
+        assert_eq!(3, svg.matches(">Level ").count());
+    }
+
+    #[test]
+    fn render_can_draw_target_lines() {
+        let svg = render(&test_layout(), true);
+        assert!(svg.contains("<line x1=\"24\" y1=\"24\" x2=\"104\" y2=\"24\""));
+        assert!(svg.contains("stroke-width=\"4\""));
+    }
+
+    #[test]
+    fn target_lines_are_scaled_by_attraction_weights() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(-2, -1, 3, 3), 0));
+        rooms.push(Room::new(Rect::new(2, -1, 5, 3), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        // The repulsion is heavier, but only the attraction gets a line
+        let mut targets = Vector::new();
+        targets.push(Target::new(from.clone(), to.clone(), 2.0));
+        targets.push(Target::new_repulsion(from, to, 10.0, 1.0));
+        let svg = render(&Layout::new(rooms, targets), true);
+        assert_eq!(1, svg.matches("<line").count());
+        assert!(svg.contains("stroke-width=\"4\""));
+    }
+
+    #[test]
+    fn render_outlines_masked_rooms() {
+        let mask = Mask::parse(&lines(&["##.", "###"])).unwrap();
+        let mut room = Room::new(Rect::new(0, 0, 3, 2), 0);
+        room.set_shape(Shape::Masked(mask));
+        let mut rooms = Vector::new();
+        rooms.push(room);
+        let svg = render(&Layout::new(rooms, Vector::new()), false);
+        assert_eq!(0, svg.matches("<rect").count());
+        // Along the edges of the tiles that face outside, around the corner
+        let outline = "M0 0h16M0 0v16M16 0h16M32 0v16M0 32h16M0 16v16\
+                       M16 32h16M32 16h16M32 32h16M48 16v16";
+        assert!(svg.contains(&format!("<path d=\"{}\" fill=\"none\" \
+                                       stroke=\"#333333\"/>", outline)));
+    }
+}
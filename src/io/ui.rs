@@ -3,6 +3,14 @@
 use std::io::{self, Error, ErrorKind};
 use std::str::FromStr;
 
+/// How much should be printed about the progress of the program
+#[derive(Copy, Clone, PartialEq, Debug)]
+pub enum Verbosity {
+	Quiet,
+	Normal,
+	Verbose
+}
+
 /// Reads input from stdin and returns it wrapped in a result.
 pub fn get_input() -> Result<String, Error> {
 	let mut result = String::new();
@@ -42,4 +50,15 @@ pub fn get_parsed_input_loop<T: FromStr>() -> T {
 			Err(reason) => println!("Error: {}. Please try again.", reason)
 		}
 	}
+}
+
+/// Tries to get a count that is at least 1 until succeeds
+pub fn get_count_input_loop() -> usize {
+	loop {
+		match get_parsed_input_loop() {
+			0 => println!("Error: The value must be at least 1. Please try \
+				again."),
+			count => return count
+		}
+	}
 }
\ No newline at end of file
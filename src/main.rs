@@ -1,43 +1,223 @@
-extern crate rand;
 extern crate dwarfilib;
 use dwarfilib::genetics::breeding;
-use dwarfilib::io::{output, input};
-use dwarfilib::io::ui::{get_input_loop, get_parsed_input_loop};
-use std::{thread, time};
+use dwarfilib::genetics::genes::TargetKind;
+use dwarfilib::collections::Vector;
+use dwarfilib::io::{output, input, cli, export, svg, quickfort};
+use dwarfilib::io::ui::{get_input_loop, get_count_input_loop, Verbosity};
+use dwarfilib::mapping::site::Site;
+use std::{env, process, thread, time};
+use std::io::{self, Write};
+use std::sync::Arc;
 
-/// A main-function that brings everything together under a text UI.
-/// # Panics
-/// Panics if unable to read or save for some reason
-fn main() { //TODO: Move all this to actual functions
-	println!("############\n#DWARFITECT#\n############\n");
-	let mut input_file = String::new();
-	let mut valid_file = false;
-	while !valid_file {
-		println!("Input file name: ");
-		input_file = get_input_loop();
-		valid_file = input::exists(&String::from(input_file.trim()));
-		if !valid_file {
-			println!("\"{}\" doesn't exist in the dwarfitect binary folder!",
-			input_file.trim());
-		}
-	}
-	println!("Output file name: ");
-	let output_file = get_input_loop();
-	println!("Population size: ");
-	let pop_size: usize = get_parsed_input_loop();
-	println!("Generations: ");
-	let generations: usize = get_parsed_input_loop();
-	println!("");
-	let mut rng = rand::thread_rng();
-	let bp = input::read(String::from(input_file.trim()));
+/// Exit code for invalid command line arguments
+const EXIT_USAGE: i32 = 64;
+/// Exit code for when the input file couldn't be read or was invalid
+const EXIT_INPUT: i32 = 65;
+/// Exit code for when the result couldn't be saved
+const EXIT_OUTPUT: i32 = 73;
+
+/// A main-function that brings everything together. Takes options from the
+/// command line and asks for missing ones under a text UI.
+fn main() {
+	let args: Vec<String> = env::args().skip(1).collect();
+	let options = match cli::parse(&args) {
+		Ok(options) => options,
+		Err(reason) => {
+			let _ = writeln!(io::stderr(), "{}\n\n{}", reason, cli::USAGE);
+			process::exit(EXIT_USAGE);
+		}
+	};
+	if options.help {
+		println!("{}", cli::USAGE);
+		return;
+	}
+	let code = run(options);
+	process::exit(code);
+}
+
+/// Runs the program with given options and returns the exit code
+fn run(options: cli::Options) -> i32 {
+	let verbosity = options.verbosity;
+	let interactive = options.input.is_none() || options.output.is_none() ||
+		options.pop_size.is_none() || options.generations.is_none();
+	let seed = match options.seed {
+		Some(seed) => seed,
+		None => breeding::random_seed()
+	};
+	if verbosity != Verbosity::Quiet {
+		println!("############\n#DWARFITECT#\n############\n");
+		println!("Seed: {}\n", seed);
+	}
+	let input_file = match options.input.clone() {
+		Some(file) => file,
+		None => ask_input_file()
+	};
+	let output_file = match options.output.clone() {
+		Some(file) => file,
+		None => {
+			println!("Output file name: ");
+			String::from(get_input_loop().trim())
+		}
+	};
+	let pop_size: usize = match options.pop_size {
+		Some(size) => size,
+		None => {
+			println!("Population size: ");
+			get_count_input_loop()
+		}
+	};
+	let generations: usize = match options.generations {
+		Some(generations) => generations,
+		None => {
+			println!("Generations: ");
+			get_count_input_loop()
+		}
+	};
+	if verbosity != Verbosity::Quiet {
+		println!("");
+	}
+	let bp = match input::read(input_file.clone()) {
+		Ok(bp) => bp,
+		Err(reason) => {
+			let _ = writeln!(io::stderr(), "ERROR! Couldn't read {}: {}",
+				input_file, reason);
+			return EXIT_INPUT;
+		}
+	};
+	let mut site = match options.site {
+		Some(ref site_file) => match input::read_site(site_file.clone()) {
+			Ok(site) => site,
+			Err(reason) => {
+				let _ = writeln!(io::stderr(), "ERROR! Couldn't read {}: {}",
+					site_file, reason);
+				return EXIT_INPUT;
+			}
+		},
+		None => Site::empty(),
+	};
+	if let Err(errors) = bp.validate_on_site(&site) {
+		let _ = writeln!(io::stderr(), "ERROR! Couldn't read {}: {}",
+			input_file, input::ReadError::Invalid(errors));
+		return EXIT_INPUT;
+	}
+	if let Some(area) = bp.area() {
+		site.set_bounds(area);
+	}
+	let site = Arc::new(site);
 	let (genes, targets) = bp.compile();
-	let result = breeding::breeder(genes, targets, pop_size, generations, &mut rng);
-	let layout = result.as_layout();
-	let matrix = layout.as_char_matrix();
-	match output::save_matrix(matrix, String::from(output_file.trim())) {
-		Err(reason) => println!("\nERROR! Couldn't save the result! ({:?})", reason),
-		Ok(_) => println!("\nSuccesfully saved result to {}", output_file.trim())
-	};
-	let sleep_time = time::Duration::new(5, 0);
-	thread::sleep(sleep_time);
-}
\ No newline at end of file
+	let mut config = bp.config();
+	options.apply_to(&mut config);
+	let mut rng = breeding::seeded_rng(seed);
+	let fitness_function = Arc::new(bp.fitness_function(&targets));
+	let result = if config.islands > 1 {
+		breeding::island_breeder(genes, targets, site, fitness_function,
+			pop_size, generations, &config, verbosity, &mut rng)
+	} else {
+		breeding::breeder(genes, targets, site, fitness_function, pop_size,
+			generations, &config, verbosity, &mut rng)
+	};
+	let mut layout = result.as_layout();
+	layout.set_room_types(bp.room_types());
+	let recovered = layout.compact();
+	if verbosity != Verbosity::Quiet {
+		println!("\nCompaction recovered {} tiles of bounding box area",
+		recovered);
+	}
+	if layout.overflow() > 0 {
+		let _ = writeln!(io::stderr(), "\nWARNING! Couldn't fit the layout in \
+			the area, {} tiles are outside it", layout.overflow());
+	}
+	let unconnected = layout.connect();
+	if unconnected > 0 {
+		let _ = writeln!(io::stderr(), "\nWARNING! Couldn't route corridors \
+			between {} room pairs", unconnected);
+	}
+	let violated = layout.violated_separations();
+	for i in 0..violated.len() {
+		let (t, from, to, distance) = violated[i];
+		let min_distance = match layout.get_targets()[t].kind {
+			TargetKind::Repel(min_distance) => min_distance,
+			_ => continue,
+		};
+		let rooms = layout.get_rooms();
+		let _ = writeln!(io::stderr(), "\nWARNING! {} and {} are only {:.1} \
+			tiles apart, should be at least {}",
+			layout.name_for(rooms[from].get_key()),
+			layout.name_for(rooms[to].get_key()), distance, min_distance);
+	}
+	let unmet = layout.unmet_adjacencies();
+	for t in 0..layout.get_targets().len() {
+		let count = unmet.iter().filter(|u| u.0 == t).count();
+		if count == 0 {
+			continue;
+		}
+		let target = &layout.get_targets()[t];
+		let rooms = layout.get_rooms();
+		let _ = writeln!(io::stderr(), "\nWARNING! Couldn't put {} of {} rooms \
+			of type {} next to a room of type {}", count, target.from_id.len(),
+			layout.name_for(rooms[target.from_id[0]].get_key()),
+			layout.name_for(rooms[target.to_id[0]].get_key()));
+	}
+	let levels = layout.shaft_levels();
+	let mut matrices = Vector::new_with_size(levels.len());
+	for i in 0..levels.len() {
+		matrices.push((levels[i], layout.as_level_matrix(levels[i])));
+	}
+	let seed_file = format!("{}.seed", output_file);
+	let saved = output::save_levels_with_legend(&matrices, &layout.legend(),
+		output_file.clone())
+		.and_then(|_| output::save(format!("{}\n", seed), seed_file))
+		.and_then(|_| match options.json {
+			Some(ref json_file) => export::save_json(&layout,
+				json_file.clone()),
+			None => Ok(()),
+		})
+		.and_then(|_| match options.svg {
+			Some(ref svg_file) => svg::save_svg(&layout, options.svg_targets,
+				svg_file.clone()),
+			None => Ok(()),
+		})
+		.and_then(|_| match options.quickfort {
+			Some(ref dig_file) => quickfort::save_blueprint(&layout,
+				quickfort::Mode::Dig, dig_file.clone()),
+			None => Ok(()),
+		})
+		.and_then(|_| match options.quickfort_build {
+			Some(ref build_file) => quickfort::save_blueprint(&layout,
+				quickfort::Mode::Build, build_file.clone()),
+			None => Ok(()),
+		});
+	let code = match saved {
+		Err(reason) => {
+			let _ = writeln!(io::stderr(), "\nERROR! Couldn't save the result! \
+				({:?})", reason);
+			EXIT_OUTPUT
+		}
+		Ok(_) => {
+			if verbosity != Verbosity::Quiet {
+				println!("\nSuccesfully saved result to {}", output_file);
+			}
+			0
+		}
+	};
+	if interactive {
+		// Give the user some time to read the results before a console
+		// window closes
+		let sleep_time = time::Duration::new(5, 0);
+		thread::sleep(sleep_time);
+	}
+	code
+}
+
+/// Asks the user for an input file until they give one that exists
+fn ask_input_file() -> String {
+	loop {
+		println!("Input file name: ");
+		let input_file = String::from(get_input_loop().trim());
+		if input::exists(&input_file) {
+			return input_file;
+		}
+		println!("\"{}\" doesn't exist in the dwarfitect binary folder!",
+		input_file);
+	}
+}
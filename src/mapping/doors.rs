@@ -0,0 +1,191 @@
+//! Module for the doors of rooms. Doors are given on the walls of a room as it
+//! is in its blueprint, and they turn with the room when it's rotated.
+
+use mapping::shapes::{Point, Rect, Direction};
+use mapping::shapes::Direction::{Left, Right, Up, Down};
+use collections::Vector;
+
+/// Maximum number of doors a room can have
+pub const MAX_DOORS: usize = 4;
+
+/// A door on the walls of a room
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Door {
+    /// On given side of the room, offset tiles from the left end of the top
+    /// and bottom walls or from the top end of the side walls. Corners can't
+    /// have doors.
+    At(Direction, isize),
+    /// Anywhere on the walls, wherever a corridor happens to need one
+    Anywhere,
+}
+
+/// The doors of a room. A room without any doors listed can have them
+/// anywhere. Doors is Copy, so that genes carrying them can be too.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Doors {
+    doors: [Door; MAX_DOORS],
+    len: usize,
+}
+
+impl Door {
+    /// Gives the door as it is after its room is rotated clockwise, given the
+    /// height of the room (walls included) before rotating
+    pub fn rotate(&self, h: isize) -> Door {
+        match *self {
+            Door::At(Up, offset) => Door::At(Right, offset),
+            Door::At(Right, offset) => Door::At(Down, h - 1 - offset),
+            Door::At(Down, offset) => Door::At(Left, offset),
+            Door::At(Left, offset) => Door::At(Up, h - 1 - offset),
+            Door::Anywhere => Door::Anywhere,
+        }
+    }
+    /// Gives the door as it is after its room is mirrored from left to right,
+    /// given the width of the room (walls included)
+    pub fn mirror(&self, w: isize) -> Door {
+        match *self {
+            Door::At(Up, offset) => Door::At(Up, w - 1 - offset),
+            Door::At(Down, offset) => Door::At(Down, w - 1 - offset),
+            Door::At(Left, offset) => Door::At(Right, offset),
+            Door::At(Right, offset) => Door::At(Left, offset),
+            Door::Anywhere => Door::Anywhere,
+        }
+    }
+    /// Checks that the door is on a wall of a room with given size (walls
+    /// included) and not on a corner
+    pub fn fits(&self, w: isize, h: isize) -> bool {
+        match *self {
+            Door::At(Up, offset) | Door::At(Down, offset) =>
+                offset >= 1 && offset <= w - 2,
+            Door::At(Left, offset) | Door::At(Right, offset) =>
+                offset >= 1 && offset <= h - 2,
+            Door::Anywhere => true,
+        }
+    }
+    /// Gives the wall tile of the door on given room, or None if the door can
+    /// be anywhere
+    pub fn tile(&self, room: Rect) -> Option<Point> {
+        let bottom_right = room.bottom_right();
+        match *self {
+            Door::At(Up, offset) => Some(Point::new(room.x + offset, room.y)),
+            Door::At(Down, offset) => Some(Point::new(room.x + offset,
+                                                      bottom_right.y - 1)),
+            Door::At(Left, offset) => Some(Point::new(room.x, room.y + offset)),
+            Door::At(Right, offset) => Some(Point::new(bottom_right.x - 1,
+                                                       room.y + offset)),
+            Door::Anywhere => None,
+        }
+    }
+    /// Gives the tile right outside the door on given room, where corridors
+    /// leave from, or None if the door can be anywhere
+    pub fn outside(&self, room: Rect) -> Option<Point> {
+        self.tile(room).map(|tile| match *self {
+            Door::At(Up, _) => Point::new(tile.x, tile.y - 1),
+            Door::At(Down, _) => Point::new(tile.x, tile.y + 1),
+            Door::At(Left, _) => Point::new(tile.x - 1, tile.y),
+            _ => Point::new(tile.x + 1, tile.y),
+        })
+    }
+}
+
+impl Doors {
+    /// Creates a list without any doors; the room can have them anywhere
+    pub fn new() -> Self {
+        Doors {
+            doors: [Door::Anywhere; MAX_DOORS],
+            len: 0,
+        }
+    }
+    /// Adds a door to the list. Returns false if there already are MAX_DOORS
+    /// doors.
+    pub fn push(&mut self, door: Door) -> bool {
+        if self.len == MAX_DOORS {
+            return false;
+        }
+        self.doors[self.len] = door;
+        self.len += 1;
+        true
+    }
+    /// Gives the listed doors
+    pub fn as_slice(&self) -> &[Door] {
+        &self.doors[..self.len]
+    }
+    /// Checks if the room has doors only at the listed places
+    pub fn are_fixed(&self) -> bool {
+        self.len > 0 && !self.as_slice().contains(&Door::Anywhere)
+    }
+    /// Gives the doors as they are after their room is rotated clockwise,
+    /// given the height of the room (walls included) before rotating
+    pub fn rotate(&self, h: isize) -> Doors {
+        let mut rotated = *self;
+        for i in 0..self.len {
+            rotated.doors[i] = self.doors[i].rotate(h);
+        }
+        rotated
+    }
+    /// Gives the doors as they are after their room is mirrored from left to
+    /// right, given the width of the room (walls included)
+    pub fn mirror(&self, w: isize) -> Doors {
+        let mut mirrored = *self;
+        for i in 0..self.len {
+            mirrored.doors[i] = self.doors[i].mirror(w);
+        }
+        mirrored
+    }
+    /// Gives the wall tiles of the listed doors that have a place on given
+    /// room
+    pub fn tiles(&self, room: Rect) -> Vector<Point> {
+        let mut tiles = Vector::new();
+        for i in 0..self.len {
+            if let Some(tile) = self.doors[i].tile(room) {
+                tiles.push(tile);
+            }
+        }
+        tiles
+    }
+    /// Gives the points that distances to given room are measured from: the
+    /// wall tiles of the doors if they are fixed, otherwise the center of the
+    /// room
+    pub fn entrances(&self, room: Rect) -> Vector<Point> {
+        let mut entrances = if self.are_fixed() {
+            self.tiles(room)
+        } else {
+            Vector::new()
+        };
+        if entrances.len() == 0 {
+            entrances.push(room.center());
+        }
+        entrances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use mapping::shapes::{Point, Rect};
+    use mapping::shapes::Direction::{Left, Right, Up, Down};
+
+    #[test]
+    fn doors_turn_with_room() {
+        let mut doors = Doors::new();
+        assert!(!doors.are_fixed());
+        doors.push(Door::At(Up, 1));
+        doors.push(Door::At(Left, 3));
+        assert!(doors.are_fixed());
+        // A 4x5 room turns into a 5x4 room
+        let rotated = doors.rotate(5);
+        let room = Rect::new(10, 0, 5, 4);
+        let mut tiles = rotated.tiles(room);
+        tiles.sort();
+        assert_eq!(&[Point::new(11, 0), Point::new(14, 1)], &tiles[..]);
+        assert_eq!(Some(Point::new(15, 1)), rotated.as_slice()[0].outside(room));
+        // Four turns bring the doors back
+        assert_eq!(doors, rotated.rotate(4).rotate(5).rotate(4));
+        let mirrored = doors.mirror(4).as_slice().to_vec();
+        assert_eq!(vec![Door::At(Up, 2), Door::At(Right, 3)], mirrored);
+        doors.push(Door::Anywhere);
+        assert!(!doors.are_fixed());
+        assert!(!Door::At(Down, 3).fits(4, 5));
+        assert!(Door::At(Left, 3).fits(4, 5));
+    }
+}
@@ -0,0 +1,358 @@
+//! Module for the shapes of rooms. Rooms are rectangles unless their blueprint
+//! gives them a mask: an ASCII picture of which tiles of the bounding
+//! rectangle belong to the room. Walls are the room's own outermost tiles, the
+//! same way as with rectangular rooms.
+
+use mapping::shapes::{Point, Rect};
+use collections::Vector;
+
+/// Maximum width and height of a mask; each row of a mask is stored as bits
+pub const MAX_MASK_SIZE: usize = 32;
+/// Character of a mask tile that belongs to the room
+pub const MASK_TILE: char = '#';
+/// Character of a mask tile that doesn't belong to the room
+pub const MASK_EMPTY: char = '.';
+
+/// The tiles of a bounding rectangle that belong to a room. Mask is Copy, so
+/// that genes carrying one can be too.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub struct Mask {
+    rows: [u32; MAX_MASK_SIZE],
+    w: isize,
+    h: isize,
+}
+
+/// The shape of a room inside its bounding rectangle
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Shape {
+    /// The room fills its bounding rectangle
+    Rectangle,
+    /// The room only has the tiles of the mask
+    Masked(Mask),
+}
+
+impl Mask {
+    /// Parses a mask from its rows of MASK_TILE and MASK_EMPTY characters.
+    /// Returns None if the rows aren't all as long, the mask is larger than
+    /// MAX_MASK_SIZE, has other characters, has a row or a column without
+    /// tiles or its tiles aren't all connected to each other.
+    pub fn parse(lines: &[String]) -> Option<Mask> {
+        let h = lines.len();
+        let w = match lines.first() {
+            Some(line) => line.chars().count(),
+            None => return None,
+        };
+        if w == 0 || w > MAX_MASK_SIZE || h > MAX_MASK_SIZE {
+            return None;
+        }
+        let mut mask = Mask {
+            rows: [0; MAX_MASK_SIZE],
+            w: w as isize,
+            h: h as isize,
+        };
+        for y in 0..h {
+            if lines[y].chars().count() != w {
+                return None;
+            }
+            for (x, c) in lines[y].chars().enumerate() {
+                match c {
+                    MASK_TILE => mask.rows[y] |= 1 << x,
+                    MASK_EMPTY => {}
+                    _ => return None,
+                }
+            }
+        }
+        let columns = mask.rows.iter().fold(0, |columns, &row| columns | row);
+        if mask.rows[..h].contains(&0) || columns.count_ones() as usize != w {
+            return None;
+        }
+        if mask.is_connected() { Some(mask) } else { None }
+    }
+    /// Checks that every tile can be reached from every other one through
+    /// tiles next to each other
+    fn is_connected(&self) -> bool {
+        let mut reached = [0u32; MAX_MASK_SIZE];
+        let mut stack = Vector::new();
+        let first = self.rows[0].trailing_zeros() as isize;
+        reached[0] |= 1 << first;
+        stack.push(Point::new(first, 0));
+        while let Some(tile) = stack.pop() {
+            let neighbours = [(tile.x - 1, tile.y), (tile.x + 1, tile.y),
+                              (tile.x, tile.y - 1), (tile.x, tile.y + 1)];
+            for &(x, y) in neighbours.iter() {
+                if self.contains(x, y) && reached[y as usize] & (1 << x) == 0 {
+                    reached[y as usize] |= 1 << x;
+                    stack.push(Point::new(x, y));
+                }
+            }
+        }
+        reached == self.rows
+    }
+    pub fn get_w(&self) -> isize {
+        self.w
+    }
+    pub fn get_h(&self) -> isize {
+        self.h
+    }
+    /// Checks if the tile at (x, y), relative to the top left corner of the
+    /// mask, belongs to the room
+    pub fn contains(&self, x: isize, y: isize) -> bool {
+        x >= 0 && y >= 0 && x < self.w && y < self.h &&
+        self.rows[y as usize] & (1 << x) != 0
+    }
+    /// Gives the mask turned clockwise; its width and height are swapped
+    pub fn rotate(&self) -> Mask {
+        let mut rotated = Mask {
+            rows: [0; MAX_MASK_SIZE],
+            w: self.h,
+            h: self.w,
+        };
+        for y in 0..rotated.h {
+            for x in 0..rotated.w {
+                if self.contains(y, self.h - 1 - x) {
+                    rotated.rows[y as usize] |= 1 << x;
+                }
+            }
+        }
+        rotated
+    }
+    /// Gives the mask mirrored from left to right
+    pub fn mirror(&self) -> Mask {
+        let mut mirrored = *self;
+        for y in 0..self.h {
+            mirrored.rows[y as usize] = 0;
+            for x in 0..self.w {
+                if self.contains(self.w - 1 - x, y) {
+                    mirrored.rows[y as usize] |= 1 << x;
+                }
+            }
+        }
+        mirrored
+    }
+    /// Gives the rows of the mask in the same format as it's parsed from
+    pub fn lines(&self) -> Vector<String> {
+        let mut lines = Vector::new_with_size(self.h as usize);
+        for y in 0..self.h {
+            lines.push((0..self.w)
+                .map(|x| if self.contains(x, y) { MASK_TILE } else { MASK_EMPTY })
+                .collect());
+        }
+        lines
+    }
+}
+
+impl Shape {
+    /// Checks if the room fills its bounding rectangle
+    pub fn is_rectangle(&self) -> bool {
+        *self == Shape::Rectangle
+    }
+    /// Checks if the tile belongs to a room of this shape with given bounding
+    /// rectangle
+    pub fn contains(&self, room: Rect, tile: Point) -> bool {
+        match *self {
+            Shape::Rectangle => room.collides_with(Rect::new(tile.x, tile.y,
+                                                             1, 1)),
+            Shape::Masked(ref mask) => mask.contains(tile.x - room.x,
+                                                     tile.y - room.y),
+        }
+    }
+    /// Checks if the tile is a wall of the room: it belongs to the room, but
+    /// one of the eight tiles around it doesn't
+    pub fn is_wall(&self, room: Rect, tile: Point) -> bool {
+        if !self.contains(room, tile) {
+            return false;
+        }
+        for y in tile.y - 1..tile.y + 2 {
+            for x in tile.x - 1..tile.x + 2 {
+                if !self.contains(room, Point::new(x, y)) {
+                    return true;
+                }
+            }
+        }
+        false
+    }
+    /// Gives the tiles of the room with given bounding rectangle
+    pub fn tiles(&self, room: Rect) -> Vector<Point> {
+        let mut tiles = Vector::new();
+        for y in room.y..room.y + room.h {
+            for x in room.x..room.x + room.w {
+                if self.contains(room, Point::new(x, y)) {
+                    tiles.push(Point::new(x, y));
+                }
+            }
+        }
+        tiles
+    }
+    /// Gives the places where a door could be on the room with given bounding
+    /// rectangle, as pairs of the wall tile and the tile right outside it. A
+    /// door must be in the middle of a straight wall, so that there is room
+    /// on both sides of it.
+    pub fn door_spots(&self, room: Rect) -> Vector<(Point, Point)> {
+        let mut spots = Vector::new();
+        let walls = self.tiles(room);
+        for i in 0..walls.len() {
+            let wall = walls[i];
+            let sides = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+            for &(dx, dy) in sides.iter() {
+                let outside = Point::new(wall.x + dx, wall.y + dy);
+                let inside = Point::new(wall.x - dx, wall.y - dy);
+                let along = [Point::new(wall.x + dy, wall.y + dx),
+                             Point::new(wall.x - dy, wall.y - dx)];
+                if !self.contains(room, outside) &&
+                   self.contains(room, inside) &&
+                   along.iter().all(|&tile| self.contains(room, tile)) {
+                    spots.push((wall, outside));
+                }
+            }
+        }
+        spots
+    }
+    /// Checks if the tile is covered by a room of this shape when the room is
+    /// given by its gene rectangle: the bounding rectangle grown by the one
+    /// tile gap that's left after rooms to the right and below them
+    pub fn covers(&self, gene_rect: Rect, tile: Point) -> bool {
+        let room = Rect::new(gene_rect.x, gene_rect.y, gene_rect.w - 1,
+                             gene_rect.h - 1);
+        match *self {
+            Shape::Rectangle => gene_rect.collides_with(Rect::new(tile.x,
+                                                                  tile.y, 1, 1)),
+            Shape::Masked(_) => [(0, 0), (1, 0), (0, 1), (1, 1)].iter().any(
+                |&(dx, dy)| self.contains(room, Point::new(tile.x - dx,
+                                                           tile.y - dy))),
+        }
+    }
+    /// Gives the shape turned clockwise with its room
+    pub fn rotate(&self) -> Shape {
+        match *self {
+            Shape::Rectangle => Shape::Rectangle,
+            Shape::Masked(ref mask) => Shape::Masked(mask.rotate()),
+        }
+    }
+    /// Gives the shape mirrored from left to right with its room
+    pub fn mirror(&self) -> Shape {
+        match *self {
+            Shape::Rectangle => Shape::Rectangle,
+            Shape::Masked(ref mask) => Shape::Masked(mask.mirror()),
+        }
+    }
+}
+
+/// Checks if two rooms, given by their gene rectangles (see Shape::covers) and
+/// shapes, collide: they overlap or have no gap between them. Rooms whose
+/// bounding rectangles collide may still fit into each other's empty corners.
+pub fn collide(a: Rect, a_shape: &Shape, b: Rect, b_shape: &Shape) -> bool {
+    if !a.collides_with(b) {
+        return false;
+    }
+    if a_shape.is_rectangle() && b_shape.is_rectangle() {
+        return true;
+    }
+    for y in a.y.max(b.y)..(a.y + a.h).min(b.y + b.h) {
+        for x in a.x.max(b.x)..(a.x + a.w).min(b.x + b.w) {
+            let tile = Point::new(x, y);
+            if a_shape.covers(a, tile) && b_shape.covers(b, tile) {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Gives the length of the edge that two rooms, given by their gene rectangles
+/// (see Shape::covers) and shapes, share: the number of tiles covered by one
+/// room right next to a tile covered by the other. Rooms whose bounding
+/// rectangles touch may still only meet across each other's empty corners.
+pub fn contact_length(a: Rect, a_shape: &Shape, b: Rect, b_shape: &Shape)
+    -> isize {
+    if a_shape.is_rectangle() && b_shape.is_rectangle() {
+        return a.contact_length(b);
+    }
+    let mut length = 0;
+    for y in a.y.max(b.y - 1)..(a.y + a.h).min(b.y + b.h + 1) {
+        for x in a.x.max(b.x - 1)..(a.x + a.w).min(b.x + b.w + 1) {
+            let tile = Point::new(x, y);
+            if !a_shape.covers(a, tile) || b_shape.covers(b, tile) {
+                continue;
+            }
+            let sides = [(1, 0), (-1, 0), (0, 1), (0, -1)];
+            if sides.iter().any(|&(dx, dy)| {
+                b_shape.covers(b, Point::new(x + dx, y + dy))
+            }) {
+                length += 1;
+            }
+        }
+    }
+    length
+}
+
+#[cfg(test)]
+pub mod tests {
+
+    use super::*;
+    use mapping::shapes::{Point, Rect};
+
+    /// Turns the rows of a mask picture into the strings that Mask::parse takes
+    pub fn lines(rows: &[&str]) -> Vec<String> {
+        rows.iter().map(|row| row.to_string()).collect()
+    }
+
+    #[test]
+    fn masks_turn_and_mirror() {
+        let mask = Mask::parse(&lines(&["###",
+                                        "#..",
+                                        "#.."])).unwrap();
+        assert_eq!(&["###", "..#", "..#"], &mask.rotate().lines()[..]);
+        assert_eq!(&["###", "..#", "..#"], &mask.mirror().lines()[..]);
+        assert_eq!(&["#..", "#..", "###"], &mask.rotate().rotate().mirror()
+                                                .lines()[..]);
+        assert_eq!(mask, mask.rotate().rotate().rotate().rotate());
+        assert_eq!(None, Mask::parse(&lines(&["##", "#"])));
+        assert_eq!(None, Mask::parse(&lines(&["#.", ".."])));
+        assert_eq!(None, Mask::parse(&lines(&["#.", ".#"])));
+        assert_eq!(None, Mask::parse(&lines(&["#x"])));
+    }
+
+    #[test]
+    fn masked_rooms_fit_into_each_others_corners() {
+        let l = Shape::Masked(Mask::parse(&lines(&["###...",
+                                                   "###...",
+                                                   "######",
+                                                   "######"])).unwrap());
+        let turned = l.rotate().rotate();
+        // The bounding rectangles overlap only where neither room has tiles
+        assert!(!collide(Rect::new(0, 0, 7, 5), &l, Rect::new(4, -2, 7, 5),
+                         &turned));
+        assert!(collide(Rect::new(0, 0, 7, 5), &l, Rect::new(3, -2, 7, 5),
+                        &turned));
+        assert!(collide(Rect::new(0, 0, 7, 5), &l, Rect::new(4, -2, 7, 5),
+                        &Shape::Rectangle));
+        assert!(l.is_wall(Rect::new(0, 0, 6, 4), Point::new(2, 1)));
+        assert!(!l.is_wall(Rect::new(0, 0, 6, 4), Point::new(1, 2)));
+        let spots = l.door_spots(Rect::new(0, 0, 6, 4));
+        assert!(spots.contains(&(Point::new(4, 2), Point::new(4, 1))));
+        assert!(!spots.iter().any(|&(wall, _)| wall == Point::new(2, 0)));
+    }
+
+    #[test]
+    fn contact_is_measured_on_covered_tiles() {
+        let l = Shape::Masked(Mask::parse(&lines(&["##....",
+                                                   "##....",
+                                                   "##....",
+                                                   "######",
+                                                   "######"])).unwrap());
+        let a = Rect::new(0, 0, 7, 6);
+        let rect = Shape::Rectangle;
+        // The bounding rectangles share an edge of 4 tiles over the empty
+        // corner, but the rooms don't meet
+        let above = Rect::new(3, -4, 4, 4);
+        assert_eq!(4, a.contact_length(above));
+        assert_eq!(0, contact_length(a, &l, above, &rect));
+        assert_eq!(0, contact_length(above, &rect, a, &l));
+        // Under the L, the rooms share its whole bottom wall
+        let below = Rect::new(0, 6, 6, 4);
+        assert_eq!(6, contact_length(a, &l, below, &rect));
+        assert_eq!(6, contact_length(below, &rect, a, &l));
+        assert_eq!(3, contact_length(Rect::new(0, 0, 3, 3), &rect,
+                                     Rect::new(3, -1, 5, 5), &rect));
+    }
+}
@@ -3,4 +3,8 @@
 
 #[macro_use]
 pub mod shapes;
-pub mod rooms;
\ No newline at end of file
+pub mod rooms;
+pub mod doors;
+pub mod masks;
+pub mod paths;
+pub mod site;
\ No newline at end of file
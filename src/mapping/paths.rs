@@ -0,0 +1,389 @@
+//! Module for finding paths between rooms. Uses A* on a grid of tiles where
+//! blocked tiles are marked in a Matrix.
+
+use std::cmp::Ordering;
+use mapping::shapes::{Point, Rect};
+use mapping::doors::Doors;
+use mapping::masks::Shape;
+use collections::{Heap, Matrix, Vector};
+
+/// The steps that can be taken from a tile: right, left, down and up
+const DIRECTIONS: [Point; 4] = [Point { x: 1, y: 0 }, Point { x: -1, y: 0 },
+                                Point { x: 0, y: 1 }, Point { x: 0, y: -1 }];
+
+/// A corridor that connects two rooms of a layout on level z. Tiles are listed
+/// in order, from the wall of the from-room to the wall of the to-room. If the
+/// rooms are on different levels, the corridor leads from one of them to the
+/// stairs.
+pub struct Corridor {
+    pub from: usize,
+    pub to: usize,
+    pub z: isize,
+    pub tiles: Vector<Point>,
+}
+
+/// A tile waiting in the A* open set
+struct Node {
+    estimate: isize,
+    cost: isize,
+    point: Point,
+}
+
+impl PartialEq for Node {
+    fn eq(&self, other: &Node) -> bool {
+        self.estimate == other.estimate && self.cost == other.cost
+    }
+}
+
+impl PartialOrd for Node {
+    /// Orders the nodes by their estimated total cost. Ties are broken in
+    /// favour of the node that has already travelled further, as it's
+    /// probably closer to the goal.
+    fn partial_cmp(&self, other: &Node) -> Option<Ordering> {
+        match self.estimate.cmp(&other.estimate) {
+            Ordering::Equal => Some(other.cost.cmp(&self.cost)),
+            ordering => Some(ordering),
+        }
+    }
+}
+
+impl Corridor {
+    /// Creates a new corridor between rooms from and to on level z
+    pub fn new(from: usize, to: usize, z: isize, tiles: Vector<Point>) -> Self {
+        Corridor {
+            from: from,
+            to: to,
+            z: z,
+            tiles: tiles,
+        }
+    }
+}
+
+/// Checks if the tile at given point is inside the grid and not blocked
+pub fn is_free(grid: &Matrix<bool>, point: Point) -> bool {
+    point.x >= 0 && point.y >= 0 && (point.x as usize) < grid.w &&
+    (point.y as usize) < grid.h &&
+    grid.get(point.x as usize, point.y as usize).is_none()
+}
+
+/// Returns the free tiles that are directly next to the walls of given rect,
+/// excluding the corners (a door in a corner wouldn't lead anywhere). Walls of
+/// one or two tiles have no tiles but corners, so the tiles next to all of
+/// their tiles are given instead; otherwise small rooms couldn't be reached.
+pub fn door_tiles(grid: &Matrix<bool>, rect: Rect) -> Vector<Point> {
+    let mut tiles = Vector::new();
+    let bottom_right = rect.bottom_right();
+    let (min_x, max_x) = if rect.w > 2 {
+        (rect.x + 1, bottom_right.x - 1)
+    } else {
+        (rect.x, bottom_right.x)
+    };
+    let (min_y, max_y) = if rect.h > 2 {
+        (rect.y + 1, bottom_right.y - 1)
+    } else {
+        (rect.y, bottom_right.y)
+    };
+    for x in min_x..max_x {
+        push_if_free(grid, &mut tiles, Point::new(x, rect.y - 1));
+        push_if_free(grid, &mut tiles, Point::new(x, bottom_right.y));
+    }
+    for y in min_y..max_y {
+        push_if_free(grid, &mut tiles, Point::new(rect.x - 1, y));
+        push_if_free(grid, &mut tiles, Point::new(bottom_right.x, y));
+    }
+    tiles
+}
+
+/// Pushes the point to tiles if it's free in the grid
+fn push_if_free(grid: &Matrix<bool>, tiles: &mut Vector<Point>, point: Point) {
+    if is_free(grid, point) {
+        tiles.push(point);
+    }
+}
+
+/// Returns the free tiles that a corridor may leave a room from: the tiles
+/// right outside its doors if they are fixed, otherwise its door_tiles, or the
+/// tiles outside its door spots if it has a mask
+pub fn exits(grid: &Matrix<bool>, rect: Rect, shape: &Shape, doors: &Doors)
+    -> Vector<Point> {
+    if !doors.are_fixed() {
+        if shape.is_rectangle() {
+            return door_tiles(grid, rect);
+        }
+        let spots = shape.door_spots(rect);
+        let mut tiles = Vector::new_with_size(spots.len());
+        for i in 0..spots.len() {
+            push_if_free(grid, &mut tiles, spots[i].1);
+        }
+        return tiles;
+    }
+    let mut tiles = Vector::new();
+    for door in doors.as_slice() {
+        if let Some(tile) = door.outside(rect) {
+            push_if_free(grid, &mut tiles, tile);
+        }
+    }
+    tiles
+}
+
+/// Returns the wall tile of a room at rect that's next to the given tile
+/// outside of the room
+pub fn wall_next_to(rect: Rect, shape: &Shape, tile: Point) -> Point {
+    if !shape.is_rectangle() {
+        let spots = shape.door_spots(rect);
+        for i in 0..spots.len() {
+            if spots[i].1 == tile {
+                return spots[i].0;
+            }
+        }
+    }
+    let bottom_right = rect.bottom_right();
+    Point::new(clamp(tile.x, rect.x, bottom_right.x - 1),
+               clamp(tile.y, rect.y, bottom_right.y - 1))
+}
+
+fn clamp(n: isize, min: isize, max: isize) -> isize {
+    if n < min {
+        min
+    } else if n > max {
+        max
+    } else {
+        n
+    }
+}
+
+/// Manhattan distance between two points
+pub fn manhattan(a: Point, b: Point) -> isize {
+    let diff = a.diff(b);
+    diff.x.abs() + diff.y.abs()
+}
+
+/// Heuristic for A*: the manhattan distance to the closest goal
+fn estimate(point: Point, goals: &Vector<Point>) -> isize {
+    let mut smallest = isize::max_value();
+    for i in 0..goals.len() {
+        let dist = manhattan(point, goals[i]);
+        if dist < smallest {
+            smallest = dist;
+        }
+    }
+    smallest
+}
+
+/// Finds a shortest path through the free tiles of the grid from any of the
+/// start tiles to any of the goal tiles using A*. Moves only horizontally and
+/// vertically. Returns the tiles of the path in order (including the start and
+/// goal tiles), or None if no path exists.
+pub fn find_path(grid: &Matrix<bool>, starts: &Vector<Point>,
+                 goals: &Vector<Point>) -> Option<Vector<Point>> {
+    if goals.len() == 0 {
+        return None;
+    }
+    let mut open = Heap::new();
+    let mut costs: Matrix<isize> = Matrix::new(grid.w, grid.h);
+    let mut came_from: Matrix<Point> = Matrix::new(grid.w, grid.h);
+    let mut is_goal: Matrix<bool> = Matrix::new(grid.w, grid.h);
+    for i in 0..goals.len() {
+        if is_free(grid, goals[i]) {
+            is_goal.set(goals[i].x as usize, goals[i].y as usize, true);
+        }
+    }
+    for i in 0..starts.len() {
+        let start = starts[i];
+        if is_free(grid, start) {
+            costs.set(start.x as usize, start.y as usize, 0);
+            open.push(Node {
+                estimate: estimate(start, goals),
+                cost: 0,
+                point: start,
+            });
+        }
+    }
+    while let Some(node) = open.pop() {
+        let current = node.point;
+        let best_cost = costs.get(current.x as usize, current.y as usize)
+            .unwrap_or(isize::max_value());
+        if node.cost > best_cost {
+            continue; // A better route to this tile was already handled
+        }
+        if is_goal.get(current.x as usize, current.y as usize).is_some() {
+            return Some(build_path(&came_from, current));
+        }
+        for d in 0..DIRECTIONS.len() {
+            let next = Point::new(current.x + DIRECTIONS[d].x,
+                                  current.y + DIRECTIONS[d].y);
+            if !is_free(grid, next) {
+                continue;
+            }
+            let cost = node.cost + 1;
+            let known = *costs.get(next.x as usize, next.y as usize);
+            if known.map_or(true, |c| cost < c) {
+                costs.set(next.x as usize, next.y as usize, cost);
+                came_from.set(next.x as usize, next.y as usize, current);
+                open.push(Node {
+                    estimate: cost + estimate(next, goals),
+                    cost: cost,
+                    point: next,
+                });
+            }
+        }
+    }
+    None
+}
+
+/// Finds the lengths of the shortest paths from the start tiles to every free
+/// tile of the grid. Each start tile comes with a cost that paths starting
+/// from it begin with. Tiles that can't be reached are left empty in the
+/// returned matrix.
+pub fn distances_from(grid: &Matrix<bool>, starts: &Vector<(Point, isize)>)
+    -> Matrix<isize> {
+    // Every step costs the same, so walking breadth first reaches the tiles in
+    // the order of their cost, as long as each start tile joins the walk when
+    // the walk gets to its cost
+    let mut starts = starts.clone();
+    starts.sort_by(|a, b| a.1.cmp(&b.1));
+    let mut costs: Matrix<isize> = Matrix::new(grid.w, grid.h);
+    let mut queue: Vector<Point> = Vector::new();
+    let mut next = 0;
+    let mut s = 0;
+    loop {
+        let queued_cost = if next < queue.len() {
+            costs.get(queue[next].x as usize, queue[next].y as usize).unwrap()
+        } else {
+            isize::max_value()
+        };
+        let start_is_next = s < starts.len() && starts[s].1 <= queued_cost;
+        let (current, cost) = if start_is_next {
+            let (start, cost) = starts[s];
+            s += 1;
+            if !is_free(grid, start) {
+                continue;
+            }
+            let known = *costs.get(start.x as usize, start.y as usize);
+            if known.map_or(false, |c| c <= cost) {
+                continue;
+            }
+            costs.set(start.x as usize, start.y as usize, cost);
+            (start, cost)
+        } else if next < queue.len() {
+            next += 1;
+            (queue[next - 1], queued_cost)
+        } else {
+            break;
+        };
+        for d in 0..DIRECTIONS.len() {
+            let neighbour = Point::new(current.x + DIRECTIONS[d].x,
+                                       current.y + DIRECTIONS[d].y);
+            if !is_free(grid, neighbour) {
+                continue;
+            }
+            let known = *costs.get(neighbour.x as usize, neighbour.y as usize);
+            if known.map_or(true, |c| cost + 1 < c) {
+                costs.set(neighbour.x as usize, neighbour.y as usize, cost + 1);
+                queue.push(neighbour);
+            }
+        }
+    }
+    costs
+}
+
+/// Walks the came_from matrix back from the end tile and returns the path in
+/// start-to-end order
+fn build_path(came_from: &Matrix<Point>, end: Point) -> Vector<Point> {
+    let mut reversed = Vector::new();
+    let mut current = end;
+    reversed.push(current);
+    while let Some(previous) = *came_from.get(current.x as usize,
+                                              current.y as usize) {
+        reversed.push(previous);
+        current = previous;
+    }
+    let mut path = Vector::new_with_size(reversed.len());
+    while let Some(point) = reversed.pop() {
+        path.push(point);
+    }
+    path
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use mapping::shapes::{Point, Rect};
+    use mapping::masks::Shape;
+    use collections::{Matrix, Vector};
+
+    #[test]
+    fn find_path_goes_around_walls() {
+        let mut grid = Matrix::new(5, 5);
+        for y in 0..4 {
+            grid.set(2, y, true);
+        }
+        let mut starts = Vector::new();
+        starts.push(Point::new(0, 0));
+        let mut goals = Vector::new();
+        goals.push(Point::new(4, 0));
+        let path = find_path(&grid, &starts, &goals).unwrap();
+        assert_eq!(13, path.len());
+        assert_eq!(Point::new(0, 0), path[0]);
+        assert_eq!(Point::new(4, 0), path[path.len() - 1]);
+        for i in 0..path.len() {
+            assert!(is_free(&grid, path[i]));
+        }
+    }
+
+    #[test]
+    fn find_path_returns_none_when_blocked() {
+        let mut grid = Matrix::new(5, 5);
+        for y in 0..5 {
+            grid.set(2, y, true);
+        }
+        let mut starts = Vector::new();
+        starts.push(Point::new(0, 0));
+        let mut goals = Vector::new();
+        goals.push(Point::new(4, 4));
+        assert!(find_path(&grid, &starts, &goals).is_none());
+    }
+
+    #[test]
+    fn distances_from_adds_start_costs() {
+        let mut grid = Matrix::new(5, 3);
+        for y in 0..2 {
+            grid.set(2, y, true);
+        }
+        let mut starts = Vector::new();
+        starts.push((Point::new(0, 0), 3));
+        starts.push((Point::new(1, 0), 0));
+        let distances = distances_from(&grid, &starts);
+        assert_eq!(Some(1), *distances.get(0, 0));
+        assert_eq!(Some(7), *distances.get(4, 0));
+        assert_eq!(None, *distances.get(2, 0));
+    }
+
+    #[test]
+    fn door_tiles_skip_corners() {
+        let grid = Matrix::new(7, 7);
+        let tiles = door_tiles(&grid, Rect::new(1, 1, 4, 3));
+        assert_eq!(6, tiles.len());
+        for i in 0..tiles.len() {
+            let wall = wall_next_to(Rect::new(1, 1, 4, 3), &Shape::Rectangle,
+                                    tiles[i]);
+            assert_eq!(1, (wall.x - tiles[i].x).abs() +
+                          (wall.y - tiles[i].y).abs());
+        }
+    }
+
+    #[test]
+    fn small_rooms_have_door_tiles() {
+        let grid = Matrix::new(7, 7);
+        let tiles = door_tiles(&grid, Rect::new(3, 3, 1, 1));
+        assert_eq!(4, tiles.len());
+        assert!(tiles.contains(&Point::new(3, 2)));
+        assert!(tiles.contains(&Point::new(4, 3)));
+        // A room one tile wide can only be left from the sides of its middle
+        let tiles = door_tiles(&grid, Rect::new(3, 1, 1, 4));
+        assert_eq!(6, tiles.len());
+        assert!(!tiles.contains(&Point::new(2, 1)));
+        assert_eq!(8, door_tiles(&grid, Rect::new(2, 2, 2, 2)).len());
+    }
+}
@@ -1,36 +1,524 @@
 //! This module contains functionality related to manipulating a solution.
 
-use mapping::shapes::{Rect, Point};
+use mapping::shapes::{self, Rect, Point, Orientation};
+use mapping::paths::{self, Corridor};
+use mapping::doors::Doors;
+use mapping::masks::{self, Shape};
+use mapping::site::Site;
+use genetics::genes::{self, Target, TargetKind, ADJACENT_CONTACT, LEVEL_DISTANCE};
 use std::cmp::Ordering;
+use std::sync::Arc;
 use collections::{Matrix, Vector};
 
+/// Character used for corridor tiles (and the openings they and doors make in
+/// walls)
+pub const CORRIDOR_CHAR: char = '+';
+/// Character used for room walls
+pub const WALL_CHAR: char = '#';
+/// Character used for the stair shaft that connects the levels of a layout
+pub const STAIRS_CHAR: char = '>';
+/// Glyphs given to room types by default, in order of room keys
+const DEFAULT_GLYPHS: &'static str = "0123456789abcdefghijklmnopqrstuvwxyz\
+                                      ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+/// Glyph for room types whose key is too large to get a default glyph. Such
+/// room types may share it, so only glyphs given in the blueprint are checked
+/// for duplicates.
+const FALLBACK_GLYPH: char = '.';
+
 /// Room is the equvalient of Gene in a finished solution
 pub struct Room {
     rect: Rect,
+    key: usize,
+    z: isize,
+    pinned: bool,
+    doors: Doors,
+    shape: Shape,
+    orientation: Orientation,
+}
+
+/// The name and the glyph that a type of room is drawn with
+#[derive(Clone, PartialEq, Debug)]
+pub struct RoomType {
+    pub key: usize,
+    pub name: String,
+    pub glyph: char,
 }
 
 /// Layout is the equvalient of Chromosome in a finished solution
 pub struct Layout {
     rooms: Vector<Room>,
+    targets: Vector<Target>,
+    corridors: Vector<Corridor>,
+    room_types: Vector<RoomType>,
+    fitness: f32,
+    site: Arc<Site>,
 }
 
 // Implement methods that manipulate the rectangle inside the room
 impl_rect_methods!(Room, rect);
 
 impl Room {
-	/// Creates a new room from given rect
-    pub fn new(rect: Rect) -> Self {
-        Room { rect: rect }
+	/// Creates a new room of given type from given rect
+    pub fn new(rect: Rect, key: usize) -> Self {
+        Room::new_on_level(rect, key, 0)
+    }
+    /// Creates a new room of given type from given rect on z-level z
+    pub fn new_on_level(rect: Rect, key: usize, z: isize) -> Self {
+        Room { rect: rect, key: key, z: z, pinned: false, doors: Doors::new(),
+               shape: Shape::Rectangle, orientation: Orientation::new() }
+    }
+    /// Pins or unpins the room. Compaction doesn't move pinned rooms.
+    pub fn set_pinned(&mut self, pinned: bool) {
+        self.pinned = pinned;
+    }
+    /// Checks if the room is pinned
+    pub fn is_pinned(&self) -> bool {
+        self.pinned
+    }
+    /// Gives the key of this room's type
+    pub fn get_key(&self) -> usize {
+        self.key
+    }
+    /// Gives the z-level of this room
+    pub fn get_z(&self) -> isize {
+        self.z
+    }
+    /// Sets the doors of the room. Corridors only go through fixed doors.
+    pub fn set_doors(&mut self, doors: Doors) {
+        self.doors = doors;
+    }
+    /// Gives the doors of the room
+    pub fn get_doors(&self) -> Doors {
+        self.doors
+    }
+    /// Gives the wall tiles of the room's fixed doors
+    pub fn door_tiles(&self) -> Vector<Point> {
+        self.doors.tiles(self.rect)
+    }
+    /// Gives the points that distances to the room are measured from (see
+    /// Doors::entrances)
+    pub fn entrances(&self) -> Vector<Point> {
+        self.doors.entrances(self.rect)
+    }
+    /// Sets the shape of the room inside its rectangle
+    pub fn set_shape(&mut self, shape: Shape) {
+        self.shape = shape;
+    }
+    /// Gives the shape of the room
+    pub fn get_shape(&self) -> Shape {
+        self.shape
+    }
+    /// Sets the orientation of the room compared to its blueprint
+    pub fn set_orientation(&mut self, orientation: Orientation) {
+        self.orientation = orientation;
+    }
+    /// Gives the orientation of the room compared to its blueprint
+    pub fn get_orientation(&self) -> Orientation {
+        self.orientation
+    }
+    /// Gives the tiles of the room, walls included
+    pub fn tiles(&self) -> Vector<Point> {
+        self.shape.tiles(self.rect)
+    }
+    /// Checks if the tile belongs to the room
+    pub fn contains(&self, tile: Point) -> bool {
+        self.shape.contains(self.rect, tile)
+    }
+    /// Rotates the room in place (switches its width with its height) and
+    /// turns its doors and shape with it
+    pub fn rot_in_place(&mut self) {
+        self.doors = self.doors.rotate(self.rect.h);
+        self.shape = self.shape.rotate();
+        self.orientation = self.orientation.rotate();
+        self.rect = self.rect.rotate();
     }
 }
 
+impl RoomType {
+    /// Creates a new room type
+    pub fn new(key: usize, name: String, glyph: char) -> Self {
+        RoomType {
+            key: key,
+            name: name,
+            glyph: glyph,
+        }
+    }
+}
+
+/// Gives the glyph used for a room type that hasn't been given one
+pub fn default_glyph(key: usize) -> char {
+    DEFAULT_GLYPHS.chars().nth(key).unwrap_or(FALLBACK_GLYPH)
+}
+
+/// Checks if the glyph is used for something else than room floors
+pub fn is_reserved_glyph(glyph: char) -> bool {
+    glyph == WALL_CHAR || glyph == CORRIDOR_CHAR || glyph == STAIRS_CHAR ||
+    glyph.is_whitespace()
+}
+
 impl Layout {
-	/// Creates a new layout from given rooms
-    pub fn new(rooms: Vector<Room>) -> Self {
-        Layout { rooms: rooms }
+	/// Creates a new layout from given rooms. Room indexes in targets should
+	/// correspond to the indexes in rooms.
+    pub fn new(rooms: Vector<Room>, targets: Vector<Target>) -> Self {
+        Layout {
+            rooms: rooms,
+            targets: targets,
+            corridors: Vector::new(),
+            room_types: Vector::new(),
+            fitness: 0.0,
+            site: Arc::new(Site::empty()),
+        }
+    }
+    /// Sets the site the layout is on. Compaction and corridors avoid its
+    /// blocked tiles.
+    pub fn set_site(&mut self, site: Arc<Site>) {
+        self.site = site;
+    }
+    /// Sets the fitness that the solution this layout was made from achieved
+    pub fn set_fitness(&mut self, fitness: f32) {
+        self.fitness = fitness;
+    }
+    /// Gives the fitness that the solution this layout was made from achieved
+    pub fn get_fitness(&self) -> f32 {
+        self.fitness
+    }
+    /// Gives the rooms of this layout
+    pub fn get_rooms(&self) -> &Vector<Room> {
+        &self.rooms
+    }
+    /// Gives the targets of this layout
+    pub fn get_targets(&self) -> &Vector<Target> {
+        &self.targets
+    }
+    /// Gives the distance travelled between the nearest entrances of rooms
+    /// with given indexes (see Room::entrances), going through the stairs if
+    /// they are on different levels. The fitness is measured the same way.
+    pub fn travel_distance(&self, from: usize, to: usize) -> f32 {
+        let from_entrances = self.rooms[from].entrances();
+        let to_entrances = self.rooms[to].entrances();
+        if self.rooms[from].z == self.rooms[to].z {
+            return shapes::nearest_dist(&from_entrances, &to_entrances);
+        }
+        let stairs = [self.rooms[0].center()];
+        let levels = (self.rooms[from].z - self.rooms[to].z).abs() as f32;
+        shapes::nearest_dist(&from_entrances, &stairs) +
+            levels * LEVEL_DISTANCE + shapes::nearest_dist(&stairs, &to_entrances)
+    }
+    /// Gives the z-levels that have rooms on them, from the top level down
+    pub fn levels(&self) -> Vector<isize> {
+        let mut levels: Vector<isize> = Vector::new();
+        for i in 0..self.rooms.len() {
+            if !levels.contains(&self.rooms[i].z) {
+                levels.push(self.rooms[i].z);
+            }
+        }
+        levels.sort();
+        levels
+    }
+    /// Gives the number of room tiles outside the bounds of the site
+    pub fn overflow(&self) -> isize {
+        let mut overflow = 0;
+        for i in 0..self.rooms.len() {
+            overflow += self.site.overflow(self.rooms[i].rect);
+        }
+        overflow
+    }
+    /// Gives every z-level from the top level of the layout to the bottom one,
+    /// including the levels without rooms that the stair shaft goes through
+    pub fn shaft_levels(&self) -> Vector<isize> {
+        let levels = self.levels();
+        let mut all = Vector::new();
+        if let (Some(&top), Some(&bottom)) = (levels.first(), levels.last()) {
+            for z in top..bottom + 1 {
+                all.push(z);
+            }
+        }
+        all
+    }
+    /// Gives the position of the stair shaft, which is in the center of room
+    /// 0, or None if the layout has only one level
+    pub fn stairs(&self) -> Option<Point> {
+        if self.levels().len() > 1 {
+            Some(self.rooms[0].center())
+        } else {
+            None
+        }
+    }
+    /// Calculates the average travel distance between the rooms of each
+    /// target (see travel_distance), in the same order as the targets
+    pub fn target_distances(&self) -> Vector<f32> {
+        let mut distances = Vector::new_with_size(self.targets.len());
+        for t in 0..self.targets.len() {
+            let target = &self.targets[t];
+            let mut total = 0.0;
+            let mut n = 0;
+            for i in 0..target.from_id.len() {
+                for j in 0..target.to_id.len() {
+                    total += self.travel_distance(target.from_id[i],
+                                                  target.to_id[j]);
+                    n += 1;
+                }
+            }
+            distances.push(if n > 0 { total / n as f32 } else { 0.0 });
+        }
+        distances
     }
-    /// Calculates a smallest bounding box for the layout
-    fn calculate_bounding_box(&self) -> Rect {
+    /// Finds the pairs of rooms of repulsion targets that are closer to each
+    /// other than the target's minimum distance. Returns the index of the
+    /// target, the indexes of the rooms and their distance for each pair.
+    pub fn violated_separations(&self) -> Vector<(usize, usize, usize, f32)> {
+        let mut violations = Vector::new();
+        for t in 0..self.targets.len() {
+            let target = &self.targets[t];
+            let min_distance = match target.kind {
+                TargetKind::Repel(min_distance) => min_distance,
+                _ => continue,
+            };
+            for i in 0..target.from_id.len() {
+                for j in 0..target.to_id.len() {
+                    let (from, to) = (target.from_id[i], target.to_id[j]);
+                    // Pairs that are in the target both ways are only
+                    // reported once
+                    if from == to || (from > to &&
+                        target.from_id.contains(&to) &&
+                        target.to_id.contains(&from)) {
+                        continue;
+                    }
+                    let distance = self.travel_distance(from, to);
+                    if distance < min_distance {
+                        violations.push((t, from, to, distance));
+                    }
+                }
+            }
+        }
+        violations
+    }
+    /// Gives how much too close to each other the rooms of repulsion targets
+    /// are in total (see violated_separations)
+    fn separation_shortfall(&self) -> f32 {
+        let violations = self.violated_separations();
+        let mut shortfall = 0.0;
+        for i in 0..violations.len() {
+            let (t, _, _, distance) = violations[i];
+            if let TargetKind::Repel(min_distance) = self.targets[t].kind {
+                shortfall += min_distance - distance;
+            }
+        }
+        shortfall
+    }
+    /// Finds the rooms of adjacency targets that aren't next to any of the
+    /// rooms they should be next to. Returns (target index, room index) pairs.
+    pub fn unmet_adjacencies(&self) -> Vector<(usize, usize)> {
+        genes::find_unmet_adjacencies(&self.targets,
+                                      |from, to| self.are_adjacent(from, to))
+    }
+    /// Sets the room types (names and glyphs) used when drawing the layout
+    /// and its legend
+    pub fn set_room_types(&mut self, room_types: Vector<RoomType>) {
+        self.room_types = room_types;
+    }
+    /// Gives the glyph that rooms with given key are drawn with
+    fn glyph_for(&self, key: usize) -> char {
+        for i in 0..self.room_types.len() {
+            if self.room_types[i].key == key {
+                return self.room_types[i].glyph;
+            }
+        }
+        default_glyph(key)
+    }
+    /// Gives the name of the room type with given key
+    pub fn name_for(&self, key: usize) -> String {
+        for i in 0..self.room_types.len() {
+            if self.room_types[i].key == key {
+                return self.room_types[i].name.clone();
+            }
+        }
+        format!("Room {}", key)
+    }
+    /// Creates a legend that explains the characters of as_char_matrix. Each
+    /// line tells the glyph, name, key and amount of one type of room.
+    pub fn legend(&self) -> Vector<String> {
+        let mut legend = Vector::new();
+        legend.push(format!("{} Wall", WALL_CHAR));
+        if self.corridors.len() > 0 ||
+           self.rooms.iter().any(|room| room.doors.are_fixed()) {
+            legend.push(format!("{} Corridor", CORRIDOR_CHAR));
+        }
+        if self.stairs().is_some() {
+            legend.push(format!("{} Stairs", STAIRS_CHAR));
+        }
+        let mut keys: Vector<usize> = Vector::new();
+        for i in 0..self.rooms.len() {
+            if !keys.contains(&self.rooms[i].key) {
+                keys.push(self.rooms[i].key);
+            }
+        }
+        keys.sort();
+        for i in 0..keys.len() {
+            let mut amount = 0;
+            for j in 0..self.rooms.len() {
+                if self.rooms[j].key == keys[i] {
+                    amount += 1;
+                }
+            }
+            legend.push(format!("{} {} (key {}, {} room{})",
+                                self.glyph_for(keys[i]), self.name_for(keys[i]),
+                                keys[i], amount,
+                                if amount == 1 { "" } else { "s" }));
+        }
+        legend
+    }
+    /// Connects every pair of rooms specified by the attraction targets with a
+    /// corridor that goes through the free space between rooms. Rooms on different
+    /// levels are both connected to the stairs instead. Replaces any
+    /// previously made corridors. Returns the number of room pairs that
+    /// couldn't be connected.
+    pub fn connect(&mut self) -> usize {
+        self.corridors = Vector::new();
+        // Leave a border of one tile around the rooms for corridors to use
+        let rooms_box = self.calculate_rooms_bounding_box();
+        let origin = Point::new(rooms_box.x - 1, rooms_box.y - 1);
+        let levels = self.levels();
+        let mut grids: Vector<Matrix<bool>> = Vector::new_with_size(levels.len());
+        for _ in 0..levels.len() {
+            grids.push(Matrix::new((rooms_box.w + 2) as usize,
+                                   (rooms_box.h + 2) as usize));
+        }
+        let mut rects = Vector::new_with_size(self.rooms.len());
+        for i in 0..self.rooms.len() {
+            let mut rect = self.rooms[i].rect;
+            rect.x -= origin.x;
+            rect.y -= origin.y;
+            let grid = &mut grids[level_index(&levels, self.rooms[i].z)];
+            let tiles = self.rooms[i].shape.tiles(rect);
+            for t in 0..tiles.len() {
+                grid.set(tiles[t].x as usize, tiles[t].y as usize, true);
+            }
+            rects.push(rect);
+        }
+        // Corridors can't go through blocked tiles or outside the bounds either
+        for l in 0..levels.len() {
+            let grid = &mut grids[l];
+            for x in 0..grid.w {
+                for y in 0..grid.h {
+                    let tile = Point::new(x as isize + origin.x,
+                                          y as isize + origin.y);
+                    if self.site.is_blocked(tile.x, tile.y, levels[l]) ||
+                       !self.site.is_inside(Rect::new(tile.x, tile.y, 1, 1)) {
+                        grid.set(x, y, true);
+                    }
+                }
+            }
+        }
+        let stairs = self.stairs().map(|p| Point::new(p.x - origin.x, p.y - origin.y));
+        let mut connected: Vector<(usize, usize)> = Vector::new();
+        let mut at_stairs: Vector<usize> = Vector::new();
+        let mut failed = 0;
+        for t in 0..self.targets.len() {
+            if !self.targets[t].is_attraction() {
+                continue;
+            }
+            for i in 0..self.targets[t].from_id.len() {
+                for j in 0..self.targets[t].to_id.len() {
+                    let from = self.targets[t].from_id[i];
+                    let to = self.targets[t].to_id[j];
+                    if from == to || connected.contains(&(from, to)) ||
+                       connected.contains(&(to, from)) {
+                        continue;
+                    }
+                    connected.push((from, to));
+                    let z = self.rooms[from].z;
+                    if z == self.rooms[to].z {
+                        let grid = &grids[level_index(&levels, z)];
+                        match route(grid, rects[from], &self.rooms[from],
+                                    rects[to], &self.rooms[to]) {
+                            Some(tiles) => self.corridors.push(Corridor::new(
+                                from, to, z, shifted(tiles, origin))),
+                            None => failed += 1,
+                        }
+                        continue;
+                    }
+                    // Rooms on different levels meet at the stairs
+                    let mut reached = true;
+                    for &room in [from, to].iter() {
+                        if at_stairs.contains(&room) {
+                            continue;
+                        }
+                        let room_z = self.rooms[room].z;
+                        let grid = &grids[level_index(&levels, room_z)];
+                        let shaft = stairs.unwrap();
+                        let mut stair_room = None;
+                        for r in 0..self.rooms.len() {
+                            if self.rooms[r].z == room_z &&
+                               self.rooms[r].shape.contains(rects[r], shaft) {
+                                stair_room = Some(r);
+                            }
+                        }
+                        let tiles = match stair_room {
+                            Some(r) if r == room => Some(Vector::new()),
+                            Some(r) => route(grid, rects[room],
+                                             &self.rooms[room], rects[r],
+                                             &self.rooms[r]),
+                            None => route_to_point(grid, rects[room],
+                                                   &self.rooms[room], shaft),
+                        };
+                        match tiles {
+                            Some(tiles) => {
+                                at_stairs.push(room);
+                                if tiles.len() > 0 {
+                                    let other = if room == from { to } else { from };
+                                    self.corridors.push(Corridor::new(
+                                        room, other, room_z, shifted(tiles, origin)));
+                                }
+                            }
+                            None => reached = false,
+                        }
+                    }
+                    if !reached {
+                        failed += 1;
+                    }
+                }
+            }
+        }
+        failed
+    }
+    /// Returns the corridors made by connect
+    pub fn get_corridors(&self) -> &Vector<Corridor> {
+        &self.corridors
+    }
+    /// Calculates a smallest bounding box for the layout, including corridors
+    pub fn calculate_bounding_box(&self) -> Rect {
+        let rooms_box = self.calculate_rooms_bounding_box();
+        let mut min_x = rooms_box.x;
+        let mut min_y = rooms_box.y;
+        let mut max_x = rooms_box.x + rooms_box.w;
+        let mut max_y = rooms_box.y + rooms_box.h;
+        for i in 0..self.corridors.len() {
+            let tiles = &self.corridors[i].tiles;
+            for j in 0..tiles.len() {
+                if tiles[j].x < min_x {
+                    min_x = tiles[j].x;
+                }
+                if tiles[j].y < min_y {
+                    min_y = tiles[j].y;
+                }
+                if tiles[j].x + 1 > max_x {
+                    max_x = tiles[j].x + 1;
+                }
+                if tiles[j].y + 1 > max_y {
+                    max_y = tiles[j].y + 1;
+                }
+            }
+        }
+        Rect {
+            x: min_x,
+            y: min_y,
+            w: max_x - min_x,
+            h: max_y - min_y,
+        }
+    }
+    /// Calculates a smallest bounding box for the rooms of the layout
+    fn calculate_rooms_bounding_box(&self) -> Rect {
         let mut min_x = isize::max_value();
         let mut min_y = isize::max_value();
         let mut max_x = isize::min_value();
@@ -58,28 +546,584 @@ impl Layout {
             h: max_y - min_y,
         }
     }
-    /// Converts the layout to a char matrix for saving purposes
+    /// Compacts the layout: slides every room that isn't pinned towards room 0,
+    /// nearest rooms first, for as long as it can be done without the room
+    /// colliding with another one, going further outside the site's bounds or
+    /// onto blocked tiles (see can_move). Rooms that must be next to each
+    /// other are slid together. A gap of one tile is kept between rooms so
+    /// that corridors still fit. If the layout had corridors, they are routed
+    /// again after the rooms have been moved. Returns the amount of bounding
+    /// box area recovered.
+    pub fn compact(&mut self) -> isize {
+        let area_before = self.calculate_bounding_box().area();
+        let had_corridors = self.corridors.len() > 0;
+        self.corridors = Vector::new();
+        if self.rooms.len() > 1 {
+            let anchor = self.rooms[0].center();
+            let mut order: Vector<usize> = Vector::new_with_size(self.rooms.len());
+            for i in 1..self.rooms.len() {
+                if !self.rooms[i].pinned {
+                    order.push(i);
+                }
+            }
+            {
+                let rooms = &self.rooms;
+                order.sort_by(|a, b| {
+                    let dist_a = anchor.dist(rooms[*a].center());
+                    let dist_b = anchor.dist(rooms[*b].center());
+                    dist_a.partial_cmp(&dist_b).unwrap_or(Ordering::Equal)
+                });
+            }
+            for i in 0..order.len() {
+                self.slide_towards(order[i], anchor);
+            }
+        }
+        if had_corridors {
+            self.connect();
+        }
+        area_before - self.calculate_bounding_box().area()
+    }
+    /// Moves the room with given index one tile at a time towards the given
+    /// point until it can't move any closer. The rooms it's required to be
+    /// next to (and the rooms they are required to be next to) move along with
+    /// it, unless one of them is pinned or room 0; then steps that would leave
+    /// more rooms without the rooms they must be next to are not taken. Steps
+    /// that would bring repelled rooms closer than they may be aren't taken
+    /// either.
+    fn slide_towards(&mut self, index: usize, point: Point) {
+        let mut group = self.adjacency_group(index);
+        if group.iter().any(|&i| i == 0 || self.rooms[i].pinned) {
+            group = Vector::new();
+            group.push(index);
+        }
+        let unmet = self.unmet_adjacencies().len();
+        loop {
+            let mut moved = false;
+            let center = self.rooms[index].center();
+            let step_x = (point.x - center.x).signum();
+            let step_y = (point.y - center.y).signum();
+            if step_x != 0 && self.try_move(&group, step_x, 0, unmet) {
+                moved = true;
+            }
+            if step_y != 0 && self.try_move(&group, 0, step_y, unmet) {
+                moved = true;
+            }
+            if !moved {
+                break;
+            }
+        }
+    }
+    /// Gives the room with given index and all the rooms that are linked to
+    /// it by met adjacency targets, directly or through other rooms
+    fn adjacency_group(&self, index: usize) -> Vector<usize> {
+        let mut group = Vector::new();
+        group.push(index);
+        let mut i = 0;
+        while i < group.len() {
+            let room = group[i];
+            for t in 0..self.targets.len() {
+                let target = &self.targets[t];
+                if target.kind != TargetKind::Adjacent {
+                    continue;
+                }
+                let others = if target.from_id.contains(&room) {
+                    &target.to_id
+                } else if target.to_id.contains(&room) {
+                    &target.from_id
+                } else {
+                    continue;
+                };
+                for j in 0..others.len() {
+                    let other = others[j];
+                    if !group.contains(&other) && self.are_adjacent(room, other) {
+                        group.push(other);
+                    }
+                }
+            }
+            i += 1;
+        }
+        group
+    }
+    /// Checks if the rooms with given indexes are right next to each other
+    /// (see Gene::is_adjacent_to)
+    fn are_adjacent(&self, a: usize, b: usize) -> bool {
+        let (a, b) = (&self.rooms[a], &self.rooms[b]);
+        a.z == b.z &&
+        masks::contact_length(with_gap(a.rect), &a.shape, with_gap(b.rect),
+                              &b.shape) >= ADJACENT_CONTACT
+    }
+    /// Moves the rooms with given indexes by (dx, dy) if they can be moved
+    /// there, the move doesn't leave more than `unmet` rooms without the
+    /// rooms they must be next to and it doesn't increase the separation
+    /// shortfall. Returns true if the rooms were moved.
+    fn try_move(&mut self, group: &Vector<usize>, dx: isize, dy: isize,
+                unmet: usize) -> bool {
+        if !self.can_move(group, dx, dy) {
+            return false;
+        }
+        let shortfall = self.separation_shortfall();
+        for i in 0..group.len() {
+            self.rooms[group[i]].rect.x += dx;
+            self.rooms[group[i]].rect.y += dy;
+        }
+        if self.unmet_adjacencies().len() > unmet ||
+           self.separation_shortfall() > shortfall {
+            for i in 0..group.len() {
+                self.rooms[group[i]].rect.x -= dx;
+                self.rooms[group[i]].rect.y -= dy;
+            }
+            return false;
+        }
+        true
+    }
+    /// Checks if the rooms with given indexes can be moved by (dx, dy) without
+    /// getting next to or on top of another room on the same level, further
+    /// outside the site's bounds, on blocked tiles of the site or over the
+    /// stair shaft on another level than room 0's
+    fn can_move(&self, group: &Vector<usize>, dx: isize, dy: isize) -> bool {
+        for g in 0..group.len() {
+            let index = group[g];
+            let room = &self.rooms[index];
+            let mut rect = room.rect;
+            rect.x += dx;
+            rect.y += dy;
+            if self.site.overflow(rect) > self.site.overflow(room.rect) ||
+               self.site.blocked_tile_under(rect, &room.shape, room.z).is_some() {
+                return false;
+            }
+            if room.z != self.rooms[0].z &&
+               room.shape.contains(rect, self.rooms[0].center()) {
+                return false;
+            }
+            for i in 0..self.rooms.len() {
+                let other = &self.rooms[i];
+                if !group.contains(&i) && other.z == room.z &&
+                   masks::collide(with_gap(rect), &room.shape,
+                                  with_gap(other.rect), &other.shape) {
+                    return false;
+                }
+            }
+        }
+        true
+    }
+    /// Converts the layout to a char matrix for saving purposes. The levels
+    /// the stair shaft goes through (see as_level_matrix) are drawn from the
+    /// top down, one below another with an empty row between them.
     pub fn as_char_matrix(&self) -> Matrix<char> {
+        let levels = self.shaft_levels();
+        if levels.len() == 0 {
+            return self.as_level_matrix(0);
+        }
+        let bounding_box = self.calculate_bounding_box();
+        let (w, h) = (bounding_box.w as usize, bounding_box.h as usize);
+        let mut matrix = Matrix::new(w, levels.len() * (h + 1) - 1);
+        for l in 0..levels.len() {
+            let level = self.as_level_matrix(levels[l]);
+            for y in 0..h {
+                for x in 0..w {
+                    if let Some(c) = *level.get(x, y) {
+                        matrix.set(x, l * (h + 1) + y, c);
+                    }
+                }
+            }
+        }
+        matrix
+    }
+    /// Converts the level z of the layout to a char matrix. All levels are
+    /// drawn to the same size so that they line up.
+    pub fn as_level_matrix(&self, z: isize) -> Matrix<char> {
         let bounding_box = self.calculate_bounding_box();
         let top_left = bounding_box.top_left();
         let mut matrix = Matrix::new(bounding_box.w as usize, bounding_box.h as usize);
         for i in 0..self.rooms.len() {
-            let w = self.rooms[i].get_w();
-            let h = self.rooms[i].get_h();
-            let room_top_left = self.rooms[i].top_left();
-            let base_x = room_top_left.x - top_left.x;
-            let base_y = room_top_left.y - top_left.y;
-            for x in 0..w {
-                for y in 0..h {
-                    if x == 0 || x == w - 1 || y == 0 || y == h - 1 {
-                        matrix.set((x + base_x) as usize, (y + base_y) as usize, '#');
-                        // TODO: Different chars
-                    } else {
-                        matrix.set((x + base_x) as usize, (y + base_y) as usize, '.');
-                    }
-                }
+            if self.rooms[i].z != z {
+                continue;
+            }
+            let glyph = self.glyph_for(self.rooms[i].key);
+            let rect = self.rooms[i].rect;
+            let shape = self.rooms[i].shape;
+            let tiles = self.rooms[i].tiles();
+            for t in 0..tiles.len() {
+                let c = if shape.is_wall(rect, tiles[t]) {
+                    WALL_CHAR
+                } else {
+                    glyph
+                };
+                matrix.set((tiles[t].x - top_left.x) as usize,
+                           (tiles[t].y - top_left.y) as usize, c);
+            }
+            // Fixed doors are gaps in the wall
+            let doors = self.rooms[i].door_tiles();
+            for d in 0..doors.len() {
+                matrix.set((doors[d].x - top_left.x) as usize,
+                           (doors[d].y - top_left.y) as usize, CORRIDOR_CHAR);
             }
         }
+        for i in 0..self.corridors.len() {
+            if self.corridors[i].z != z {
+                continue;
+            }
+            let tiles = &self.corridors[i].tiles;
+            for j in 0..tiles.len() {
+                matrix.set((tiles[j].x - top_left.x) as usize,
+                           (tiles[j].y - top_left.y) as usize,
+                           CORRIDOR_CHAR);
+            }
+        }
+        if let Some(stairs) = self.stairs() {
+            matrix.set((stairs.x - top_left.x) as usize,
+                       (stairs.y - top_left.y) as usize, STAIRS_CHAR);
+        }
         matrix
     }
 }
+
+/// Gives the index of level z in a list of levels
+fn level_index(levels: &Vector<isize>, z: isize) -> usize {
+    let mut index = 0;
+    while levels[index] != z {
+        index += 1;
+    }
+    index
+}
+
+/// Routes a corridor between the walls of two rooms on a grid, through their
+/// doors if they are fixed. The rooms are at the given rects in grid
+/// coordinates. The corridor includes the wall tiles where it enters the rooms.
+fn route(grid: &Matrix<bool>, from: Rect, from_room: &Room, to: Rect,
+         to_room: &Room) -> Option<Vector<Point>> {
+    let starts = paths::exits(grid, from, &from_room.shape, &from_room.doors);
+    let goals = paths::exits(grid, to, &to_room.shape, &to_room.doors);
+    paths::find_path(grid, &starts, &goals).map(|path| {
+        let mut tiles = Vector::new_with_size(path.len() + 2);
+        tiles.push(paths::wall_next_to(from, &from_room.shape, path[0]));
+        for p in 0..path.len() {
+            tiles.push(path[p]);
+        }
+        tiles.push(paths::wall_next_to(to, &to_room.shape,
+                                       path[path.len() - 1]));
+        tiles
+    })
+}
+
+/// Routes a corridor from the wall of a room at given rect to a free point on
+/// a grid
+fn route_to_point(grid: &Matrix<bool>, from: Rect, from_room: &Room,
+                  point: Point) -> Option<Vector<Point>> {
+    let starts = paths::exits(grid, from, &from_room.shape, &from_room.doors);
+    let mut goals = Vector::new();
+    goals.push(point);
+    paths::find_path(grid, &starts, &goals).map(|path| {
+        let mut tiles = Vector::new_with_size(path.len() + 1);
+        tiles.push(paths::wall_next_to(from, &from_room.shape, path[0]));
+        for p in 0..path.len() {
+            tiles.push(path[p]);
+        }
+        tiles
+    })
+}
+
+/// Moves corridor tiles from grid coordinates back to layout coordinates
+fn shifted(mut tiles: Vector<Point>, origin: Point) -> Vector<Point> {
+    for p in 0..tiles.len() {
+        tiles[p].x += origin.x;
+        tiles[p].y += origin.y;
+    }
+    tiles
+}
+
+/// Grows the rect by the one tile gap that's left between rooms (the same way
+/// a gene is one tile larger than its room)
+fn with_gap(rect: Rect) -> Rect {
+    Rect::new(rect.x, rect.y, rect.w + 1, rect.h + 1)
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use mapping::shapes::Rect;
+    use mapping::shapes::Direction::Down;
+    use mapping::doors::{Door, Doors};
+    use mapping::masks::{Mask, Shape};
+    use mapping::masks::tests::lines;
+    use genetics::genes::Target;
+    use collections::Vector;
+
+    #[test]
+    fn connect_routes_corridors_between_targets() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(6, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(0, 8, 11, 4), 0));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        to.push(2);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from, to, 1.0));
+        let mut layout = Layout::new(rooms, targets);
+        assert_eq!(0, layout.connect());
+        assert_eq!(2, layout.get_corridors().len());
+        let matrix = layout.as_char_matrix();
+        let mut corridor_tiles = 0;
+        for x in 0..matrix.w {
+            for y in 0..matrix.h {
+                if *matrix.get(x, y) == Some(CORRIDOR_CHAR) {
+                    corridor_tiles += 1;
+                }
+            }
+        }
+        // Room 0 and 1 are separated by a single column, room 2 by 3 rows
+        assert_eq!(3 + 5, corridor_tiles);
+    }
+
+    #[test]
+    fn corridors_reach_rooms_of_one_tile() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(8, 2, 1, 1), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from, to, 1.0));
+        let mut layout = Layout::new(rooms, targets);
+        assert_eq!(0, layout.connect());
+        assert_eq!(1, layout.get_corridors().len());
+    }
+
+    #[test]
+    fn corridors_go_through_fixed_doors() {
+        let mut doors = Doors::new();
+        doors.push(Door::At(Down, 2));
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        let mut room = Room::new(Rect::new(6, 0, 5, 5), 1);
+        room.set_doors(doors);
+        rooms.push(room);
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from, to, 1.0));
+        let mut layout = Layout::new(rooms, targets);
+        // The door is a gap in the wall even before there are corridors
+        assert_eq!(Some(CORRIDOR_CHAR), *layout.as_char_matrix().get(8, 4));
+        assert_eq!(0, layout.connect());
+        let tiles = &layout.get_corridors()[0].tiles;
+        assert_eq!(Point::new(8, 4), tiles[tiles.len() - 1]);
+        assert_eq!(Point::new(8, 5), tiles[tiles.len() - 2]);
+    }
+
+    #[test]
+    fn separations_are_not_routed_but_checked() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(6, 0, 5, 5), 1));
+        rooms.push(Room::new(Rect::new(20, 0, 5, 5), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        to.push(2);
+        let mut targets = Vector::new();
+        targets.push(Target::new_repulsion(from, to.clone(), 1.0, 10.0));
+        targets.push(Target::new_repulsion(to.clone(), to, 1.0, 15.0));
+        let mut layout = Layout::new(rooms, targets);
+        assert_eq!(0, layout.connect());
+        assert_eq!(0, layout.get_corridors().len());
+        let violated = layout.violated_separations();
+        assert_eq!(2, violated.len());
+        assert_eq!((0, 0, 1, 6.0), violated[0]);
+        // Rooms 1 and 2 are only reported once
+        assert_eq!((1, 1, 2, 14.0), violated[1]);
+    }
+
+    #[test]
+    fn compact_keeps_adjacent_rooms_next_to_each_other() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(20, 0, 5, 5), 1));
+        rooms.push(Room::new(Rect::new(26, 0, 5, 5), 2));
+        let mut from = Vector::new();
+        from.push(2);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new_adjacency(from.clone(), to, 1.0));
+        let mut to = Vector::new();
+        to.push(0);
+        targets.push(Target::new_adjacency(from, to, 1.0));
+        let mut layout = Layout::new(rooms, targets);
+        let mut unmet = Vector::new();
+        unmet.push((1, 2));
+        assert!(unmet == layout.unmet_adjacencies());
+        layout.compact();
+        // Room 2 could slide next to room 0 only by leaving room 1
+        assert_eq!(6, layout.get_rooms()[1].get_x());
+        assert_eq!(12, layout.get_rooms()[2].get_x());
+        assert!(unmet == layout.unmet_adjacencies());
+    }
+
+    #[test]
+    fn rooms_on_different_levels_are_connected_through_stairs() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new_on_level(Rect::new(0, 0, 5, 5), 0, 0));
+        rooms.push(Room::new_on_level(Rect::new(8, 0, 5, 5), 1, 0));
+        rooms.push(Room::new_on_level(Rect::new(0, 7, 5, 5), 2, 1));
+        let mut from = Vector::new();
+        from.push(1);
+        let mut to = Vector::new();
+        to.push(2);
+        let mut targets = Vector::new();
+        targets.push(Target::new(from, to, 1.0));
+        let mut layout = Layout::new(rooms, targets);
+        let mut levels = Vector::new();
+        levels.push(0);
+        levels.push(1);
+        assert!(levels == layout.levels());
+        assert_eq!(Some(Point::new(2, 2)), layout.stairs());
+        assert_eq!(0, layout.connect());
+        // Room 1 leads to room 0 that has the stairs, and room 2 to the
+        // bottom of the stair shaft
+        assert_eq!(2, layout.get_corridors().len());
+        assert_eq!(0, layout.get_corridors()[0].z);
+        assert_eq!(1, layout.get_corridors()[1].z);
+        let top = layout.as_level_matrix(0);
+        let bottom = layout.as_level_matrix(1);
+        assert_eq!(Some(STAIRS_CHAR), *top.get(2, 2));
+        assert_eq!(Some(STAIRS_CHAR), *bottom.get(2, 2));
+        assert_eq!(None, *bottom.get(1, 1));
+        assert_eq!(Some('2'), *bottom.get(1, 8));
+        assert_eq!(None, *top.get(1, 8));
+        // Both levels are drawn, one below another
+        let matrix = layout.as_char_matrix();
+        assert_eq!(2 * top.h + 1, matrix.h);
+        assert_eq!(Some('2'), *matrix.get(1, top.h + 1 + 8));
+    }
+
+    #[test]
+    fn compaction_keeps_rooms_inside_the_site() {
+        use mapping::site::Site;
+        use std::sync::Arc;
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(12, 0, 5, 5), 1));
+        let mut layout = Layout::new(rooms, Vector::new());
+        let mut site = Site::empty();
+        site.set_bounds(Rect::new(10, -10, 20, 20));
+        layout.set_site(Arc::new(site));
+        let overflow = layout.overflow();
+        layout.compact();
+        assert_eq!(Rect::new(10, 0, 5, 5), layout.rooms[1].rect);
+        assert_eq!(overflow, layout.overflow());
+    }
+
+    #[test]
+    fn compaction_keeps_repelled_rooms_apart() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(20, 0, 5, 5), 1));
+        let mut from = Vector::new();
+        from.push(0);
+        let mut to = Vector::new();
+        to.push(1);
+        let mut targets = Vector::new();
+        targets.push(Target::new_repulsion(from, to, 1.0, 12.0));
+        let mut layout = Layout::new(rooms, targets);
+        layout.compact();
+        assert_eq!(Rect::new(12, 0, 5, 5), layout.rooms[1].rect);
+        assert_eq!(0, layout.violated_separations().len());
+    }
+
+    #[test]
+    fn compaction_keeps_the_stair_shaft_free() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new_on_level(Rect::new(0, 0, 5, 5), 0, 0));
+        rooms.push(Room::new_on_level(Rect::new(8, 0, 5, 5), 1, 1));
+        let mut layout = Layout::new(rooms, Vector::new());
+        layout.compact();
+        // Room 1 would otherwise slide right under room 0
+        assert_eq!(Rect::new(3, 0, 5, 5), layout.rooms[1].rect);
+    }
+
+    #[test]
+    fn compact_recovers_area_without_overlaps() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(20, 0, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(0, 15, 5, 5), 0));
+        rooms.push(Room::new(Rect::new(12, 14, 3, 3), 0));
+        let mut layout = Layout::new(rooms, Vector::new());
+        let area_before = layout.calculate_bounding_box().area();
+        let recovered = layout.compact();
+        assert!(recovered > 0);
+        assert_eq!(area_before - recovered,
+                   layout.calculate_bounding_box().area());
+        assert_eq!(Rect::new(0, 6, 5, 5), layout.rooms[2].rect);
+        assert_eq!(Rect::new(6, 1, 3, 3), layout.rooms[3].rect);
+        assert_eq!(Rect::new(10, 0, 5, 5), layout.rooms[1].rect);
+        for i in 0..layout.rooms.len() {
+            for j in i + 1..layout.rooms.len() {
+                assert!(!with_gap(layout.rooms[i].rect)
+                    .collides_with(with_gap(layout.rooms[j].rect)));
+            }
+        }
+    }
+
+    #[test]
+    fn compact_leaves_pinned_rooms_in_place() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 5, 5), 0));
+        let mut pinned = Room::new(Rect::new(20, 0, 5, 5), 0);
+        pinned.set_pinned(true);
+        rooms.push(pinned);
+        rooms.push(Room::new(Rect::new(30, 0, 5, 5), 0));
+        let mut layout = Layout::new(rooms, Vector::new());
+        layout.compact();
+        assert_eq!(Rect::new(20, 0, 5, 5), layout.rooms[1].rect);
+        assert_eq!(Rect::new(26, 0, 5, 5), layout.rooms[2].rect);
+    }
+
+    #[test]
+    fn rooms_are_drawn_with_their_glyphs() {
+        let mut rooms = Vector::new();
+        rooms.push(Room::new(Rect::new(0, 0, 3, 3), 0));
+        rooms.push(Room::new(Rect::new(4, 0, 3, 3), 1));
+        rooms.push(Room::new(Rect::new(8, 0, 3, 3), 1));
+        let mut layout = Layout::new(rooms, Vector::new());
+        let mut types = Vector::new();
+        types.push(RoomType::new(1, String::from("Bedroom"), 'b'));
+        layout.set_room_types(types);
+        let matrix = layout.as_char_matrix();
+        assert_eq!(Some(WALL_CHAR), *matrix.get(0, 0));
+        assert_eq!(Some('0'), *matrix.get(1, 1));
+        assert_eq!(Some('b'), *matrix.get(5, 1));
+        assert_eq!(Some('b'), *matrix.get(9, 1));
+        let legend = layout.legend();
+        assert_eq!(3, legend.len());
+        assert_eq!("0 Room 0 (key 0, 1 room)", legend[1]);
+        assert_eq!("b Bedroom (key 1, 2 rooms)", legend[2]);
+    }
+
+    #[test]
+    fn masked_rooms_are_drawn_by_their_masks() {
+        let mask = Mask::parse(&lines(&["###..", "###..", "#####", "#####"]));
+        let mut room = Room::new(Rect::new(0, 0, 5, 4), 0);
+        room.set_shape(Shape::Masked(mask.unwrap()));
+        room.rot_in_place();
+        let mut rooms = Vector::new();
+        rooms.push(room);
+        let layout = Layout::new(rooms, Vector::new());
+        let matrix = layout.as_char_matrix();
+        // The turned L has its empty corner at the bottom right
+        assert_eq!(Some('0'), *matrix.get(1, 1));
+        assert_eq!(Some(WALL_CHAR), *matrix.get(1, 3));
+        assert_eq!(None, *matrix.get(2, 4));
+        assert_eq!(Some(WALL_CHAR), *matrix.get(3, 2));
+    }
+}
@@ -1,8 +1,21 @@
 //! Module for all kinds of geometry
 use std::cmp::Ordering;
+use std::f32;
+
+/// Gives the straight line distance between the closest pair of points, one
+/// from each list, or infinity if either list is empty
+pub fn nearest_dist(from: &[Point], to: &[Point]) -> f32 {
+    let mut shortest = f32::INFINITY;
+    for a in from {
+        for b in to {
+            shortest = shortest.min(a.dist(*b));
+        }
+    }
+    shortest
+}
 
 /// Direction enums
-#[derive(Copy, Clone)]
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
 pub enum Direction {
     Left,
     Right,
@@ -19,7 +32,7 @@ pub struct Point {
 
 /// A simple rectangle struct
 /// (x, y) is the top left corner, w is width, h is height
-#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug)]
+#[derive(PartialEq, Eq, PartialOrd, Ord, Copy, Clone, Debug, Hash)]
 pub struct Rect {
     pub x: isize,
     pub y: isize,
@@ -27,6 +40,14 @@ pub struct Rect {
     pub h: isize,
 }
 
+/// How a room is placed compared to its blueprint: first mirrored from left to
+/// right if mirrored is true, then turned clockwise quarter_turns times
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Hash)]
+pub struct Orientation {
+    pub quarter_turns: usize,
+    pub mirrored: bool,
+}
+
 impl Point {
 	/// Creates a new point with given coordinates
 	pub fn new(x: isize, y: isize) -> Self {
@@ -67,6 +88,25 @@ impl Rect {
         self.x < rect.x + rect.w && rect.x < self.x + self.w && self.y < rect.y + rect.h &&
         rect.y < self.y + self.h
     }
+    /// Checks if the other rectangle is completely inside this one
+    pub fn encloses(&self, rect: Rect) -> bool {
+        rect.x >= self.x && rect.y >= self.y &&
+        rect.x + rect.w <= self.x + self.w && rect.y + rect.h <= self.y + self.h
+    }
+    /// Gives the length of the edge this rect shares with another rect that
+    /// is right next to it without overlapping, or 0 if they don't touch.
+    /// Rects that only meet at a corner don't touch.
+    pub fn contact_length(&self, rect: Rect) -> isize {
+        let overlap_x = (self.x + self.w).min(rect.x + rect.w) - self.x.max(rect.x);
+        let overlap_y = (self.y + self.h).min(rect.y + rect.h) - self.y.max(rect.y);
+        if self.x + self.w == rect.x || rect.x + rect.w == self.x {
+            overlap_y.max(0)
+        } else if self.y + self.h == rect.y || rect.y + rect.h == self.y {
+            overlap_x.max(0)
+        } else {
+            0
+        }
+    }
     /// Returns this rect's area (w*h)
     pub fn area(&self) -> isize {
         self.w * self.h
@@ -113,6 +153,33 @@ impl Rect {
     }
 }
 
+impl Orientation {
+    /// The orientation of a room as it's given in its blueprint
+    pub fn new() -> Self {
+        Orientation { quarter_turns: 0, mirrored: false }
+    }
+    /// Gives the orientation after the room is turned clockwise
+    pub fn rotate(&self) -> Orientation {
+        Orientation {
+            quarter_turns: (self.quarter_turns + 1) % 4,
+            mirrored: self.mirrored,
+        }
+    }
+    /// Gives the orientation after the room is mirrored from left to right.
+    /// Mirroring a turned room is the same as mirroring it first and then
+    /// turning it the other way.
+    pub fn mirror(&self) -> Orientation {
+        Orientation {
+            quarter_turns: (4 - self.quarter_turns) % 4,
+            mirrored: !self.mirrored,
+        }
+    }
+    /// Gives the clockwise rotation in degrees: 0, 90, 180 or 270
+    pub fn degrees(&self) -> usize {
+        self.quarter_turns * 90
+    }
+}
+
 /// Implement methods that interact with an enclosed Rect in a struct
 #[macro_export]
 macro_rules! impl_rect_methods {
@@ -126,16 +193,6 @@ macro_rules! impl_rect_methods {
 		    pub fn origo_cmp(&self, other: &$Struct) -> Ordering {
 		    	self.$rect.origo_cmp(&other.$rect)
 		    }
-		    /// Rotates the struct's rect in place (switches it's rectangles
-		    /// width with its height). Only works if mutable.
-		    pub fn rot_in_place(&mut self) {
-		        self.$rect = self.$rect.rotate();
-		    }
-		    /// Checks if this struct collides with another struct. Uses Rect's
-		    /// collides_with for this
-		    pub fn collides_with(&self, other: $Struct) -> bool {
-		        self.$rect.collides_with(other.$rect)
-		    }
 		    /// Gives the area of this structs's rect
 		    pub fn area(&self) -> isize {
 		        self.$rect.area()
@@ -247,6 +304,26 @@ mod tests {
         assert!(!rect1.collides_with(rect2));
     }
 
+    #[test]
+    fn orientations_add_up() {
+        let orientation = Orientation::new().rotate().mirror();
+        assert_eq!(Orientation { quarter_turns: 3, mirrored: true }, orientation);
+        assert_eq!(270, orientation.degrees());
+        assert_eq!(Orientation::new(), orientation.rotate().mirror());
+        assert_eq!(Orientation::new(), orientation.mirror().rotate().rotate()
+                                                  .rotate());
+    }
+
+    #[test]
+    fn rect_contact_length_works() {
+        let rect1 = Rect::new(0, 0, 5, 4);
+        assert_eq!(3, rect1.contact_length(Rect::new(5, 1, 2, 6)));
+        assert_eq!(5, rect1.contact_length(Rect::new(-1, -2, 7, 2)));
+        assert_eq!(0, rect1.contact_length(Rect::new(5, 4, 2, 2)));
+        assert_eq!(0, rect1.contact_length(Rect::new(6, 0, 2, 2)));
+        assert_eq!(0, rect1.contact_length(Rect::new(4, 0, 2, 2)));
+    }
+
     #[test]
     fn rect_area_calculation_works() {
         let rect1 = Rect {
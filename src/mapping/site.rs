@@ -0,0 +1,290 @@
+//! This module contains the site map: the terrain of the embark that the
+//! layout has to fit in.
+
+use std::fmt::{self, Display, Formatter};
+use mapping::shapes::{Point, Rect};
+use mapping::masks::Shape;
+use collections::{Matrix, Vector};
+
+/// Character marking the tile where the center of room 0 goes
+pub const ORIGIN_CHAR: char = '@';
+
+/// The kinds of tiles a site map can have. Everything but open tiles blocks
+/// rooms.
+#[derive(Copy, Clone, PartialEq, Eq, Debug)]
+pub enum Terrain {
+    /// Free to dig or build on
+    Open,
+    /// Rock that can't or shouldn't be dug out
+    Undiggable,
+    /// Rivers, brooks, pools and other water
+    Water,
+    /// Aquifer layers
+    Aquifer,
+    /// Structures that are already there
+    Structure,
+}
+
+impl Terrain {
+    /// Gives the terrain that a character of a site map stands for, or None if
+    /// the character doesn't mean anything
+    pub fn from_char(c: char) -> Option<Terrain> {
+        match c {
+            '.' | ' ' | ORIGIN_CHAR => Some(Terrain::Open),
+            '#' => Some(Terrain::Undiggable),
+            '~' => Some(Terrain::Water),
+            'A' => Some(Terrain::Aquifer),
+            'X' => Some(Terrain::Structure),
+            _ => None,
+        }
+    }
+    /// Checks if rooms can't be placed on this terrain
+    pub fn is_blocked(&self) -> bool {
+        *self != Terrain::Open
+    }
+}
+
+/// Problems that can be found when parsing a site map. Lines and columns
+/// start from 1.
+#[derive(PartialEq, Debug)]
+pub enum SiteError {
+    /// The character doesn't stand for any terrain
+    UnknownTile { line: usize, column: usize, tile: char },
+    /// The origin is marked more than once
+    DuplicateOrigin { line: usize, column: usize },
+}
+
+impl Display for SiteError {
+    /// Human-readable description of the problem
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match *self {
+            SiteError::UnknownTile { line, column, tile } => write!(f,
+                "line {}, column {}: unknown tile '{}'", line, column, tile),
+            SiteError::DuplicateOrigin { line, column } => write!(f,
+                "line {}, column {}: the origin is already marked", line,
+                column),
+        }
+    }
+}
+
+/// Site is the terrain that a layout is placed on. It has a map for each
+/// z-level, and layout coordinates are mapped to it so that the center of room
+/// 0 is at the origin. Tiles outside the maps are open. The site may also have
+/// bounds that rooms should stay inside.
+#[derive(PartialEq)]
+pub struct Site {
+    levels: Vector<Matrix<Terrain>>,
+    origin: Point,
+    bounds: Option<Rect>,
+}
+
+impl Site {
+    /// Creates a site with no blocked tiles
+    pub fn empty() -> Self {
+        Site {
+            levels: Vector::new(),
+            origin: Point::new(0, 0),
+            bounds: None,
+        }
+    }
+    /// Parses a site map. Each line is a row of tiles; an empty line starts
+    /// the map of the next level down. The origin is marked with ORIGIN_CHAR
+    /// and defaults to the center of the map of the top level.
+    pub fn parse(text: &str) -> Result<Site, SiteError> {
+        let mut sections: Vector<Vector<(usize, &str)>> = Vector::new();
+        let mut section = Vector::new();
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().len() == 0 {
+                if section.len() > 0 {
+                    sections.push(section);
+                    section = Vector::new();
+                }
+            } else {
+                section.push((i + 1, line));
+            }
+        }
+        if section.len() > 0 {
+            sections.push(section);
+        }
+        let mut levels = Vector::new_with_size(sections.len());
+        let mut origin = None;
+        for s in 0..sections.len() {
+            let rows = &sections[s];
+            let mut width = 0;
+            for r in 0..rows.len() {
+                width = rows[r].1.chars().count().max(width);
+            }
+            let mut matrix = Matrix::new(width, rows.len());
+            for y in 0..rows.len() {
+                let (line, row) = rows[y];
+                let mut chars = row.chars();
+                for x in 0..width {
+                    let c = chars.next().unwrap_or(' ');
+                    match Terrain::from_char(c) {
+                        Some(terrain) => matrix.set(x, y, terrain),
+                        None => return Err(SiteError::UnknownTile {
+                            line: line, column: x + 1, tile: c }),
+                    }
+                    if c == ORIGIN_CHAR {
+                        if origin.is_some() {
+                            return Err(SiteError::DuplicateOrigin {
+                                line: line, column: x + 1 });
+                        }
+                        origin = Some(Point::new(x as isize, y as isize));
+                    }
+                }
+            }
+            levels.push(matrix);
+        }
+        let origin = match origin {
+            Some(origin) => origin,
+            None if levels.len() > 0 => Point::new(levels[0].w as isize / 2,
+                                                   levels[0].h as isize / 2),
+            None => Point::new(0, 0),
+        };
+        Ok(Site {
+            levels: levels,
+            origin: origin,
+            bounds: None,
+        })
+    }
+    /// Sets the area (in layout coordinates) that rooms should stay inside
+    pub fn set_bounds(&mut self, bounds: Rect) {
+        self.bounds = Some(bounds);
+    }
+    /// Returns the area that rooms should stay inside, if there's one
+    pub fn get_bounds(&self) -> Option<Rect> {
+        self.bounds
+    }
+    /// Checks if given rectangle is inside the bounds. Everything is inside
+    /// when there are no bounds.
+    pub fn is_inside(&self, rect: Rect) -> bool {
+        self.overflow(rect) == 0
+    }
+    /// Gives the number of tiles of given rectangle that are outside the bounds
+    pub fn overflow(&self, rect: Rect) -> isize {
+        let bounds = match self.bounds {
+            Some(bounds) => bounds,
+            None => return 0,
+        };
+        let w = (rect.x + rect.w).min(bounds.x + bounds.w) - rect.x.max(bounds.x);
+        let h = (rect.y + rect.h).min(bounds.y + bounds.h) - rect.y.max(bounds.y);
+        rect.area() - w.max(0) * h.max(0)
+    }
+    /// Gives the area (in layout coordinates) that the map of level z covers,
+    /// or None if there's no map of the level
+    pub fn map_area(&self, z: isize) -> Option<Rect> {
+        if z < 0 || z as usize >= self.levels.len() {
+            return None;
+        }
+        let level = &self.levels[z as usize];
+        Some(Rect::new(-self.origin.x, -self.origin.y, level.w as isize,
+                       level.h as isize))
+    }
+    /// Gives the terrain at layout coordinates (x, y) on level z
+    pub fn terrain_at(&self, x: isize, y: isize, z: isize) -> Terrain {
+        if z < 0 || z as usize >= self.levels.len() {
+            return Terrain::Open;
+        }
+        let level = &self.levels[z as usize];
+        let map_x = x + self.origin.x;
+        let map_y = y + self.origin.y;
+        if map_x < 0 || map_y < 0 || map_x as usize >= level.w ||
+           map_y as usize >= level.h {
+            return Terrain::Open;
+        }
+        match *level.get(map_x as usize, map_y as usize) {
+            Some(terrain) => terrain,
+            None => Terrain::Open,
+        }
+    }
+    /// Checks if the tile at layout coordinates (x, y) on level z is blocked
+    pub fn is_blocked(&self, x: isize, y: isize, z: isize) -> bool {
+        self.terrain_at(x, y, z).is_blocked()
+    }
+    /// Finds a blocked tile inside given rectangle on level z, if there's any
+    pub fn blocked_tile_in(&self, rect: Rect, z: isize) -> Option<Point> {
+        if z < 0 || z as usize >= self.levels.len() {
+            return None;
+        }
+        let level = &self.levels[z as usize];
+        // Only look at the part of the rectangle that is on the map
+        let min_x = (rect.x + self.origin.x).max(0);
+        let min_y = (rect.y + self.origin.y).max(0);
+        let max_x = (rect.x + rect.w + self.origin.x).min(level.w as isize);
+        let max_y = (rect.y + rect.h + self.origin.y).min(level.h as isize);
+        for y in min_y..max_y {
+            for x in min_x..max_x {
+                if let Some(terrain) = *level.get(x as usize, y as usize) {
+                    if terrain.is_blocked() {
+                        return Some(Point::new(x - self.origin.x,
+                                               y - self.origin.y));
+                    }
+                }
+            }
+        }
+        None
+    }
+    /// Finds a blocked tile under a room of given shape and bounding
+    /// rectangle on level z, if there's any
+    pub fn blocked_tile_under(&self, room: Rect, shape: &Shape, z: isize)
+        -> Option<Point> {
+        let tile = self.blocked_tile_in(room, z);
+        if tile.is_none() || shape.is_rectangle() {
+            return tile;
+        }
+        let tiles = shape.tiles(room);
+        for i in 0..tiles.len() {
+            if self.is_blocked(tiles[i].x, tiles[i].y, z) {
+                return Some(tiles[i]);
+            }
+        }
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use mapping::shapes::{Point, Rect};
+
+    #[test]
+    fn parse_maps_tiles_around_origin() {
+        let site = Site::parse("..~~\r\n.@.X\n##\n\nAAAA\n").unwrap();
+        assert_eq!(Terrain::Open, site.terrain_at(0, 0, 0));
+        assert_eq!(Terrain::Water, site.terrain_at(1, -1, 0));
+        assert_eq!(Terrain::Structure, site.terrain_at(2, 0, 0));
+        assert_eq!(Terrain::Undiggable, site.terrain_at(-1, 1, 0));
+        // Short lines are padded with open tiles and outside the map is open
+        assert_eq!(Terrain::Open, site.terrain_at(1, 1, 0));
+        assert_eq!(Terrain::Open, site.terrain_at(-5, 0, 0));
+        assert!(site.is_blocked(2, -1, 1));
+        assert!(!site.is_blocked(2, -1, 2));
+        assert_eq!(Some(Point::new(2, 0)),
+                   site.blocked_tile_in(Rect::new(0, 0, 3, 1), 0));
+        assert_eq!(None, site.blocked_tile_in(Rect::new(-1, 0, 2, 1), 0));
+        assert_eq!(Some(Rect::new(-1, -1, 4, 3)), site.map_area(0));
+        assert_eq!(None, site.map_area(2));
+    }
+
+    #[test]
+    fn overflow_counts_tiles_outside_bounds() {
+        let mut site = Site::empty();
+        assert!(site.is_inside(Rect::new(-100, -100, 5, 5)));
+        site.set_bounds(Rect::new(-5, -5, 10, 10));
+        assert!(site.is_inside(Rect::new(-5, -5, 10, 10)));
+        assert_eq!(6, site.overflow(Rect::new(3, 0, 4, 3)));
+        assert_eq!(9, site.overflow(Rect::new(20, 20, 3, 3)));
+    }
+
+    #[test]
+    fn parse_finds_invalid_tiles() {
+        assert_eq!(Some(SiteError::UnknownTile { line: 2, column: 3, tile: '?' }),
+                   Site::parse("...\n..?\n").err());
+        assert_eq!(Some(SiteError::DuplicateOrigin { line: 1, column: 4 }),
+                   Site::parse(".@.@\n").err());
+        let site = Site::parse("...\n.#.\n...\n").unwrap();
+        assert!(site.is_blocked(0, 0, 0));
+    }
+}
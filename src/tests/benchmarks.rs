@@ -1,23 +1,47 @@
 use mapping::shapes::Rect;
-use genetics::genes::Gene;
+use genetics::genes::{Gene, Chromosome};
 use genetics::breeding;
+use genetics::config::GaConfig;
+use genetics::fitness::AreaFitness;
+use genetics::pool::BreedingPool;
+use mapping::site::Site;
+use std::sync::Arc;
 use collections::Vector;
 use rand;
 
 extern crate test;
 use tests::benchmarks::test::Bencher;
 
-#[bench]
-fn breeding_benchmark(b: &mut Bencher) {
+/// Creates a population of 500 chromosomes with 16 genes each
+fn benchmark_population() -> Vector<Chromosome<AreaFitness>> {
 	let mut genes: Vector<Gene> = Vector::new();
 	for i in 1..17 {
 		let rect = Rect{ x: 0, y: 0, w: (i*13)%7 + 4, h: (i*5)%7 + 4};
 		genes.push(Gene::new(rect, i - 1));
 	}
+	let mut rng = breeding::seeded_rng(1);
+	breeding::generate_initial_population(genes, Vector::new(),
+		Arc::new(Site::empty()), Arc::new(AreaFitness), 500, &mut rng)
+}
+
+#[bench]
+fn breeding_benchmark(b: &mut Bencher) {
+	let population = benchmark_population();
+	let mut rng = rand::thread_rng();
+	let config = GaConfig::new();
+	b.iter(|| {
+		breeding::breed(population.clone(), &config, &mut rng); //TODO: Find out how to do this without cloning
+	});
+}
+
+#[bench]
+fn parallel_breeding_benchmark(b: &mut Bencher) {
+	let population = benchmark_population();
 	let mut rng = rand::thread_rng();
-	let population = breeding::generate_initial_population(
-		genes, Vector::new(), 500, &mut rng);
+	let mut config = GaConfig::new();
+	config.threads = 4;
+	let pool = BreedingPool::new(config.threads);
 	b.iter(|| {
-		breeding::breed(population.clone(), &mut rng); //TODO: Find out how to do this without cloning
+		breeding::breed_in_pool(population.clone(), &pool, &config, &mut rng);
 	});
-}
\ No newline at end of file
+}